@@ -0,0 +1,160 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parses a `YYYY-MM-DD` date into the number of days since 1970-01-01.
+pub fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+/// Whether a string is a date or the start of one: `2021`, `2021-05`, or `2021-05-01`
+pub fn is_date_prefix(value: &str) -> bool {
+    matches!(value.len(), 4 | 7 | 10)
+        && value.chars().enumerate().all(|(idx, c)| {
+            if idx == 4 || idx == 7 {
+                c == '-'
+            } else {
+                c.is_ascii_digit()
+            }
+        })
+}
+
+/// Parses a command-line date bound, which may be partial as with `is_date_prefix`
+pub fn parse_date_prefix(arg: &str) -> Result<String, String> {
+    if is_date_prefix(arg) {
+        Ok(String::from(arg))
+    } else {
+        Err(format!(
+            "'{}' isn't a date like 2021, 2021-05, or 2021-05-01",
+            arg
+        ))
+    }
+}
+
+// Howard Hinnant's days_from_civil algorithm: http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of `days_from_civil`: (year, month, day) for a number of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Formats a number of days since 1970-01-01 as `YYYY-MM-DD`
+pub fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The day of the week, where Monday is 0 and Sunday is 6
+pub fn weekday(days: i64) -> i64 {
+    // 1970-01-01 was a Thursday
+    (days + 3).rem_euclid(7)
+}
+
+/// Parses a `--modified-since` argument into a time: either an age like `48h`
+/// (in `s`, `m`, `h`, `d`, or `w`) before now, or a `YYYY-MM-DD` date, meaning midnight UTC
+pub fn parse_since(arg: &str) -> Result<SystemTime, String> {
+    if let Some(days) = parse_date(arg) {
+        if days < 0 {
+            return Err(format!("'{}' is before 1970", arg));
+        }
+        return Ok(UNIX_EPOCH + Duration::from_secs(days as u64 * 86400));
+    }
+    let invalid = || {
+        format!(
+            "'{}' is neither a date like 2021-05-01 nor an age like 48h or 7d",
+            arg
+        )
+    };
+    let unit = arg.chars().last().ok_or_else(invalid)?;
+    let seconds_per_unit = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let amount: u64 = arg[..arg.len() - 1].parse().map_err(|_| invalid())?;
+    let age = Duration::from_secs(amount.saturating_mul(seconds_per_unit));
+    Ok(SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH))
+}
+
+const MONTH_ABBREVIATIONS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses a log's `timestamp`, a JavaScript date string like
+/// `Sat May 01 2021 06:27:03 GMT-0400 (Eastern Daylight Time)`, into seconds since 1970 (UTC)
+pub fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let mut parts = timestamp.split_whitespace().skip(1);
+    let month_name = parts.next()?;
+    let month = MONTH_ABBREVIATIONS
+        .iter()
+        .position(|&name| name == month_name)? as i64
+        + 1;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let year: i64 = parts.next()?.parse().ok()?;
+    let mut time = parts.next()?.splitn(3, ':');
+    let hours: i64 = time.next()?.parse().ok()?;
+    let minutes: i64 = time.next()?.parse().ok()?;
+    let seconds: i64 = time.next()?.parse().ok()?;
+
+    // the offset from UTC, like GMT-0400
+    let offset = parts.next()?.strip_prefix("GMT")?;
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let offset_hours: i64 = offset.get(1..3)?.parse().ok()?;
+    let offset_minutes: i64 = offset.get(3..5)?.parse().ok()?;
+    let offset_seconds = sign * (offset_hours * 3600 + offset_minutes * 60);
+
+    let local = days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds;
+    Some(local - offset_seconds)
+}
+
+/// Formats seconds since 1970 as an ISO 8601 time in UTC, like `2021-05-01T10:27:03Z`
+pub fn format_timestamp(seconds: i64) -> String {
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_date(seconds.div_euclid(86400)),
+        seconds.div_euclid(3600).rem_euclid(24),
+        seconds.div_euclid(60).rem_euclid(60),
+        seconds.rem_euclid(60)
+    )
+}
+
+/// Formats seconds since 1970 as `YYYY-MM-DD HH:MM` in UTC
+pub fn format_time(seconds: i64) -> String {
+    let minutes = seconds.div_euclid(60);
+    format!(
+        "{} {:02}:{:02}",
+        format_date(minutes.div_euclid(24 * 60)),
+        minutes.div_euclid(60).rem_euclid(24),
+        minutes.rem_euclid(60)
+    )
+}
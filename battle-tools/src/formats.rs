@@ -0,0 +1,97 @@
+/// Battle room ids and format names, whose conventions have changed over Showdown's history
+use crate::str_to_id;
+
+/// The generation of formats named without one. Showdown only began prefixing every format
+/// with its generation when Generation 7 came out; until then, unprefixed names like `ou`
+/// were the current generation's formats, which is Generation 6 for nearly all archived logs.
+const UNPREFIXED_GENERATION: u32 = 6;
+
+/// A format, like Generation 8 OU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    pub generation: u32,
+    /// The rest of the format id, like `ou` or `randombattle`
+    pub tier: String,
+}
+
+impl Format {
+    /// Parses a format id or name, like `gen8ou`, `[Gen 8] OU`, or, from older logs, `ou`
+    pub fn parse(name: &str) -> Option<Self> {
+        let id = str_to_id(name);
+        if id.is_empty() {
+            return None;
+        }
+        if let Some(rest) = id.strip_prefix("gen") {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            if digits > 0 && digits < rest.len() {
+                return Some(Self {
+                    generation: rest[..digits].parse().ok()?,
+                    tier: String::from(&rest[digits..]),
+                });
+            }
+        }
+        Some(Self {
+            generation: UNPREFIXED_GENERATION,
+            tier: id,
+        })
+    }
+
+    /// The format's current id, like `gen8ou`
+    pub fn id(&self) -> String {
+        format!("gen{}{}", self.generation, self.tier)
+    }
+}
+
+/// Normalizes a format id or name to the current id convention, so that `ou`, `gen6ou`,
+/// and `[Gen 6] OU` are all `gen6ou`
+pub fn normalize_format(name: &str) -> Option<String> {
+    Format::parse(name).map(|format| format.id())
+}
+
+/// Whether a format id is for a format with randomized teams, like Random Battles and
+/// Battle Factory
+pub fn is_randomized(format_id: &str) -> bool {
+    format_id.contains("random") || format_id.contains("factory")
+}
+
+/// A battle room's id, like `battle-gen8ou-12345`. Private rooms' ids end in a password suffix,
+/// like `battle-gen8ou-12345-abcdefghij`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomId {
+    pub format: Format,
+    pub number: u64,
+    pub suffix: Option<String>,
+}
+
+impl RoomId {
+    /// Parses a room id, with or without the `battle-` prefix, or the filename of its log
+    pub fn parse(room: &str) -> Option<Self> {
+        let mut parts = strip_room(room).splitn(3, '-');
+        let format = Format::parse(parts.next()?)?;
+        let number = parts.next()?.parse().ok()?;
+        Some(Self {
+            format,
+            number,
+            suffix: parts.next().map(String::from),
+        })
+    }
+}
+
+/// The room id a log's filename is for, like `gen8ou-1234` for `gen8ou-1234.log.json.gz`
+pub fn room_from_filename(filename: &str) -> &str {
+    filename
+        .trim_end_matches(".gz")
+        .trim_end_matches(".log.json")
+}
+
+/// A room id or log filename without the `battle-` prefix or log extensions
+pub fn strip_room(room: &str) -> &str {
+    let room = room_from_filename(room);
+    room.strip_prefix("battle-").unwrap_or(room)
+}
+
+/// The number of a room or its log's filename, like 1234 in `gen8ou-1234.log.json`.
+/// This doesn't parse the format, since it's called for every file when filtering on room numbers.
+pub fn room_number(room: &str) -> Option<u64> {
+    strip_room(room).split('-').nth(1)?.parse().ok()
+}
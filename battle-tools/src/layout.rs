@@ -0,0 +1,275 @@
+/// How battle log archives are laid out, which determines where a log's date comes from
+use std::{
+    cmp::Ordering,
+    ffi::{OsStr, OsString},
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+const LAYOUT_SUFFIX: &str = ":layout=";
+const LABEL_SUFFIX: &str = ":source-label=";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Logs are in `YYYY-MM-DD` directories, as on the Showdown server: the date is the name of
+    /// the directory containing the log.
+    Daily,
+    /// Logs are grouped by month (e.g. `2021-05/gen8ou/*.log.json`): the date is the name of
+    /// the nearest enclosing `YYYY-MM` directory.
+    Monthly,
+    /// Logs aren't organized by date at all: the date is the log file's modification date.
+    Flat,
+}
+
+impl Layout {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "daily" => Some(Layout::Daily),
+            "monthly" => Some(Layout::Monthly),
+            "flat" => Some(Layout::Flat),
+            _ => None,
+        }
+    }
+
+    /// The date for logs directly inside `directory`, given the date of its parent directory
+    pub fn directory_date(&self, directory: &Path, parent_date: Option<&str>) -> Option<String> {
+        let name = directory.file_name()?.to_str()?;
+        match self {
+            Layout::Daily => Some(String::from(name)),
+            Layout::Monthly if is_month(name) => Some(String::from(name)),
+            Layout::Monthly => parent_date.map(String::from),
+            Layout::Flat => None,
+        }
+    }
+}
+
+/// Whether a directory name looks like `YYYY-MM`
+fn is_month(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() == 7
+        && bytes[4] == b'-'
+        && bytes[..4].iter().all(u8::is_ascii_digit)
+        && bytes[5..].iter().all(u8::is_ascii_digit)
+}
+
+/// The modification date of a file as `YYYY-MM-DD` (UTC), for logs in flat archives
+pub fn modification_date(metadata: &Metadata) -> Option<String> {
+    let seconds = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some(crate::date::format_date((seconds / 86400) as i64))
+}
+
+/// A directory to search, along with how it's laid out
+#[derive(Debug, Clone)]
+pub struct ArchiveRoot {
+    pub path: PathBuf,
+    pub layout: Layout,
+    /// Which archive the directory is, like `main` or `sim`, when archives from several servers
+    /// are searched together; the same room can be a different battle on each server
+    pub label: Option<String>,
+}
+
+impl ArchiveRoot {
+    /// Parses a directory argument, which may end in a layout hint like `:layout=monthly`
+    /// and a source label like `:source-label=main`, in either order
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let mut root = Self {
+            path: PathBuf::from(arg),
+            layout: Layout::Daily,
+            label: None,
+        };
+        let (mut has_layout, mut rest) = (false, arg);
+        // hints are taken off the end one at a time
+        loop {
+            let layout = rest.rfind(LAYOUT_SUFFIX);
+            let label = rest.rfind(LABEL_SUFFIX);
+            match (layout, label) {
+                (Some(idx), label) if label.is_none_or(|label| label < idx) && !has_layout => {
+                    let name = &rest[idx + LAYOUT_SUFFIX.len()..];
+                    root.layout = Layout::from_name(name).ok_or_else(|| {
+                        format!(
+                            "unknown layout '{}' (expected daily, monthly, or flat)",
+                            name
+                        )
+                    })?;
+                    has_layout = true;
+                    rest = &rest[..idx];
+                }
+                (_, Some(idx)) if root.label.is_none() => {
+                    let label = &rest[idx + LABEL_SUFFIX.len()..];
+                    if label.is_empty() {
+                        return Err(String::from("source labels can't be empty"));
+                    }
+                    root.label = Some(String::from(label));
+                    rest = &rest[..idx];
+                }
+                _ => break,
+            }
+        }
+        root.path = PathBuf::from(rest);
+        Ok(root)
+    }
+
+    /// Parses a command-line directory argument; paths that aren't valid UTF-8 can't have a
+    /// layout hint and are used as-is.
+    pub fn from_os_str(arg: &OsStr) -> Result<Self, OsString> {
+        match arg.to_str() {
+            Some(s) => Self::parse(s).map_err(OsString::from),
+            None => Ok(Self {
+                path: PathBuf::from(arg),
+                layout: Layout::Daily,
+                label: None,
+            }),
+        }
+    }
+}
+
+/// Canonicalizes `roots` and drops any that repeat or are inside another root, with a warning,
+/// so no log is searched twice. Roots that can't be canonicalized are kept, to be reported as
+/// unreadable during traversal.
+pub fn dedupe_roots(roots: Vec<ArchiveRoot>) -> Vec<ArchiveRoot> {
+    // On Windows, canonical paths are verbatim (`\\?\C:\...`), which also lifts the
+    // 260-character limit on the paths of everything inside them.
+    let roots: Vec<ArchiveRoot> = roots
+        .into_iter()
+        .map(|root| ArchiveRoot {
+            path: root.path.canonicalize().unwrap_or(root.path),
+            layout: root.layout,
+            label: root.label,
+        })
+        .collect();
+
+    let mut kept = vec![];
+    for (idx, root) in roots.iter().enumerate() {
+        let containing = roots.iter().enumerate().find(|(other_idx, other)| {
+            *other_idx != idx
+                && root.path.starts_with(&other.path)
+                // of identical roots, keep the first
+                && (root.path != other.path || *other_idx < idx)
+        });
+        match containing {
+            Some((_, other)) if root.path == other.path => {
+                eprintln!(
+                    "Ignoring {}, which was given more than once",
+                    root.path.display()
+                )
+            }
+            Some((_, other)) => eprintln!(
+                "Ignoring {}, which is already searched as part of {}",
+                root.path.display(),
+                other.path.display()
+            ),
+            None => kept.push(root.clone()),
+        }
+    }
+    kept
+}
+
+/// Which dates are wanted, as (possibly partial) dates like `2021-05` that bound them inclusively
+#[derive(Debug, Clone, Default)]
+pub struct DateRange {
+    pub from: Option<String>,
+    pub until: Option<String>,
+}
+
+impl DateRange {
+    /// Whether any date starting with `prefix` (like `2021-05` for a month) is in the range
+    pub fn overlaps(&self, prefix: &str) -> bool {
+        // only as much of each as both have is compared, so 2021-05 is within 2021-05-15..2021
+        let compare = |bound: &str| {
+            let len = prefix.len().min(bound.len());
+            prefix.as_bytes()[..len].cmp(&bound.as_bytes()[..len])
+        };
+        self.from
+            .as_deref()
+            .is_none_or(|from| compare(from) != Ordering::Less)
+            && self
+                .until
+                .as_deref()
+                .is_none_or(|until| compare(until) != Ordering::Greater)
+    }
+}
+
+/// The subdirectories of `directory`, with their names; entries that aren't directories or
+/// whose names aren't UTF-8 are left out
+fn subdirectories(directory: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut subdirectories = vec![];
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Ok(name) = entry.file_name().into_string() {
+            subdirectories.push((name, entry.path()));
+        }
+    }
+    subdirectories.sort();
+    Ok(subdirectories)
+}
+
+/// The directories to search in an archive laid out as Showdown writes it,
+/// `ROOT/YYYY-MM/FORMAT/YYYY-MM-DD/`, for logs in `dates` and formats `include_format` accepts.
+///
+/// Whole months or formats are returned where nothing in them is left out, so there are only
+/// as many roots as needed. Directories that can't be read are returned too, so that searching
+/// them reports why; only `root` itself being unreadable is an error.
+pub fn discover_roots(
+    root: &Path,
+    dates: &DateRange,
+    include_format: impl Fn(&str) -> bool,
+) -> io::Result<Vec<ArchiveRoot>> {
+    let daily = |path: PathBuf| ArchiveRoot {
+        path,
+        layout: Layout::Daily,
+        label: None,
+    };
+    let mut roots = vec![];
+    for (month, month_path) in subdirectories(root)? {
+        if !is_month(&month) || !dates.overlaps(&month) {
+            continue;
+        }
+        let formats = match subdirectories(&month_path) {
+            Ok(formats) => formats,
+            Err(_) => {
+                roots.push(daily(month_path));
+                continue;
+            }
+        };
+
+        let mut month_roots = vec![];
+        let mut whole_month = true;
+        for (format, format_path) in formats {
+            if !include_format(&format) {
+                whole_month = false;
+                continue;
+            }
+            let days = match subdirectories(&format_path) {
+                Ok(days) => days,
+                Err(_) => {
+                    month_roots.push(daily(format_path));
+                    continue;
+                }
+            };
+            let (included, excluded): (Vec<_>, Vec<_>) =
+                days.into_iter().partition(|(day, _)| dates.overlaps(day));
+            if excluded.is_empty() {
+                month_roots.push(daily(format_path));
+            } else {
+                whole_month = false;
+                month_roots.extend(included.into_iter().map(|(_, path)| daily(path)));
+            }
+        }
+        if whole_month {
+            roots.push(daily(month_path));
+        } else {
+            roots.extend(month_roots);
+        }
+    }
+    Ok(roots)
+}
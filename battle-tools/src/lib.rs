@@ -0,0 +1,126 @@
+/// Pokémon Showdown battle log handling shared between battlesearch and anonbattle, so both
+/// tools parse logs identically and fixes land in one place
+pub mod date;
+pub mod formats;
+pub mod layout;
+pub mod protocol;
+
+use std::{fmt, str::FromStr};
+
+/// The bytes of a name's id, as `str_to_id` gives them. Non-ASCII characters are all dropped,
+/// so this works on raw bytes, even ones that aren't valid UTF-8.
+fn id_bytes(name: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    name.iter()
+        .filter(|byte| byte.is_ascii_alphanumeric())
+        .map(u8::to_ascii_lowercase)
+}
+
+/// Converts a username or other name to an id, as Showdown does: lowercase, with only
+/// letters and numbers
+pub fn str_to_id(str: &str) -> String {
+    id_bytes(str.as_bytes()).map(char::from).collect()
+}
+
+/// Whether `name` converts to the id `id`, without allocating the id as `str_to_id` would
+pub fn has_id(name: &[u8], id: &str) -> bool {
+    id_bytes(name).eq(id.bytes())
+}
+
+/// An accented Latin letter's unaccented form, for the legacy id scheme
+fn unaccented(letter: char) -> Option<&'static str> {
+    Some(match letter {
+        'à'..='å' => "a",
+        'æ' => "ae",
+        'ç' => "c",
+        'è'..='ë' => "e",
+        'ì'..='ï' => "i",
+        'ð' => "d",
+        'ñ' => "n",
+        'ò'..='ö' | 'ø' => "o",
+        'ù'..='ü' => "u",
+        'ý' | 'ÿ' => "y",
+        'þ' => "th",
+        'ß' => "ss",
+        _ => return None,
+    })
+}
+
+/// How names are converted to ids. Old servers turned accented letters into unaccented ones
+/// rather than dropping them, so archives from before 2015 need the legacy scheme for a name
+/// like "Pokémon" to have the id it had when the log was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdScheme {
+    /// As `str_to_id` converts names
+    #[default]
+    Modern,
+    /// Like the modern scheme, except that accented Latin letters become unaccented
+    Legacy,
+}
+
+impl IdScheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            IdScheme::Modern => "modern",
+            IdScheme::Legacy => "legacy",
+        }
+    }
+
+    /// Converts a name to an id under this scheme
+    pub fn to_id(self, name: &str) -> String {
+        match self {
+            IdScheme::Modern => str_to_id(name),
+            IdScheme::Legacy => {
+                let mut id = String::with_capacity(name.len());
+                self.write_id(name, &mut id).unwrap();
+                id
+            }
+        }
+    }
+
+    /// Writes a name's id under this scheme to `id`, which may be a string allocated somewhere
+    /// other than the heap
+    pub fn write_id(self, name: &str, id: &mut impl fmt::Write) -> fmt::Result {
+        match self {
+            IdScheme::Modern => {
+                for byte in id_bytes(name.as_bytes()) {
+                    id.write_char(char::from(byte))?;
+                }
+            }
+            IdScheme::Legacy => {
+                for letter in name.chars().flat_map(char::to_lowercase) {
+                    match unaccented(letter) {
+                        Some(plain) => id.write_str(plain)?,
+                        None if letter.is_ascii_alphanumeric() => id.write_char(letter)?,
+                        None => (),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the raw `name` converts to the id `id` under this scheme
+    pub fn has_id(self, name: &[u8], id: &str) -> bool {
+        match self {
+            IdScheme::Modern => has_id(name, id),
+            // names that are all ASCII convert the same either way, without allocating
+            IdScheme::Legacy if name.is_ascii() => has_id(name, id),
+            IdScheme::Legacy => self.to_id(&String::from_utf8_lossy(name)) == id,
+        }
+    }
+}
+
+impl FromStr for IdScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "modern" => Ok(IdScheme::Modern),
+            "legacy" => Ok(IdScheme::Legacy),
+            _ => Err(format!(
+                "unknown id scheme '{}' (expected modern or legacy)",
+                s
+            )),
+        }
+    }
+}
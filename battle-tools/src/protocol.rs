@@ -0,0 +1,250 @@
+/// A parser for the battle protocol lines in a log's `log` array
+///
+/// Lines look like `|move|p1a: Pikachu|Baton Pass|p1a: Pikachu`: a message type followed by
+/// pipe-separated arguments. See PROTOCOL.md and SIM-PROTOCOL.md in the Showdown repositories.
+use serde::Deserialize;
+
+/// A side and Pokémon from an identifier like `p1a: Pikachu`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PokemonIdent<'a> {
+    /// The player slot, like "p1"
+    pub side: &'a str,
+    /// The Pokémon's nickname
+    pub name: &'a str,
+}
+
+impl<'a> PokemonIdent<'a> {
+    fn parse(ident: &'a str) -> Self {
+        match ident.split_once(": ") {
+            // the position letter after the slot (p1a, p1b) only matters in doubles
+            Some((position, name)) => Self {
+                side: position.get(..2).unwrap_or(position),
+                name,
+            },
+            None => Self {
+                side: "",
+                name: ident,
+            },
+        }
+    }
+}
+
+/// A single line of a battle log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogEvent<'a> {
+    /// `|player|p1|NAME|AVATAR`
+    Player { side: &'a str, name: &'a str },
+    /// `|turn|N`
+    Turn(u32),
+    /// `|win|USER`
+    Win(&'a str),
+    /// `|tie`
+    Tie,
+    /// `|c|USER|MESSAGE`, `|chat|USER|MESSAGE`, or `|c:|TIMESTAMP|USER|MESSAGE`;
+    /// `user` includes its rank symbol, if any
+    Chat {
+        user: &'a str,
+        message: &'a str,
+        timestamp: Option<u64>,
+    },
+    /// `|j|USER`, `|J|USER`, or `|join|USER`
+    Join(&'a str),
+    /// `|l|USER`, `|L|USER`, or `|leave|USER`
+    Leave(&'a str),
+    /// `|switch|POKEMON|DETAILS|HP` (or `|drag|...`), where details look like `Pikachu, L50, F`
+    Switch {
+        pokemon: PokemonIdent<'a>,
+        details: &'a str,
+    },
+    /// `|move|POKEMON|MOVE|TARGET`
+    Move {
+        pokemon: PokemonIdent<'a>,
+        name: &'a str,
+        target: Option<PokemonIdent<'a>>,
+    },
+    /// `|teamsize|SIDE|N`, how many Pokémon a player brought
+    TeamSize { side: &'a str, size: u32 },
+    /// `|faint|POKEMON`
+    Faint(PokemonIdent<'a>),
+    /// Any other line, unparsed
+    Other(&'a str),
+}
+
+impl<'a> LogEvent<'a> {
+    pub fn parse(line: &'a str) -> Self {
+        let mut parts = line.splitn(3, '|');
+        // lines start with a pipe, so the first part is empty
+        let (kind, rest) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(""), Some(kind), rest) => (kind, rest.unwrap_or("")),
+            _ => return LogEvent::Other(line),
+        };
+        let mut args = rest.split('|');
+        match kind {
+            "player" => match (args.next(), args.next()) {
+                (Some(side), Some(name)) => LogEvent::Player { side, name },
+                _ => LogEvent::Other(line),
+            },
+            "turn" => match rest.parse() {
+                Ok(turn) => LogEvent::Turn(turn),
+                Err(_) => LogEvent::Other(line),
+            },
+            "win" => LogEvent::Win(rest),
+            "tie" => LogEvent::Tie,
+            "c" | "chat" => match rest.split_once('|') {
+                Some((user, message)) => LogEvent::Chat {
+                    user,
+                    message,
+                    timestamp: None,
+                },
+                None => LogEvent::Other(line),
+            },
+            "c:" => {
+                let mut args = rest.splitn(3, '|');
+                match (args.next(), args.next(), args.next()) {
+                    (Some(timestamp), Some(user), Some(message)) => LogEvent::Chat {
+                        user,
+                        message,
+                        timestamp: timestamp.parse().ok(),
+                    },
+                    _ => LogEvent::Other(line),
+                }
+            }
+            "j" | "J" | "join" => LogEvent::Join(rest),
+            "l" | "L" | "leave" => LogEvent::Leave(rest),
+            "switch" | "drag" => match (args.next(), args.next()) {
+                (Some(pokemon), Some(details)) => LogEvent::Switch {
+                    pokemon: PokemonIdent::parse(pokemon),
+                    details,
+                },
+                _ => LogEvent::Other(line),
+            },
+            "move" => match (args.next(), args.next()) {
+                (Some(pokemon), Some(name)) => LogEvent::Move {
+                    pokemon: PokemonIdent::parse(pokemon),
+                    name,
+                    target: args
+                        .next()
+                        .filter(|target| !target.is_empty())
+                        .map(PokemonIdent::parse),
+                },
+                _ => LogEvent::Other(line),
+            },
+            "teamsize" => match (args.next(), args.next().map(str::parse)) {
+                (Some(side), Some(Ok(size))) => LogEvent::TeamSize { side, size },
+                _ => LogEvent::Other(line),
+            },
+            "faint" => LogEvent::Faint(PokemonIdent::parse(rest)),
+            _ => LogEvent::Other(line),
+        }
+    }
+}
+
+/// The protocol lines of a battle
+#[derive(Deserialize)]
+#[serde(transparent)]
+pub struct BattleLog {
+    lines: Vec<String>,
+}
+
+impl BattleLog {
+    /// Parses the raw JSON of a `log` array; `None` if it isn't an array of strings
+    pub fn from_json(json: &[u8]) -> Option<Self> {
+        serde_json::from_slice(json).ok()
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = LogEvent<'_>> {
+        self.lines.iter().map(|line| LogEvent::parse(line))
+    }
+}
+
+/// The protocol lines of a battle, parsed into a bump arena: lines are borrowed from the JSON
+/// where they can be, and otherwise copied into the arena, so checking a log doesn't allocate
+/// a string for each of its lines
+#[cfg(feature = "arena")]
+pub struct ArenaLog<'a> {
+    lines: bumpalo::collections::Vec<'a, &'a str>,
+}
+
+#[cfg(feature = "arena")]
+impl<'a> ArenaLog<'a> {
+    /// Parses the raw JSON of a `log` array into `arena`, as `BattleLog::from_json` does
+    pub fn from_json(json: &'a [u8], arena: &'a bumpalo::Bump) -> Option<Self> {
+        let mut deserializer = serde_json::Deserializer::from_slice(json);
+        let lines = serde::de::DeserializeSeed::deserialize(arena::Lines(arena), &mut deserializer);
+        match (lines, deserializer.end()) {
+            (Ok(lines), Ok(())) => Some(Self { lines }),
+            _ => None,
+        }
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = LogEvent<'_>> {
+        self.lines.iter().map(|line| LogEvent::parse(line))
+    }
+}
+
+/// Deserializing a `log` array's lines into an arena
+#[cfg(feature = "arena")]
+mod arena {
+    use bumpalo::{collections::Vec, Bump};
+    use serde::de::{DeserializeSeed, Deserializer, SeqAccess, Visitor};
+    use std::fmt;
+
+    pub struct Lines<'a>(pub &'a Bump);
+
+    impl<'a> DeserializeSeed<'a> for Lines<'a> {
+        type Value = Vec<'a, &'a str>;
+
+        fn deserialize<D: Deserializer<'a>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_seq(self)
+        }
+    }
+
+    impl<'a> Visitor<'a> for Lines<'a> {
+        type Value = Vec<'a, &'a str>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an array of strings")
+        }
+
+        fn visit_seq<A: SeqAccess<'a>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut lines = Vec::with_capacity_in(seq.size_hint().unwrap_or(0), self.0);
+            while let Some(line) = seq.next_element_seed(Line(self.0))? {
+                lines.push(line);
+            }
+            Ok(lines)
+        }
+    }
+
+    struct Line<'a>(&'a Bump);
+
+    impl<'a> DeserializeSeed<'a> for Line<'a> {
+        type Value = &'a str;
+
+        fn deserialize<D: Deserializer<'a>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_str(self)
+        }
+    }
+
+    impl<'a> Visitor<'a> for Line<'a> {
+        type Value = &'a str;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a string")
+        }
+
+        // lines with no escapes are as they are in the JSON
+        fn visit_borrowed_str<E>(self, line: &'a str) -> Result<Self::Value, E> {
+            Ok(line)
+        }
+
+        fn visit_str<E>(self, line: &str) -> Result<Self::Value, E> {
+            Ok(self.0.alloc_str(line))
+        }
+    }
+}
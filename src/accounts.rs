@@ -0,0 +1,56 @@
+use crate::search::{str_to_id, BattleSearchError, Match, WithPath};
+/// Account registration dates from an external dump of Showdown's user database, for spotting
+/// battles against freshly-registered accounts
+use battle_tools::date;
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+pub struct AccountsDb {
+    /// Registration dates as `YYYY-MM-DD`, by user id
+    registered: HashMap<String, Arc<str>>,
+}
+
+impl AccountsDb {
+    /// Loads a CSV dump whose header row names a `userid` column and a `registertime` column
+    /// (in seconds since 1970, as Showdown stores it). Rows missing either are ignored.
+    pub fn load(path: &Path) -> Result<Self, BattleSearchError> {
+        let contents = fs::read_to_string(path).with_path(path)?;
+        let mut lines = contents.lines();
+        let header: Vec<&str> = lines
+            .next()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .collect();
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|&column| column == name)
+                .ok_or_else(|| {
+                    BattleSearchError::Path(format!("no '{}' column in the header", name)).at(path)
+                })
+        };
+        let (userid_column, registertime_column) = (column("userid")?, column("registertime")?);
+
+        let mut registered = HashMap::new();
+        for line in lines {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let (userid, registertime) =
+                match (fields.get(userid_column), fields.get(registertime_column)) {
+                    (Some(userid), Some(registertime)) => (userid, registertime),
+                    _ => continue,
+                };
+            if let Ok(seconds) = registertime.parse::<i64>() {
+                let day = Arc::from(date::format_date(seconds.div_euclid(86400)));
+                registered.insert(str_to_id(userid), day);
+            }
+        }
+        Ok(Self { registered })
+    }
+
+    /// Adds both players' registration dates to a match, where they're known
+    pub fn annotate(&self, mut m: Match) -> Match {
+        m.p1_registered = self.registered.get(&*m.p1).cloned();
+        m.p2_registered = self.registered.get(&*m.p2).cloned();
+        m
+    }
+}
@@ -0,0 +1,57 @@
+use crate::search::Match;
+/// When in the day and week a user plays, for comparing the habits of suspected alts
+use battle_tools::date;
+
+const DAY: i64 = 24 * 60 * 60;
+
+const WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// The longest bar drawn, for the busiest bucket
+const BAR_WIDTH: usize = 40;
+
+fn print_histogram<T: std::fmt::Display>(labels: impl Iterator<Item = T>, counts: &[usize]) {
+    let most = counts.iter().copied().max().unwrap_or(0).max(1);
+    for (label, &count) in labels.zip(counts) {
+        // rounding up, so buckets with any games have a bar
+        let width = (count * BAR_WIDTH).div_ceil(most);
+        let line = format!("    {}  {:>5}  {}", label, count, "#".repeat(width));
+        println!("{}", line.trim_end());
+    }
+}
+
+/// Prints histograms of the hours (UTC) and days of the week the user's games ended in.
+/// Games whose log has no readable timestamp are left out.
+pub fn print_activity_profile<'a>(matches: impl Iterator<Item = &'a Match>) {
+    let mut by_hour = [0; 24];
+    let mut by_weekday = [0; 7];
+    let mut untimed = 0;
+    for m in matches {
+        let time = match m.ended_at {
+            Some(time) => time,
+            None => {
+                untimed += 1;
+                continue;
+            }
+        };
+        by_hour[(time.rem_euclid(DAY) / (60 * 60)) as usize] += 1;
+        by_weekday[date::weekday(time.div_euclid(DAY)) as usize] += 1;
+    }
+
+    if by_hour.iter().all(|&count| count == 0) {
+        println!("No games with timestamps found.");
+    } else {
+        println!("Games by hour of the day (UTC):");
+        print_histogram((0..24).map(|hour| format!("{:02}:00", hour)), &by_hour);
+        println!();
+        println!("Games by day of the week (UTC):");
+        print_histogram(WEEKDAY_NAMES.iter(), &by_weekday);
+    }
+    if untimed > 0 {
+        println!(
+            "{} game{} had no readable timestamp and {} left out.",
+            untimed,
+            if untimed == 1 { "" } else { "s" },
+            if untimed == 1 { "was" } else { "were" }
+        );
+    }
+}
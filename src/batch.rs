@@ -0,0 +1,92 @@
+use crate::matcher::{self, Matcher};
+use crate::runner::{SearchJob, SearchSummary};
+use crate::search::{str_to_id, BattleSearchError, Match};
+/// Running many searches in one pass over the logs, for bots looking up several users at once
+///
+/// Queries are read from standard input as a JSON array like
+///     [{"id": "q1", "username": "Annika", "wins_only": true}, {"id": "q2", "username": "Mia"}]
+/// and results are written to standard output as a JSON object from query ids to their matches.
+use battle_tools::layout::ArchiveRoot;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{io, slice};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchQuery {
+    id: String,
+    username: String,
+    #[serde(default)]
+    wins_only: bool,
+    #[serde(default)]
+    forfeits_only: bool,
+    #[serde(default)]
+    no_randoms: bool,
+    filter: Option<String>,
+}
+
+impl BatchQuery {
+    /// The battles this query is after, equivalent to a search with its options
+    fn build_matcher(&self) -> Result<Box<dyn Matcher>, BattleSearchError> {
+        let user_id = str_to_id(&self.username);
+        let mut matchers: Vec<Box<dyn Matcher>> = vec![Box::new(matcher::Player {
+            user_id: user_id.clone(),
+        })];
+        if self.wins_only {
+            matchers.push(Box::new(matcher::Wins { user_id }));
+        }
+        if self.forfeits_only {
+            matchers.push(Box::new(matcher::Forfeit));
+        }
+        if self.no_randoms {
+            matchers.push(Box::new(matcher::Not(Box::new(matcher::RandomFormat))));
+        }
+        if let Some(ref filter) = self.filter {
+            matchers.push(matcher::parse_filter(
+                filter,
+                slice::from_ref(&self.username),
+            )?);
+        }
+        Ok(Box::new(matcher::And(matchers)))
+    }
+}
+
+/// Runs the queries on standard input over `directories` in a single search
+pub fn run_batch(
+    directories: Vec<ArchiveRoot>,
+    threads: u32,
+) -> Result<SearchSummary, BattleSearchError> {
+    let queries: Vec<BatchQuery> = serde_json::from_reader(io::stdin().lock())
+        .map_err(|e| BattleSearchError::Query(format!("couldn't read batch queries: {}", e)))?;
+
+    let mut job = SearchJob::new_multi(
+        queries.iter().map(|query| query.username.clone()).collect(),
+        directories,
+    );
+    job.threads = threads;
+    for query in &queries {
+        job.queries.push(query.build_matcher()?);
+    }
+
+    let mut results: Vec<Vec<Match>> = queries.iter().map(|_| vec![]).collect();
+    let summary = job.run(|m| {
+        for &idx in &m.queries {
+            results[idx].push(m.clone());
+        }
+    })?;
+
+    let mut output = Map::new();
+    for (query, matches) in queries.into_iter().zip(results) {
+        let matches = matches
+            .into_iter()
+            .map(|mut m| {
+                // which other queries it matched is irrelevant to this one
+                m.queries.clear();
+                serde_json::to_value(m).unwrap()
+            })
+            .collect();
+        output.insert(query.id, Value::Array(matches));
+    }
+    println!("{}", Value::Object(output));
+    Ok(summary)
+}
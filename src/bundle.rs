@@ -0,0 +1,131 @@
+/// Evidence bundles: copies of matching logs, with a manifest describing the search and
+/// checksums of the logs, so a bundle can be checked later for tampering
+use crate::search::{BattleSearchError, Match, WithPath};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// The SHA-256 hash of some data, in hex
+pub fn checksum(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The SHA-256 hash of a file's contents, in hex
+pub fn checksum_file(path: &Path) -> Result<String, BattleSearchError> {
+    Ok(checksum(&fs::read(path).with_path(path)?))
+}
+
+pub struct Bundle {
+    directory: PathBuf,
+    entries: Vec<Value>,
+}
+
+impl Bundle {
+    pub fn new(directory: &Path) -> Result<Self, BattleSearchError> {
+        fs::create_dir_all(directory).with_path(directory)?;
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            entries: vec![],
+        })
+    }
+
+    /// Copies a match's log into the bundle, under a directory named for its date
+    pub fn add(&mut self, m: &Match) -> Result<(), BattleSearchError> {
+        let filename = match m.path.file_name() {
+            Some(filename) => filename,
+            None => {
+                return Err(BattleSearchError::Path(format!(
+                    "couldn't get the filename of {}",
+                    m.path.display()
+                )))
+            }
+        };
+        let relative = Path::new(&*m.date).join(filename);
+        let destination = self.directory.join(&relative);
+        fs::create_dir_all(self.directory.join(&*m.date)).with_path(&destination)?;
+        let contents = fs::read(&m.path).with_path(&m.path)?;
+        fs::write(&destination, &contents).with_path(&destination)?;
+        self.entries.push(json!({
+            "file": relative,
+            "sha256": checksum(&contents),
+            "match": m,
+        }));
+        Ok(())
+    }
+
+    /// Writes the manifest, listing the search's parameters and every bundled match
+    pub fn finish(self, parameters: &Value) -> Result<(), BattleSearchError> {
+        let path = self.directory.join(MANIFEST_NAME);
+        let manifest = json!({"parameters": parameters, "matches": self.entries});
+        fs::write(&path, serde_json::to_string_pretty(&manifest).unwrap()).with_path(&path)?;
+        Ok(())
+    }
+}
+
+/// Checks that every log in a bundle is still there and unmodified, going by the checksums in
+/// its manifest, and prints those that aren't
+pub fn verify_bundle(directory: &Path) -> Result<(), BattleSearchError> {
+    let path = directory.join(MANIFEST_NAME);
+    let contents = fs::read(&path).with_path(&path)?;
+    let manifest: Value = serde_json::from_slice(&contents)
+        .map_err(|e| BattleSearchError::FaultyJSON(e.to_string()).at(&path))?;
+    let entries = match manifest["matches"].as_array() {
+        Some(entries) => entries,
+        None => {
+            return Err(BattleSearchError::FaultyJSON(String::from(
+                "the manifest doesn't list any matches",
+            ))
+            .at(&path))
+        }
+    };
+
+    let mut failed = 0;
+    for entry in entries {
+        let (file, expected) = match (entry["file"].as_str(), entry["sha256"].as_str()) {
+            (Some(file), Some(expected)) => (file, expected),
+            (Some(file), None) => {
+                failed += 1;
+                println!("no checksum  {}", file);
+                continue;
+            }
+            _ => {
+                return Err(BattleSearchError::FaultyJSON(String::from(
+                    "a match in the manifest has no file",
+                ))
+                .at(&path))
+            }
+        };
+        match checksum_file(&directory.join(file)) {
+            Ok(actual) if actual == expected => (),
+            Ok(_) => {
+                failed += 1;
+                println!("modified     {}", file);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("unreadable   {} ({})", file, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(BattleSearchError::Path(format!(
+            "{} of {} logs in the bundle don't match its manifest",
+            failed,
+            entries.len()
+        )));
+    }
+    println!(
+        "All {} logs in the bundle match its manifest.",
+        entries.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::search::{BattleSearchError, Match, WithPath};
+use crate::source;
+/// Caching of search results, so identical searches over unchanged archives return instantly
+use battle_tools::layout::ArchiveRoot;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: String,
+    matches: Vec<Match>,
+}
+
+pub struct ResultCache {
+    path: PathBuf,
+    /// The search parameters plus a fingerprint of the archives' directory mtimes
+    key: String,
+}
+
+/// Hashes a file or directory's path, modification time, and length
+fn fingerprint_file(path: &Path, hasher: &mut DefaultHasher) {
+    path.hash(hasher);
+    if let Ok(metadata) = fs::metadata(path) {
+        if let Ok(modified) = metadata.modified() {
+            if let Ok(since_epoch) = modified.duration_since(UNIX_EPOCH) {
+                since_epoch.as_nanos().hash(hasher);
+            }
+        }
+        if metadata.is_file() {
+            metadata.len().hash(hasher);
+        }
+    }
+}
+
+/// Hashes the modification time of every directory under `path`, and of every archive.
+/// Adding or removing a log changes its directory's mtime, so this changes when logs arrive
+/// without having to stat every log; an archive can be overwritten in place without changing
+/// its directory's, so its own mtime and length are hashed too.
+fn fingerprint_directory(path: &Path, hasher: &mut DefaultHasher) {
+    fingerprint_file(path, hasher);
+    if let Ok(contents) = path.read_dir() {
+        let mut subdirectories = vec![];
+        let mut archives = vec![];
+        for entry in contents.flatten() {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                subdirectories.push(path);
+            } else if source::is_archive(&path) {
+                archives.push(path);
+            }
+        }
+        archives.sort();
+        for archive in archives {
+            fingerprint_file(&archive, hasher);
+        }
+        subdirectories.sort();
+        for subdirectory in subdirectories {
+            fingerprint_directory(&subdirectory, hasher);
+        }
+    }
+}
+
+impl ResultCache {
+    /// `parameters` describes everything about the search other than the archives' contents.
+    pub fn new(
+        cache_dir: &Path,
+        parameters: &str,
+        roots: &[ArchiveRoot],
+    ) -> Result<Self, BattleSearchError> {
+        fs::create_dir_all(cache_dir).with_path(cache_dir)?;
+        let mut hasher = DefaultHasher::new();
+        for root in roots {
+            fingerprint_directory(&root.path, &mut hasher);
+        }
+        let key = format!("{} {:016x}", parameters, hasher.finish());
+
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        Ok(Self {
+            path: cache_dir.join(format!("{:016x}.json", hasher.finish())),
+            key,
+        })
+    }
+
+    /// The cached results, if this exact search has been run since the archives last changed
+    pub fn load(&self) -> Option<Vec<Match>> {
+        let entry: CacheEntry = serde_json::from_slice(&fs::read(&self.path).ok()?).ok()?;
+        // the filename is only a hash, so make sure it's not a collision
+        if entry.key == self.key {
+            Some(entry.matches)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, matches: Vec<Match>) -> Result<(), BattleSearchError> {
+        let entry = CacheEntry {
+            key: self.key.clone(),
+            matches,
+        };
+        fs::write(&self.path, serde_json::to_vec(&entry).unwrap()).with_path(&self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use crate::search::{BattleSearchError, Match, WithPath};
+/// Checkpointing, so that interrupted searches can be resumed without redoing completed work
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// How many processed files are recorded between flushes of the checkpoint file
+const FLUSH_INTERVAL: usize = 1000;
+
+/// Records processed files, one JSON object per line with the file's path and the match found
+/// in it, if any, appending to the checkpoint file. Appending means the same file can be passed
+/// to both `--checkpoint` and `--resume`.
+pub struct Checkpoint {
+    path: PathBuf,
+    writer: Mutex<CheckpointWriter>,
+}
+
+/// A line of a checkpoint file
+#[derive(Serialize, Deserialize)]
+struct Record {
+    path: PathBuf,
+    #[serde(default, rename = "match", skip_serializing_if = "Option::is_none")]
+    found: Option<Match>,
+}
+
+/// What a previous run recorded: the files it processed, and the matches found in them
+#[derive(Default)]
+pub struct Resumed {
+    pub processed: HashSet<PathBuf>,
+    pub matches: Vec<Match>,
+}
+
+struct CheckpointWriter {
+    file: BufWriter<File>,
+    unflushed: usize,
+}
+
+impl Checkpoint {
+    pub fn new(path: &Path) -> Result<Self, BattleSearchError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_path(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Mutex::new(CheckpointWriter {
+                file: BufWriter::new(file),
+                unflushed: 0,
+            }),
+        })
+    }
+
+    /// Marks a file as fully processed, with the match found in it, if any
+    pub fn record(&self, path: &Path, found: Option<&Match>) -> Result<(), BattleSearchError> {
+        let record = Record {
+            // paths that aren't UTF-8 can't be written as JSON strings
+            path: PathBuf::from(path.to_string_lossy().into_owned()),
+            found: found.cloned(),
+        };
+        let line = serde_json::to_string(&record)
+            .map_err(|e| BattleSearchError::FaultyJSON(e.to_string()))
+            .with_path(&self.path)?;
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer.file, "{}", line).with_path(&self.path)?;
+        writer.unflushed += 1;
+        if writer.unflushed >= FLUSH_INTERVAL {
+            writer.file.flush().with_path(&self.path)?;
+            writer.unflushed = 0;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<(), BattleSearchError> {
+        let mut writer = self.writer.lock().unwrap();
+        writer.file.flush().with_path(&self.path)?;
+        writer.unflushed = 0;
+        Ok(())
+    }
+}
+
+/// Loads the files recorded as processed by a previous run, and the matches found in them.
+///
+/// Paths are compared exactly as they were traversed, so a search should be resumed
+/// with the same directory arguments it was started with.
+pub fn load_processed(path: &Path) -> Result<Resumed, BattleSearchError> {
+    let mut resumed = Resumed::default();
+    for line in BufReader::new(File::open(path).with_path(path)?).lines() {
+        let line = line.with_path(path)?;
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Record>(&line) {
+            Ok(record) => {
+                resumed.processed.insert(record.path);
+                resumed.matches.extend(record.found);
+            }
+            // checkpoints from before matches were recorded are one bare path per line
+            Err(_) if !line.starts_with('{') => {
+                resumed.processed.insert(PathBuf::from(line));
+            }
+            // a power cut can leave a truncated final line, whose file is then checked again
+            Err(_) => (),
+        }
+    }
+    Ok(resumed)
+}
@@ -0,0 +1,276 @@
+use crate::runner::{self, FileLimits};
+use crate::search::{str_to_id, BattleSearchError, PokemonSet, WithPath};
+use crate::source::{self, LogData, LogVisitor, SourceLog};
+/// Comparing how a user played two battles, side by side, to back up reports of two accounts
+/// playing identically (as when one player is ghosting for another)
+use battle_tools::formats;
+use battle_tools::layout::ArchiveRoot;
+use battle_tools::protocol::{BattleLog, LogEvent};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// How wide each battle's column is; longer entries push the marker out rather than being cut
+const COLUMN_WIDTH: usize = 32;
+const LABEL_WIDTH: usize = 8;
+/// Marks rows where both battles went the same way
+const SAME: &str = "<- same";
+
+/// Finds the logs of particular rooms, reading them as it goes
+struct RoomFinder {
+    rooms: Vec<String>,
+    logs: HashMap<String, (PathBuf, Vec<u8>)>,
+}
+
+impl LogVisitor for RoomFinder {
+    fn is_fresh(&self, _modified: &dyn Fn() -> Option<SystemTime>) -> bool {
+        true
+    }
+
+    fn wants(&mut self, path: &Path) -> bool {
+        let filename = match path.file_name() {
+            Some(filename) => filename.to_string_lossy(),
+            None => return false,
+        };
+        let room = formats::strip_room(&filename);
+        self.rooms.iter().any(|wanted| wanted == room) && !self.logs.contains_key(room)
+    }
+
+    fn is_sampling(&self) -> bool {
+        false
+    }
+
+    fn sample_size(&mut self, _directory: &Path, logs: usize) -> usize {
+        logs
+    }
+
+    fn wants_players(&mut self, _players: &str) -> bool {
+        true
+    }
+
+    fn allows_symlink(&mut self, _path: &Path) -> bool {
+        true
+    }
+
+    fn visit(&mut self, log: SourceLog) {
+        let data = match log.data {
+            LogData::File => {
+                let mut buffer = vec![];
+                match runner::read_log(&log.path, &mut buffer, FileLimits::default()) {
+                    Ok(_) => buffer,
+                    Err(e) => {
+                        eprintln!("Skipping {}: {}", log.path.display(), e);
+                        return;
+                    }
+                }
+            }
+            LogData::Read(data) => data,
+        };
+        let filename = log.path.file_name().unwrap_or_default().to_string_lossy();
+        let room = String::from(formats::strip_room(&filename));
+        self.logs.insert(room, (log.path, data));
+    }
+
+    fn count_read(&mut self, _bytes: usize) {}
+
+    fn file_limits(&self) -> FileLimits {
+        FileLimits::default()
+    }
+
+    fn abandon(&mut self, path: &Path, reason: &str) {
+        eprintln!("Abandoned {}: {}", path.display(), reason);
+    }
+
+    fn should_stop(&self) -> bool {
+        self.logs.len() == self.rooms.len()
+    }
+
+    fn skip(&mut self, path: &Path, error: BattleSearchError) -> Result<(), BattleSearchError> {
+        eprintln!("Skipping {}: {}", path.display(), error);
+        Ok(())
+    }
+
+    fn skip_too_long(&mut self, path: &Path) {
+        eprintln!(
+            "Skipping {}, whose path is too long to open",
+            path.display()
+        );
+    }
+}
+
+/// The fields of a log that are compared
+#[derive(Deserialize)]
+struct ComparedLog {
+    p1: String,
+    p2: String,
+    #[serde(default)]
+    p1team: Option<Vec<PokemonSet>>,
+    #[serde(default)]
+    p2team: Option<Vec<PokemonSet>>,
+    log: BattleLog,
+}
+
+/// How the user played a battle
+struct Play {
+    opponent: String,
+    /// Species on the user's team, sorted
+    team: Vec<String>,
+    lead: Option<String>,
+    /// The moves the user's Pokémon used each turn, like "Pikachu: Baton Pass", from turn 1
+    turns: Vec<Vec<String>>,
+}
+
+impl Play {
+    fn from_log(data: &[u8], user_id: &str, path: &Path) -> Result<Self, BattleSearchError> {
+        let log: ComparedLog = serde_json::from_slice(data)
+            .map_err(|e| BattleSearchError::FaultyJSON(e.to_string()))
+            .with_path(path)?;
+        let (side, opponent, team) = if str_to_id(&log.p1) == user_id {
+            ("p1", log.p2, log.p1team)
+        } else if str_to_id(&log.p2) == user_id {
+            ("p2", log.p1, log.p2team)
+        } else {
+            return Err(BattleSearchError::Path(format!(
+                "{} didn't play in {}",
+                user_id,
+                path.display()
+            )));
+        };
+
+        // nicknames to species, since moves are used by nickname
+        let mut species: HashMap<&str, &str> = HashMap::new();
+        let mut seen = vec![];
+        let mut lead = None;
+        let mut turns: Vec<Vec<String>> = vec![];
+        for event in log.log.lines() {
+            match event {
+                LogEvent::Switch { pokemon, details } if pokemon.side == side => {
+                    let name = details.split(',').next().unwrap_or(details);
+                    species.insert(pokemon.name, name);
+                    lead.get_or_insert_with(|| String::from(name));
+                    if !seen.contains(&name) {
+                        seen.push(name);
+                    }
+                }
+                LogEvent::Turn(_) => turns.push(vec![]),
+                LogEvent::Move { pokemon, name, .. } if pokemon.side == side => {
+                    if let Some(turn) = turns.last_mut() {
+                        let user = species.get(pokemon.name).unwrap_or(&pokemon.name);
+                        turn.push(format!("{}: {}", user, name));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // the teams players brought, if the log has them, or else the Pokémon they sent out
+        let mut team: Vec<String> = match team {
+            Some(team) => team.into_iter().map(|set| set.species).collect(),
+            None => seen.into_iter().map(String::from).collect(),
+        };
+        team.sort();
+        Ok(Self {
+            opponent,
+            team,
+            lead,
+            turns,
+        })
+    }
+}
+
+/// Prints a row, marking it if both battles' entries are the same
+fn print_row(label: &str, a: &str, b: &str) -> bool {
+    let same = !a.is_empty() && a == b;
+    let line = format!(
+        "{:<label$} {:<column$} {:<column$} {}",
+        label,
+        a,
+        b,
+        if same { SAME } else { "" },
+        label = LABEL_WIDTH,
+        column = COLUMN_WIDTH
+    );
+    println!("{}", line.trim_end());
+    same
+}
+
+/// Finds the logs of two rooms and prints how the user played each, side by side
+pub fn compare_battles(
+    username: &str,
+    rooms: [&str; 2],
+    directories: &[ArchiveRoot],
+) -> Result<(), BattleSearchError> {
+    let rooms: Vec<String> = rooms
+        .iter()
+        .map(|room| String::from(formats::strip_room(room.rsplit('/').next().unwrap_or(room))))
+        .collect();
+    let mut finder = RoomFinder {
+        rooms: rooms.clone(),
+        logs: HashMap::new(),
+    };
+    for root in directories {
+        if finder.should_stop() {
+            break;
+        }
+        source::open(root).walk(&mut finder)?;
+    }
+
+    let user_id = str_to_id(username);
+    let mut plays = vec![];
+    for room in &rooms {
+        let (path, data) = finder.logs.get(room).ok_or_else(|| {
+            BattleSearchError::Path(format!("couldn't find the log for {}", room))
+        })?;
+        plays.push(Play::from_log(data, &user_id, path)?);
+    }
+    let (a, b) = (&plays[0], &plays[1]);
+
+    println!("How {} played in each battle:", username);
+    print_row("", &rooms[0], &rooms[1]);
+    print_row("Against", &a.opponent, &b.opponent);
+    let team_size = a.team.len().max(b.team.len());
+    let same_team = a.team == b.team;
+    for idx in 0..team_size {
+        let slot = |team: &[String]| team.get(idx).cloned().unwrap_or_default();
+        print_row(
+            if idx == 0 { "Team" } else { "" },
+            &slot(&a.team),
+            &slot(&b.team),
+        );
+    }
+    let same_lead = print_row(
+        "Lead",
+        a.lead.as_deref().unwrap_or(""),
+        b.lead.as_deref().unwrap_or(""),
+    );
+
+    let (mut same_turns, mut both_turns) = (0, 0);
+    for turn in 0..a.turns.len().max(b.turns.len()) {
+        let moves = |play: &Play| {
+            play.turns
+                .get(turn)
+                .map(|moves| moves.join(", "))
+                .unwrap_or_default()
+        };
+        let (moves_a, moves_b) = (moves(a), moves(b));
+        if !moves_a.is_empty() && !moves_b.is_empty() {
+            both_turns += 1;
+        }
+        if print_row(&format!("Turn {}", turn + 1), &moves_a, &moves_b) {
+            same_turns += 1;
+        }
+    }
+
+    println!();
+    println!(
+        "Same team: {}; same lead: {}; same moves on {} of the {} turns both battles had.",
+        if same_team { "yes" } else { "no" },
+        if same_lead { "yes" } else { "no" },
+        same_turns,
+        both_turns
+    );
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::search::{str_to_id, Match};
+/// Comparing who two users have played, since alts of the same player tend to face
+/// near-identical sets of opponents while coincidentally similar players don't
+use std::collections::HashMap;
+
+/// Each opponent a user played, by id, with the name first seen and how many games they played
+#[derive(Default)]
+struct Opponents {
+    opponents: HashMap<String, (String, usize)>,
+}
+
+impl Opponents {
+    fn add(&mut self, id: &str, name: &str) {
+        self.opponents
+            .entry(String::from(id))
+            .or_insert_with(|| (String::from(name), 0))
+            .1 += 1;
+    }
+
+    /// The opponents `other` didn't play, most played first
+    fn only_here(&self, other: &Opponents) -> Vec<&(String, usize)> {
+        let mut only: Vec<(&String, &(String, usize))> = self
+            .opponents
+            .iter()
+            .filter(|(id, _)| !other.opponents.contains_key(*id))
+            .collect();
+        only.sort_by(|(a_id, (_, a_games)), (b_id, (_, b_games))| {
+            b_games.cmp(a_games).then(a_id.cmp(b_id))
+        });
+        only.into_iter().map(|(_, opponent)| opponent).collect()
+    }
+}
+
+fn print_only(username: &str, only: &[&(String, usize)]) {
+    println!("Only {} played ({}):", username, only.len());
+    for (name, games) in only {
+        let plural = if *games == 1 { "" } else { "s" };
+        println!("    {} ({} game{})", name, games, plural);
+    }
+}
+
+/// Prints the opponents each of two users played that the other didn't, and how many they
+/// share. Games between the two users aren't counted, since each is the other's opponent.
+pub fn print_opponent_diff<'a>(
+    username_a: &str,
+    username_b: &str,
+    matches: impl Iterator<Item = &'a Match>,
+) {
+    let (id_a, id_b) = (str_to_id(username_a), str_to_id(username_b));
+    let (mut opponents_a, mut opponents_b) = (Opponents::default(), Opponents::default());
+    for m in matches {
+        for (player, opponent, opponent_name) in
+            [(&m.p1, &m.p2, &m.p2_name), (&m.p2, &m.p1, &m.p1_name)]
+        {
+            let opponents = if **player == *id_a {
+                &mut opponents_a
+            } else if **player == *id_b {
+                &mut opponents_b
+            } else {
+                continue;
+            };
+            if **opponent != *id_a && **opponent != *id_b {
+                opponents.add(opponent, opponent_name);
+            }
+        }
+    }
+
+    let shared = opponents_a
+        .opponents
+        .keys()
+        .filter(|id| opponents_b.opponents.contains_key(*id))
+        .count();
+    let total = opponents_a.opponents.len() + opponents_b.opponents.len() - shared;
+    let similarity = if total == 0 {
+        0.0
+    } else {
+        shared as f64 / total as f64
+    };
+    println!(
+        "{} and {} both played {} of the {} opponents either played ({:.0}% overlap)",
+        username_a,
+        username_b,
+        shared,
+        total,
+        similarity * 100.0
+    );
+    print_only(username_a, &opponents_a.only_here(&opponents_b));
+    print_only(username_b, &opponents_b.only_here(&opponents_a));
+}
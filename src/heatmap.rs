@@ -0,0 +1,87 @@
+use crate::search::{BattleSearchError, Match, WithPath};
+/// Calendar-style heatmaps of how many games were played each day
+use battle_tools::date;
+use std::{collections::BTreeMap, fs, path::Path};
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Games per day, keyed by days since 1970-01-01; matches with unparseable dates are left out.
+pub fn count_by_day<'a>(matches: impl Iterator<Item = &'a Match>) -> BTreeMap<i64, usize> {
+    let mut counts = BTreeMap::new();
+    for m in matches {
+        if let Some(day) = date::parse_date(&m.date) {
+            *counts.entry(day).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// Prints a calendar for each month from the first to the last day with games
+pub fn print_heatmap(counts: &BTreeMap<i64, usize>) {
+    let (first, last) = match (counts.keys().next(), counts.keys().next_back()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => {
+            println!("No games found.");
+            return;
+        }
+    };
+
+    let (mut year, mut month, _) = date::civil_from_days(first);
+    let (last_year, last_month, _) = date::civil_from_days(last);
+    while (year, month) <= (last_year, last_month) {
+        println!("{} {}", MONTH_NAMES[month as usize - 1], year);
+        println!("  Mo  Tu  We  Th  Fr  Sa  Su");
+
+        let first_of_month = date::parse_date(&format!("{}-{}-1", year, month)).unwrap();
+        let mut line = "    ".repeat(date::weekday(first_of_month) as usize);
+        let mut day = first_of_month;
+        while date::civil_from_days(day).1 == month {
+            match counts.get(&day) {
+                Some(count) => line.push_str(&format!("{:>4}", count)),
+                None => line.push_str("   ."),
+            }
+            if date::weekday(day) == 6 {
+                println!("{}", line);
+                line.clear();
+            }
+            day += 1;
+        }
+        if !line.is_empty() {
+            println!("{}", line);
+        }
+        println!();
+
+        if month == 12 {
+            year += 1;
+            month = 1;
+        } else {
+            month += 1;
+        }
+    }
+}
+
+/// Writes games per day as a JSON object mapping `YYYY-MM-DD` dates to counts
+pub fn write_heatmap_json(
+    counts: &BTreeMap<i64, usize>,
+    path: &Path,
+) -> Result<(), BattleSearchError> {
+    let by_date: BTreeMap<String, usize> = counts
+        .iter()
+        .map(|(day, count)| (date::format_date(*day), *count))
+        .collect();
+    fs::write(path, serde_json::to_string_pretty(&by_date).unwrap()).with_path(path)?;
+    Ok(())
+}
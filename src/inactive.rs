@@ -0,0 +1,70 @@
+use crate::search::{str_to_id, BattleSearchError, Match, WithPath};
+/// Checking which of a list of users haven't played lately, as for staff activity checks,
+/// with one search for all of them
+use std::{collections::HashMap, fs, path::Path};
+
+/// Reads a list of usernames, one per line, leaving out blank lines
+pub fn read_users(path: &Path) -> Result<Vec<String>, BattleSearchError> {
+    let contents = fs::read_to_string(path).with_path(path)?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// How many games each of a list of users played
+pub struct Activity {
+    /// Games by user id
+    games: HashMap<String, usize>,
+}
+
+impl Activity {
+    pub fn new(usernames: &[String]) -> Self {
+        Self {
+            games: usernames.iter().map(|name| (str_to_id(name), 0)).collect(),
+        }
+    }
+
+    /// Counts a game for each listed user who played in it
+    pub fn add(&mut self, m: &Match) {
+        let players = [Some(&m.p1), Some(&m.p2), m.p3.as_ref(), m.p4.as_ref()];
+        for player in players.iter().flatten() {
+            if let Some(games) = self.games.get_mut(&***player) {
+                *games += 1;
+            }
+        }
+    }
+
+    /// Prints the users who played no games, in the order they were listed
+    pub fn print_inactive(&self, usernames: &[String], since: &str) {
+        let mut inactive: Vec<&String> = vec![];
+        for name in usernames {
+            let id = str_to_id(name);
+            let listed = inactive.iter().any(|other| str_to_id(other) == id);
+            if self.games.get(&id) == Some(&0) && !listed {
+                inactive.push(name);
+            }
+        }
+
+        if inactive.is_empty() {
+            println!(
+                "All {} users have played since {}.",
+                self.games.len(),
+                since
+            );
+            return;
+        }
+        println!(
+            "{} of {} users {} played no games since {}:",
+            inactive.len(),
+            self.games.len(),
+            if inactive.len() == 1 { "has" } else { "have" },
+            since
+        );
+        for name in inactive {
+            println!("    {}", name);
+        }
+    }
+}
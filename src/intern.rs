@@ -0,0 +1,20 @@
+/// String interning, so aggregation over huge numbers of matches stores each distinct id once
+use std::{collections::HashSet, sync::Arc};
+
+#[derive(Default)]
+pub struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// Returns the canonical copy of `string`, so that the caller's copy can be freed
+    pub fn intern(&mut self, string: &Arc<str>) -> Arc<str> {
+        match self.strings.get(string) {
+            Some(canonical) => canonical.clone(),
+            None => {
+                self.strings.insert(string.clone());
+                string.clone()
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::search::{str_to_id, BattleSearchError, LadderStanding, Match, WithPath};
+/// Players' standings on their formats' ladders, from snapshots downloaded from Showdown, for
+/// telling wins against established players from wins against throwaway low-ladder alts
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+#[derive(Deserialize)]
+struct LadderEntry {
+    userid: String,
+    elo: f64,
+}
+
+/// A ladder as served by `pokemonshowdown.com/ladder/FORMAT.json`
+#[derive(Deserialize)]
+struct Ladder {
+    formatid: String,
+    toplist: Vec<LadderEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SnapshotFile {
+    One(Ladder),
+    Many(Vec<Ladder>),
+}
+
+pub struct LadderSnapshot {
+    /// For each format id, the percentage of its ladder each listed user is rated above
+    percentiles: HashMap<String, HashMap<String, u8>>,
+}
+
+impl LadderSnapshot {
+    /// Loads a ladder, or an array of ladders for different formats, as Showdown serves them.
+    /// Only each entry's `userid` and `elo` are used.
+    pub fn load(path: &Path) -> Result<Self, BattleSearchError> {
+        let contents = fs::read(path).with_path(path)?;
+        let snapshot = serde_json::from_slice(&contents)
+            .map_err(|e| BattleSearchError::FaultyJSON(e.to_string()).at(path))?;
+        let ladders = match snapshot {
+            SnapshotFile::One(ladder) => vec![ladder],
+            SnapshotFile::Many(ladders) => ladders,
+        };
+
+        let mut percentiles = HashMap::new();
+        for ladder in ladders {
+            let mut elos: Vec<f64> = ladder.toplist.iter().map(|entry| entry.elo).collect();
+            elos.sort_by(f64::total_cmp);
+            let players = elos.len();
+            let by_user: HashMap<String, u8> = ladder
+                .toplist
+                .into_iter()
+                .map(|entry| {
+                    let below = elos.partition_point(|&elo| elo < entry.elo);
+                    (str_to_id(&entry.userid), (below * 100 / players) as u8)
+                })
+                .collect();
+            percentiles
+                .entry(str_to_id(&ladder.formatid))
+                .or_insert_with(HashMap::new)
+                .extend(by_user);
+        }
+        Ok(Self { percentiles })
+    }
+
+    /// Adds both players' standings to a match, if the snapshot has its format's ladder
+    pub fn annotate(&self, mut m: Match) -> Match {
+        let ladder = match m.format.as_deref().and_then(|f| self.percentiles.get(f)) {
+            Some(ladder) => ladder,
+            None => return m,
+        };
+        let standing = |id: &str| match ladder.get(id) {
+            Some(&percentile) => LadderStanding::Above(percentile),
+            None => LadderStanding::Unranked,
+        };
+        m.p1_ladder = Some(standing(&m.p1));
+        m.p2_ladder = Some(standing(&m.p2));
+        m
+    }
+}
@@ -0,0 +1,15 @@
+/// Searching directories and archives of Pokémon Showdown battle logs for a user's battles,
+/// as the battlesearch command does, for other programs to build on
+pub mod checkpoint;
+pub mod logcache;
+pub mod matcher;
+pub mod profile;
+pub mod progress;
+pub mod runner;
+pub mod scratch;
+pub mod search;
+pub mod source;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod throttle;
+pub mod validate;
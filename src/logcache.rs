@@ -0,0 +1,114 @@
+use crate::search::ParsedFields;
+/// An in-memory cache of logs' parsed fields, so a long-running server answering many searches
+/// over the same directories doesn't read and parse every log again for each one
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::Metadata,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// Roughly what each entry costs beyond its fields' contents
+const ENTRY_OVERHEAD: usize = 128;
+
+/// A log file as it was when it was parsed; if it's been rewritten since, it won't match.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl CacheKey {
+    pub fn new(path: &Path, metadata: &Metadata) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        }
+    }
+}
+
+struct Entry {
+    fields: Arc<ParsedFields>,
+    size: usize,
+    /// When the entry was last used, as a value of `Lru::clock`
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<CacheKey, Entry>,
+    /// Keys by when they were last used, least recently first
+    by_use: BTreeMap<u64, CacheKey>,
+    size: usize,
+    clock: u64,
+}
+
+/// A least-recently-used cache of parsed logs, holding up to a number of bytes of fields
+pub struct LogCache {
+    lru: Mutex<Lru>,
+    capacity: usize,
+}
+
+impl LogCache {
+    pub fn new(capacity_mb: usize) -> Self {
+        Self {
+            lru: Mutex::new(Lru::default()),
+            capacity: capacity_mb.saturating_mul(1024 * 1024),
+        }
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<ParsedFields>> {
+        let mut lru = self.lru.lock().unwrap();
+        lru.clock += 1;
+        let now = lru.clock;
+        let entry = lru.entries.get_mut(key)?;
+        let previous_use = std::mem::replace(&mut entry.last_used, now);
+        let fields = entry.fields.clone();
+        lru.by_use.remove(&previous_use);
+        lru.by_use.insert(now, key.clone());
+        Some(fields)
+    }
+
+    /// Adds a log's fields, evicting the least recently used logs to make room
+    pub fn insert(&self, key: CacheKey, fields: Arc<ParsedFields>) {
+        let size = ENTRY_OVERHEAD
+            + key.path.as_os_str().len()
+            + fields
+                .iter()
+                .map(|field| field.as_ref().map_or(0, Vec::len))
+                .sum::<usize>();
+        if size > self.capacity {
+            return;
+        }
+
+        let mut lru = self.lru.lock().unwrap();
+        if let Some(old) = lru.entries.remove(&key) {
+            lru.by_use.remove(&old.last_used);
+            lru.size -= old.size;
+        }
+        while lru.size + size > self.capacity {
+            let (_, evicted) = match lru.by_use.pop_first() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            if let Some(evicted) = lru.entries.remove(&evicted) {
+                lru.size -= evicted.size;
+            }
+        }
+        lru.clock += 1;
+        let now = lru.clock;
+        lru.by_use.insert(now, key.clone());
+        lru.entries.insert(
+            key,
+            Entry {
+                fields,
+                size,
+                last_used: now,
+            },
+        );
+        lru.size += size;
+    }
+}
@@ -1,68 +1,76 @@
 /// Battlesearch code for Pokémon Showdown battle logs
-mod search;
+mod accounts;
+mod activity;
+mod batch;
+mod bundle;
+mod cache;
+mod compare;
+mod diff;
+mod heatmap;
+mod inactive;
+mod intern;
+mod ladder;
+mod manifest;
+mod matrix;
+mod nice;
+mod notes;
+mod output;
+mod overlap;
+mod query;
+mod rate;
+mod renames;
+mod replays;
+mod results;
+mod selftest;
+mod serve;
+mod sets;
+mod stats;
+mod streaks;
+mod teams;
+mod terminal;
+mod timeline;
+mod usage;
 
-use search::{BattleSearchError, BattleSearcher, ToSend};
-use std::{path::PathBuf, sync::mpsc, thread};
-use structopt::StructOpt;
-
-const PIKKR_TRAINING_ROUNDS: usize = 2;
-
-fn get_filename(file: &PathBuf) -> Result<String, BattleSearchError> {
-    match file.file_name() {
-        Some(os_str) => match os_str.to_str() {
-            Some(s) => Ok(String::from(s)),
-            None => {
-                return Err(BattleSearchError::Path(format!(
-                    "Couldn't get filename of {:?}",
-                    file
-                )))
-            }
-        },
-        None => {
-            return Err(BattleSearchError::Path(format!(
-                "Couldn't get filename of {:?}",
-                file
-            )))
-        }
-    }
-}
-
-fn handle_dir(
-    directory: &PathBuf,
-    threads: &Vec<mpsc::Sender<ToSend>>,
-) -> Result<(), BattleSearchError> {
-    let mut current_sender_idx = 0;
-    let num_threads = threads.len();
-
-    let contents = directory.read_dir()?;
-    let date = get_filename(directory)?;
-    for entry in contents {
-        if let Ok(file) = entry {
-            if file.file_type()?.is_dir() {
-                handle_dir(&file.path(), &threads)?;
-            } else {
-                threads
-                    .get(current_sender_idx)
-                    .unwrap()
-                    .send(ToSend::File(file.path(), date.clone()))
-                    .unwrap_or_else(|e| {
-                        println!("{:?}", e);
-                    });
-                current_sender_idx = (current_sender_idx + 1) % num_threads;
-            }
-        }
-    }
-
-    Ok(())
-}
+use accounts::AccountsDb;
+use battle_tools::formats::{self, RoomId};
+use battle_tools::{date, layout, IdScheme};
+use battlesearch::{checkpoint, logcache, matcher, progress, runner, search, source, throttle};
+use bundle::Bundle;
+use cache::ResultCache;
+use checkpoint::Checkpoint;
+use ladder::LadderSnapshot;
+use layout::{ArchiveRoot, DateRange};
+use notes::Notes;
+use output::MatchWriter;
+use renames::Renames;
+use replays::Replays;
+use runner::{SearchJob, SearchSummary};
+use search::{BattleSearchError, IdPattern, Match, PatternIds, WithPath};
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use structopt::{
+    clap::{Error, ErrorKind, Shell},
+    StructOpt,
+};
+use throttle::Throttle;
 
 #[derive(StructOpt)]
 #[structopt(
     rename_all = "kebab-case",
     author = "Annika L.",
-    about = "Searches Pokémon Showdown battle logs"
+    about = "Searches Pokémon Showdown battle logs",
+    usage = "battlesearch [FLAGS] [OPTIONS] <username> <directories>...\n    \
+             battlesearch --batch [OPTIONS] <directories>...\n    \
+             battlesearch <SUBCOMMAND>"
 )]
 struct Options {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
     #[structopt(
         short = "w",
         long = "wins-only",
@@ -77,6 +85,388 @@ struct Options {
     )]
     forfeits_only: bool,
 
+    #[structopt(
+        long = "insta-forfeits",
+        help = "Only display games forfeited on or before turn 1, going by the log's turn \
+                counter; a common sign of win trading"
+    )]
+    insta_forfeits: bool,
+
+    #[structopt(
+        long = "no-randoms",
+        help = "Skip games in randomized formats, such as Random Battles and Battle Factory"
+    )]
+    no_randoms: bool,
+
+    #[structopt(
+        long = "filter",
+        help = "Only display games matching this expression, such as \"wins and not forfeit\". \
+                Combines wins, losses, tie, forfeit, insta-forfeit, ladder-error, random, \
+                unknown-winner, and unknown-end-type with and/or/not/parentheses."
+    )]
+    filter: Option<String>,
+
+    #[structopt(
+        long = "include-spectators",
+        help = "Also display games the user joined or chatted in without playing, tagged \
+                [spectator]"
+    )]
+    include_spectators: bool,
+
+    #[structopt(
+        long = "move",
+        help = "Only display games where the searched user used this move, such as \"Baton Pass\"; \
+                with --verbose, also display the turns it was used on"
+    )]
+    move_name: Option<String>,
+
+    #[structopt(
+        long = "room-after",
+        help = "Only display games whose room number (as in gen8ou-1234) is greater than this"
+    )]
+    room_after: Option<u64>,
+
+    #[structopt(
+        long = "room-before",
+        help = "Only display games whose room number (as in gen8ou-1234) is less than this"
+    )]
+    room_before: Option<u64>,
+
+    #[structopt(
+        long = "from",
+        help = "Only display games from this date on, such as 2021-05-01; 2021-05 or 2021 \
+                mean the start of that month or year",
+        parse(try_from_str = date::parse_date_prefix)
+    )]
+    from_date: Option<String>,
+
+    #[structopt(
+        long = "until",
+        help = "Only display games up to and including this date, such as 2021-05-31; \
+                2021-05 or 2021 include all of that month or year",
+        parse(try_from_str = date::parse_date_prefix)
+    )]
+    until_date: Option<String>,
+
+    #[structopt(
+        long = "modified-since",
+        help = "Only search log files modified since this date (YYYY-MM-DD, UTC) or within \
+                this long ago (like 48h or 7d; units are s, m, h, d, and w), skipping older \
+                files without reading them",
+        parse(try_from_str = date::parse_since)
+    )]
+    modified_since: Option<SystemTime>,
+
+    #[structopt(
+        long = "rooms-file",
+        help = "Only check the rooms listed in this file, one room id (like gen8ou-1234) or \
+                replay URL per line, and report any whose logs weren't found",
+        parse(from_os_str)
+    )]
+    rooms_file: Option<PathBuf>,
+
+    #[structopt(
+        long = "verify",
+        help = "Check each matching game's full log against its players and winner, flagging \
+                any that disagree, as corrupted or tampered-with logs would"
+    )]
+    verify: bool,
+
+    #[structopt(
+        long = "validate",
+        help = "Check every log, whether it matches or not, for missing fields, fields of the \
+                wrong type, and unknown end types, listing those that don't conform at the end"
+    )]
+    validate: bool,
+
+    #[structopt(
+        long = "sample",
+        value_name = "FRACTION|N-per-dir",
+        help = "Only search a random sample of each directory's logs, such as 0.1 for a tenth \
+                of them or 100-per-dir for 100, for a quick estimate of how many games a full \
+                search would find. Logs in archives are all searched."
+    )]
+    sample: Option<runner::Sample>,
+
+    #[structopt(
+        long = "min-score-diff",
+        value_name = "N",
+        help = "Only display games where one player finished with at least N more Pokémon left \
+                than the other, such as 3 for 3-0 or 4-1 games; games whose score can't be \
+                told from the log are left out"
+    )]
+    min_score_diff: Option<u32>,
+
+    #[structopt(
+        long = "id-scheme",
+        help = "How names are converted to ids when matching players: modern, or legacy for \
+                archives from before 2015, whose servers turned accented letters into \
+                unaccented ones instead of dropping them",
+        possible_values = &["modern", "legacy"],
+        default_value = "modern"
+    )]
+    id_scheme: IdScheme,
+
+    #[structopt(
+        long = "match-display-name",
+        help = "Match players by their exact display names, ignoring only case, instead of by \
+                id, so names that differ only in spaces or punctuation (like lookalikes of the \
+                searched user) aren't matched",
+        conflicts_with = "id-scheme"
+    )]
+    match_display_name: bool,
+
+    #[structopt(
+        long = "manifest",
+        help = "Write a JSON record of this search to this file: the arguments, when it was run, \
+                how many files were checked, errors, and the SHA-256 checksum of each matching log",
+        parse(from_os_str)
+    )]
+    manifest: Option<PathBuf>,
+
+    #[structopt(
+        long = "perf-log",
+        value_name = "PATH",
+        help = "Add a line of JSON to this file recording how fast the search ran: files and \
+                megabytes read per second, and the number of threads. Searches answered from \
+                --cache-dir aren't recorded.",
+        parse(from_os_str)
+    )]
+    perf_log: Option<PathBuf>,
+
+    #[structopt(
+        short = "v",
+        long = "verbose",
+        help = "Display the raw endType and ladderError/inputLog presence of each game"
+    )]
+    verbose: bool,
+
+    #[structopt(
+        long = "timestamps",
+        help = "Display when each battle ended, from its log's timestamp, as an ISO 8601 time \
+                in UTC (like 2021-05-01T10:27:03Z) in place of its date; battles whose log has \
+                no timestamp keep their date"
+    )]
+    timestamps: bool,
+
+    #[structopt(
+        long = "porcelain",
+        help = "Display games as tab-separated fields for scripts, in a format that won't change \
+                between releases: version (v1), date, room, p1, p2, winner (- for none, ? if \
+                unknown), end type, format, p for played or s for spectated, and path"
+    )]
+    porcelain: bool,
+
+    #[structopt(
+        long = "wide",
+        help = "On a terminal, don't cut off long usernames, rooms, and lines to fit its width"
+    )]
+    wide: bool,
+
+    #[structopt(
+        short = "s",
+        long = "sort",
+        help = "Display games in chronological order, with the gap since the previous game"
+    )]
+    sort: bool,
+
+    #[structopt(
+        long = "one-per-day",
+        help = "Only display one game from each day, to show which days the user was active on"
+    )]
+    one_per_day: bool,
+
+    #[structopt(
+        long = "max-gap-days",
+        help = "Only display games within this many days of another game (implies --sort)"
+    )]
+    max_gap_days: Option<i64>,
+
+    #[structopt(
+        long = "max-per-opponent",
+        help = "List at most this many games against each opponent, noting how many more \
+                there were; with --sort, the earliest ones",
+        parse(try_from_str = parse_game_count)
+    )]
+    max_per_opponent: Option<usize>,
+
+    #[structopt(
+        long = "split-output-by",
+        help = "Write games to a file per month in --output-dir, like 2021-05.txt, \
+                instead of displaying them",
+        possible_values = &["month"],
+        requires = "output-dir"
+    )]
+    split_output_by: Option<output::SplitBy>,
+
+    #[structopt(
+        long = "output-dir",
+        help = "The directory to write files to with --split-output-by",
+        requires = "split-output-by",
+        parse(from_os_str)
+    )]
+    output_dir: Option<PathBuf>,
+
+    #[structopt(
+        long = "hash-usernames",
+        help = "Display players as hashes of their names with this salt, which are consistent \
+                within a search but don't reveal who played"
+    )]
+    hash_usernames: Option<String>,
+
+    #[structopt(
+        long = "stats",
+        help = "Instead of listing games, display the user's first and last games and win-loss records \
+                by opponent and format"
+    )]
+    stats: bool,
+
+    #[structopt(
+        long = "min-games",
+        help = "With --stats, group opponents and formats with fewer than this many games together",
+        default_value = "1"
+    )]
+    min_games: usize,
+
+    #[structopt(
+        long = "top-opponents",
+        value_name = "N",
+        help = "Instead of listing games, display only the user's N most played opponents, with \
+                the user's record against each"
+    )]
+    top_opponents: Option<usize>,
+
+    #[structopt(
+        long = "rate-of-play",
+        help = "Instead of listing games, display the periods in which the user played more than \
+                --max-games-per-hour games within an hour, which may mean a bot was playing"
+    )]
+    rate_of_play: bool,
+
+    #[structopt(
+        long = "streaks",
+        help = "Instead of listing games, display the user's longest win and loss streaks, with \
+                the rooms and dates they started and ended on"
+    )]
+    streaks: bool,
+
+    #[structopt(
+        long = "link-bo3",
+        help = "Instead of listing games, display best-of-three sets (as in VGC), each as one \
+                match with the set's result: games between the same players in the same format, \
+                in sequential rooms ending within 30 minutes of each other, are linked until a \
+                player has won two"
+    )]
+    link_bo3: bool,
+
+    #[structopt(
+        long = "timeline",
+        value_name = "opponent",
+        help = "Instead of listing games, display the user's games against this opponent in \
+                order, numbered, with how long after the last each came and a tally of the \
+                results, for including in a report"
+    )]
+    timeline: Option<String>,
+
+    #[structopt(
+        long = "activity-profile",
+        help = "Instead of listing games, display histograms of the hours of the day and days of \
+                the week (in UTC) the user's games ended in"
+    )]
+    activity_profile: bool,
+
+    #[structopt(
+        long = "max-games-per-hour",
+        help = "With --rate-of-play, how many games in an hour is still plausible for a person",
+        default_value = "20"
+    )]
+    max_games_per_hour: usize,
+
+    #[structopt(
+        long = "heatmap",
+        help = "Instead of listing games, display a calendar of how many games were played each day"
+    )]
+    heatmap: bool,
+
+    #[structopt(
+        long = "heatmap-json",
+        help = "Write how many games were played each day to this file as JSON",
+        parse(from_os_str)
+    )]
+    heatmap_json: Option<PathBuf>,
+
+    #[structopt(
+        long = "usage",
+        help = "Instead of listing games, display how often each Pokémon was on the user's teams, \
+                by format"
+    )]
+    usage: bool,
+
+    #[structopt(
+        long = "usage-json",
+        help = "Write how often each Pokémon was on the user's teams, by format, to this file \
+                as JSON",
+        parse(from_os_str)
+    )]
+    usage_json: Option<PathBuf>,
+
+    #[structopt(
+        long = "team-clusters",
+        help = "Instead of listing games, display the distinct teams the user used in each \
+                format, grouping similar teams together, with when each was first and last used"
+    )]
+    team_clusters: bool,
+
+    #[structopt(
+        long = "accounts-db",
+        help = "A CSV dump of Showdown's user database, with userid and registertime columns; \
+                games are shown with when each player registered, to help spot new alts",
+        parse(from_os_str)
+    )]
+    accounts_db: Option<PathBuf>,
+
+    #[structopt(
+        long = "ladder-snapshot",
+        help = "A ladder downloaded from pokemonshowdown.com/ladder/FORMAT.json, or a JSON \
+                array of several; games in its formats are shown with roughly where each \
+                player stood on the ladder (as of the snapshot), to tell real opponents from \
+                low-ladder alts",
+        parse(from_os_str)
+    )]
+    ladder_snapshot: Option<PathBuf>,
+
+    #[structopt(
+        long = "notes",
+        help = "A file of notes on rooms, one per line like \"gen8ou-1234 confirmed wintrade\"; \
+                games in noted rooms are shown with their notes",
+        parse(from_os_str)
+    )]
+    notes: Option<PathBuf>,
+
+    #[structopt(
+        long = "replays",
+        help = "Also search replay.pokemonshowdown.com for the user's public replays, flagging \
+                games that have one and listing replays whose logs weren't found (without the \
+                filters that need a log). Needs the online feature."
+    )]
+    replays: bool,
+
+    #[structopt(
+        long = "renames",
+        help = "A file of account renames, one per line like \"Old Name -> New Name\"; games \
+                the user played under previous names are found too, noting who's been renamed",
+        parse(from_os_str)
+    )]
+    renames: Option<PathBuf>,
+
+    #[structopt(
+        long = "batch",
+        help = "Read a JSON array of queries like {\"id\": \"q1\", \"username\": \"Annika\", \
+                \"wins_only\": true} from standard input, and run them all in one search, \
+                writing matches keyed by query id as JSON. No username is given."
+    )]
+    batch: bool,
+
     #[structopt(
         short = "j",
         long = "threads",
@@ -85,60 +475,1145 @@ struct Options {
     )]
     threads: u32,
 
-    #[structopt(help = "The username whose battles will be displayed")]
-    username: String,
+    #[structopt(
+        long = "decompress-threads",
+        help = "The number of threads to spawn just for reading and decompressing logs \
+                (such as .log.json.gz files), leaving --threads to parse them",
+        default_value = "0"
+    )]
+    decompress_threads: u32,
+
+    #[structopt(
+        long = "queue-depth",
+        help = "How many batches of files can wait for each thread before directory traversal \
+                waits for them [default: 16]. --profile shows how full the queues got."
+    )]
+    queue_depth: Option<usize>,
+
+    #[structopt(
+        long = "max-mbps",
+        help = "Limit the total rate of reading logs to this many megabytes per second",
+        parse(try_from_str = parse_rate)
+    )]
+    max_mbps: Option<f64>,
+
+    #[structopt(
+        long = "nice",
+        help = "Search without getting in the way of other processes, as on a live server: \
+                lower the CPU and I/O priority, use at most one thread fewer than there are \
+                physical cores, limit reading to 50 megabytes per second unless --max-mbps is \
+                given, and yield to other processes between batches of logs"
+    )]
+    nice: bool,
+
+    #[structopt(
+        long = "max-bytes",
+        help = "Stop reading logs after this many bytes, displaying the games found so far"
+    )]
+    max_bytes: Option<u64>,
+
+    #[structopt(
+        long = "retries",
+        help = "Retry reading a log this many times after an I/O error, as on a network \
+                filesystem, before counting it as failed",
+        default_value = "0"
+    )]
+    retries: u32,
+
+    #[structopt(
+        long = "retry-delay",
+        help = "How many milliseconds to wait before the first retry; each retry waits twice \
+                as long as the last",
+        default_value = "100"
+    )]
+    retry_delay: u64,
+
+    #[structopt(
+        long = "file-timeout",
+        value_name = "SECS",
+        help = "Abandon a log if reading and decompressing it takes longer than this many \
+                seconds, counting it rather than letting it stall the search",
+        parse(try_from_str = parse_seconds)
+    )]
+    file_timeout: Option<Duration>,
+
+    #[structopt(
+        long = "max-file-size",
+        value_name = "BYTES",
+        help = "Abandon logs larger than this many bytes, on disk or once decompressed, \
+                without reading them in full"
+    )]
+    max_file_size: Option<u64>,
 
     #[structopt(
-        help = "The directories to search for battle logs in. Searches recursively.",
-        required(true)
+        long = "profile",
+        help = "Display how long each worker thread spent reading, parsing, and matching logs, \
+                to help choose --threads"
     )]
-    #[structopt(parse(from_os_str))]
-    directories: Vec<PathBuf>,
+    profile: bool,
+
+    #[structopt(
+        long = "fail-fast",
+        help = "Stop searching if a directory can't be read, instead of skipping it"
+    )]
+    fail_fast: bool,
+
+    #[structopt(
+        long = "restrict-to-root",
+        help = "Don't read files through symlinks that lead outside the directories given, \
+                warning about them instead, as when searching paths others can write to"
+    )]
+    restrict_to_root: bool,
+
+    #[structopt(
+        long = "cache-dir",
+        help = "Cache results in this directory, so repeating a search over unchanged \
+                directories is instant",
+        parse(from_os_str)
+    )]
+    cache_dir: Option<PathBuf>,
+
+    #[structopt(
+        long = "export-bundle",
+        help = "Copy each matching log into this directory, in a directory per date, \
+                along with a manifest.json describing the search and its results, with a \
+                checksum of each log for verify-bundle",
+        parse(from_os_str)
+    )]
+    export_bundle: Option<PathBuf>,
+
+    #[structopt(
+        long = "results-json",
+        help = "Write the matches to this file as JSON, along with what was searched for, \
+                so the results of searches run separately can be combined with merge",
+        parse(from_os_str)
+    )]
+    results_json: Option<PathBuf>,
+
+    #[structopt(
+        long = "checkpoint",
+        help = "Periodically record processed files to this file, so the search can be resumed",
+        parse(from_os_str)
+    )]
+    checkpoint: Option<PathBuf>,
+
+    #[structopt(
+        long = "resume",
+        help = "Skip files recorded as processed in this checkpoint file, reporting the \
+                matches found in them as if they had been found again",
+        parse(from_os_str)
+    )]
+    resume: Option<PathBuf>,
+
+    #[structopt(
+        long = "root",
+        help = "A log archive laid out as Showdown writes it, ROOT/YYYY-MM/FORMAT/YYYY-MM-DD, \
+                whose months, formats, and days are searched as needed for --from, --until, \
+                --no-randoms, and --rooms-file, without listing them as directories",
+        parse(from_os_str)
+    )]
+    root: Option<PathBuf>,
+
+    #[structopt(
+        help = "The username whose battles will be displayed. With * (any characters) or ? \
+                (any one character), it's a pattern, like annika* for any id starting with \
+                annika, and the ids it matched are listed at the end."
+    )]
+    username: Option<String>,
+
+    // Not required(true), since subcommands don't take it; checked in main() instead.
+    #[structopt(
+        help = "The directories to search for battle logs in. Searches recursively. \
+                Append :layout=daily (the default), :layout=monthly, or :layout=flat to say \
+                whether dates come from YYYY-MM-DD directories, YYYY-MM directories, \
+                or file modification times. Append :source-label=NAME (like \
+                :source-label=main) to tag results with the archive they came from, when \
+                searching archives from several servers, whose rooms can repeat. Zip and tar \
+                archives (.zip, .tar, .tar.gz, and .7z when built with the sevenz feature) \
+                inside them are searched as if they were directories, and can be searched \
+                directly."
+    )]
+    #[structopt(parse(try_from_os_str = ArchiveRoot::from_os_str))]
+    directories: Vec<ArchiveRoot>,
 }
 
-fn main() -> Result<(), BattleSearchError> {
-    let options = Options::from_args();
-    let mut senders = vec![];
-    let mut join_handles = vec![];
-    for _ in 1..=options.threads {
-        let (sender, receiver) = mpsc::channel();
-        let username = options.username.clone();
-        let wins_only = options.wins_only;
-        let forfeits_only = options.forfeits_only;
-        join_handles.push(thread::spawn(move || {
-            let mut searcher =
-                BattleSearcher::new(&username, PIKKR_TRAINING_ROUNDS, wins_only, forfeits_only);
-            loop {
-                match receiver.recv() {
-                    Ok(data) => match data {
-                        ToSend::File(path, date) => {
-                            if let Err(e) = searcher.check_log(&path, &date) {
-                                eprintln!("Error parsing {:?}: {:?}", path, e);
-                            }
-                        }
-                        ToSend::Done => return,
-                    },
-                    Err(e) => {
-                        eprintln!("{:?}", e);
-                        return;
-                    }
-                }
+#[derive(StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+enum Command {
+    #[structopt(
+        about = "Serves searches as JSON-RPC over HTTP, streaming matches as they're found"
+    )]
+    Serve {
+        #[structopt(
+            long = "listen",
+            help = "The address to listen on, such as 127.0.0.1:8080"
+        )]
+        listen: String,
+
+        #[structopt(
+            short = "j",
+            long = "threads",
+            help = "The number of threads to spawn per search",
+            default_value = "2"
+        )]
+        threads: u32,
+
+        #[structopt(
+            long = "cache-mb",
+            help = "Keep up to this many megabytes of parsed logs in memory between searches, \
+                    so searching the same directories again doesn't read them again"
+        )]
+        cache_mb: Option<usize>,
+
+        #[structopt(
+            long = "root",
+            help = "A directory clients may search, along with everything in it; requests for \
+                    directories outside every root are refused",
+            required(true),
+            min_values(1),
+            parse(from_os_str)
+        )]
+        roots: Vec<PathBuf>,
+    },
+
+    #[structopt(
+        about = "Displays how many games each pair of the given users played against each other, \
+                 with win-loss records for the row's user"
+    )]
+    Matrix {
+        #[structopt(help = "The users to compare", required(true), min_values(2))]
+        usernames: Vec<String>,
+
+        #[structopt(
+            long = "dirs",
+            help = "The directories to search for battle logs in, as in the main search",
+            required(true),
+            min_values(1),
+            parse(try_from_os_str = ArchiveRoot::from_os_str)
+        )]
+        directories: Vec<ArchiveRoot>,
+
+        #[structopt(
+            short = "j",
+            long = "threads",
+            help = "The number of threads to spawn",
+            default_value = "2"
+        )]
+        threads: u32,
+    },
+
+    #[structopt(
+        about = "Lists the opponents each of two users played that the other didn't, \
+                 to tell alts apart from players who happen to be similar"
+    )]
+    Diff {
+        #[structopt(help = "The first user")]
+        username_a: String,
+
+        #[structopt(help = "The second user")]
+        username_b: String,
+
+        #[structopt(
+            help = "The directories to search for battle logs in, as in the main search",
+            required(true),
+            min_values(1),
+            parse(try_from_os_str = ArchiveRoot::from_os_str)
+        )]
+        directories: Vec<ArchiveRoot>,
+
+        #[structopt(
+            long = "from",
+            help = "Only compare games from this date on, as in the main search",
+            parse(try_from_str = date::parse_date_prefix)
+        )]
+        from_date: Option<String>,
+
+        #[structopt(
+            long = "until",
+            help = "Only compare games up to and including this date, as in the main search",
+            parse(try_from_str = date::parse_date_prefix)
+        )]
+        until_date: Option<String>,
+
+        #[structopt(
+            short = "j",
+            long = "threads",
+            help = "The number of threads to spawn",
+            default_value = "2"
+        )]
+        threads: u32,
+    },
+
+    #[structopt(
+        about = "Compares how a user played two battles side by side: their teams, leads, and \
+                 moves each turn, marking where they're the same"
+    )]
+    Compare {
+        #[structopt(help = "The user whose play is compared")]
+        username: String,
+
+        #[structopt(help = "The first battle's room, like gen8ou-1234")]
+        room_a: String,
+
+        #[structopt(help = "The second battle's room")]
+        room_b: String,
+
+        #[structopt(
+            help = "The directories to look for the battles' logs in, as in the main search",
+            required(true),
+            min_values(1),
+            parse(try_from_os_str = ArchiveRoot::from_os_str)
+        )]
+        directories: Vec<ArchiveRoot>,
+    },
+
+    #[structopt(
+        about = "Lists the times two users were both playing, which one person playing both \
+                 accounts would rarely be"
+    )]
+    Overlap {
+        #[structopt(help = "The first user")]
+        username_a: String,
+
+        #[structopt(help = "The second user")]
+        username_b: String,
+
+        #[structopt(
+            help = "The directories to search for battle logs in, as in the main search",
+            required(true),
+            min_values(1),
+            parse(try_from_os_str = ArchiveRoot::from_os_str)
+        )]
+        directories: Vec<ArchiveRoot>,
+
+        #[structopt(
+            long = "game-minutes",
+            help = "How long each game is taken to have lasted before the time its log was \
+                    written, since logs only record when games ended",
+            default_value = "15"
+        )]
+        game_minutes: u32,
+
+        #[structopt(
+            long = "from",
+            help = "Only compare games from this date on, as in the main search",
+            parse(try_from_str = date::parse_date_prefix)
+        )]
+        from_date: Option<String>,
+
+        #[structopt(
+            long = "until",
+            help = "Only compare games up to and including this date, as in the main search",
+            parse(try_from_str = date::parse_date_prefix)
+        )]
+        until_date: Option<String>,
+
+        #[structopt(
+            short = "j",
+            long = "threads",
+            help = "The number of threads to spawn",
+            default_value = "2"
+        )]
+        threads: u32,
+    },
+
+    #[structopt(
+        about = "Lists which of a list of users have played no games since a date, searching \
+                 for all of them at once"
+    )]
+    Inactive {
+        #[structopt(
+            long = "since",
+            help = "Users count as active if they played a game on or after this date",
+            parse(try_from_str = date::parse_date_prefix)
+        )]
+        since: String,
+
+        #[structopt(help = "A file of usernames, one per line", parse(from_os_str))]
+        users_file: PathBuf,
+
+        #[structopt(
+            help = "The directories to search for battle logs in, as in the main search",
+            required(true),
+            min_values(1),
+            parse(try_from_os_str = ArchiveRoot::from_os_str)
+        )]
+        directories: Vec<ArchiveRoot>,
+
+        #[structopt(
+            short = "j",
+            long = "threads",
+            help = "The number of threads to spawn",
+            default_value = "2"
+        )]
+        threads: u32,
+    },
+
+    #[structopt(about = "Searches every battle with a query, such as \
+                 'player:annika AND (winner:annika OR endtype:forfeit) AND date>=2021-05'")]
+    Query {
+        #[structopt(
+            help = "The query: terms like player:NAME, winner:NAME, loser:NAME, format:ID, \
+                    endtype:TYPE, rated:true, date>=YYYY-MM-DD, or turns<N, combined with \
+                    AND, OR, NOT, and parentheses"
+        )]
+        query: String,
+
+        #[structopt(
+            long = "dirs",
+            help = "The directories to search for battle logs in, as in the main search",
+            required(true),
+            min_values(1),
+            parse(try_from_os_str = ArchiveRoot::from_os_str)
+        )]
+        directories: Vec<ArchiveRoot>,
+
+        #[structopt(
+            short = "j",
+            long = "threads",
+            help = "The number of threads to spawn",
+            default_value = "2"
+        )]
+        threads: u32,
+
+        #[structopt(
+            short = "v",
+            long = "verbose",
+            help = "Display the raw endType and ladderError/inputLog presence of each game"
+        )]
+        verbose: bool,
+    },
+
+    #[structopt(
+        about = "Searches a generated set of logs with known contents, checking that every \
+                 search finds what it should"
+    )]
+    Selftest {
+        #[structopt(
+            long = "dir",
+            help = "Where to generate the logs, to test a particular filesystem; defaults to \
+                    the system's temporary directory",
+            parse(from_os_str)
+        )]
+        directory: Option<PathBuf>,
+
+        #[structopt(long = "keep", help = "Don't delete the generated logs afterwards")]
+        keep: bool,
+    },
+
+    #[structopt(
+        about = "Checks that the logs in an evidence bundle from --export-bundle are unmodified, \
+                 going by the checksums in its manifest"
+    )]
+    VerifyBundle {
+        #[structopt(help = "The bundle's directory", parse(from_os_str))]
+        directory: PathBuf,
+    },
+
+    #[structopt(
+        about = "Combines results files from --results-json, as from searches of archives on \
+                 different machines, into one chronological list, counting battles found by \
+                 more than one search once"
+    )]
+    Merge {
+        #[structopt(
+            help = "The results files to combine",
+            required(true),
+            min_values(1),
+            parse(from_os_str)
+        )]
+        files: Vec<PathBuf>,
+
+        #[structopt(
+            long = "json",
+            help = "Write the combined results as a results file to standard output, instead \
+                    of listing them"
+        )]
+        json: bool,
+    },
+
+    #[structopt(about = "Prints a shell completion script for battlesearch to standard output")]
+    Completions {
+        #[structopt(
+            help = "The shell to generate completions for",
+            possible_values = &Shell::variants(),
+            case_insensitive = true
+        )]
+        shell: Shell,
+    },
+}
+
+/// Parses a number of seconds, which may be fractional
+fn parse_seconds(arg: &str) -> Result<Duration, String> {
+    arg.parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("{} isn't a number of seconds", arg))
+}
+
+/// Parses a number of games, which must be at least one
+fn parse_game_count(arg: &str) -> Result<usize, String> {
+    match arg.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("'{}' isn't a positive number of games", arg)),
+    }
+}
+
+/// Parses a rate in megabytes per second, which must be above zero
+fn parse_rate(arg: &str) -> Result<f64, String> {
+    match arg.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!(
+            "'{}' isn't a positive number of megabytes per second",
+            arg
+        )),
+    }
+}
+
+/// Runs a search, using and updating the result cache if there is one
+fn run_search<F: FnMut(Match)>(
+    job: SearchJob,
+    cache: Option<&ResultCache>,
+    mut on_match: F,
+) -> Result<SearchSummary, BattleSearchError> {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => return job.run(on_match),
+    };
+    if let Some(matches) = cache.load() {
+        eprintln!("Using cached results");
+        for m in matches {
+            on_match(m);
+        }
+        return Ok(SearchSummary {
+            from_cache: true,
+            ..SearchSummary::default()
+        });
+    }
+
+    let mut matches = vec![];
+    let summary = job.run(|m| {
+        matches.push(m.clone());
+        on_match(m);
+    })?;
+    // partial results shouldn't be reused
+    if summary.is_complete() {
+        cache.store(matches)?;
+    }
+    Ok(summary)
+}
+
+fn main() {
+    progress::listen();
+    if let Err(e) = run(Options::from_args()) {
+        eprintln!("battlesearch: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: Options) -> Result<(), BattleSearchError> {
+    match options.command {
+        Some(Command::Serve {
+            listen,
+            threads,
+            cache_mb,
+            roots,
+        }) => return serve::serve(&listen, threads, cache_mb, &roots),
+        Some(Command::Matrix {
+            usernames,
+            directories,
+            threads,
+        }) => {
+            let mut job = SearchJob::new_multi(usernames.clone(), directories);
+            job.threads = threads;
+            let mut aggregator = output::Aggregator::new();
+            let summary = job.run(|m| aggregator.add(m))?;
+            matrix::print_matrix(&usernames, aggregator.matches().iter());
+            summary.print();
+            return Ok(());
+        }
+        Some(Command::Diff {
+            username_a,
+            username_b,
+            directories,
+            from_date,
+            until_date,
+            threads,
+        }) => {
+            let usernames = vec![username_a.clone(), username_b.clone()];
+            let mut job = SearchJob::new_multi(usernames, directories);
+            job.dates = DateRange {
+                from: from_date,
+                until: until_date,
+            };
+            job.threads = threads;
+            let mut aggregator = output::Aggregator::new();
+            let summary = job.run(|m| aggregator.add(m))?;
+            diff::print_opponent_diff(&username_a, &username_b, aggregator.matches().iter());
+            summary.print();
+            return Ok(());
+        }
+        Some(Command::Compare {
+            username,
+            room_a,
+            room_b,
+            directories,
+        }) => return compare::compare_battles(&username, [&room_a, &room_b], &directories),
+        Some(Command::Overlap {
+            username_a,
+            username_b,
+            directories,
+            game_minutes,
+            from_date,
+            until_date,
+            threads,
+        }) => {
+            let usernames = vec![username_a.clone(), username_b.clone()];
+            let mut job = SearchJob::new_multi(usernames, directories);
+            job.dates = DateRange {
+                from: from_date,
+                until: until_date,
+            };
+            job.threads = threads;
+            let mut aggregator = output::Aggregator::new();
+            let summary = job.run(|m| aggregator.add(m))?;
+            overlap::print_overlap(
+                &username_a,
+                &username_b,
+                aggregator.matches().iter(),
+                game_minutes,
+            );
+            summary.print();
+            return Ok(());
+        }
+        Some(Command::Inactive {
+            since,
+            users_file,
+            directories,
+            threads,
+        }) => {
+            let usernames = inactive::read_users(&users_file)?;
+            if usernames.is_empty() {
+                return Err(BattleSearchError::Path(format!(
+                    "{} doesn't list any users",
+                    users_file.display()
+                )));
             }
-        }));
-        senders.push(sender);
+            let mut activity = inactive::Activity::new(&usernames);
+            let mut job = SearchJob::new_multi(usernames.clone(), directories);
+            job.dates = DateRange {
+                from: Some(since.clone()),
+                until: None,
+            };
+            job.threads = threads;
+            let summary = job.run(|m| activity.add(&m))?;
+            activity.print_inactive(&usernames, &since);
+            summary.print();
+            return Ok(());
+        }
+        Some(Command::Query {
+            query,
+            directories,
+            threads,
+            verbose,
+        }) => {
+            // no users, so every battle is checked against the query
+            let mut job = SearchJob::new_multi(vec![], directories);
+            job.filter = Some(query::parse_query(&query)?);
+            job.threads = threads;
+            let summary = job.run(|m| println!("{}", output::format_match(&m, verbose)))?;
+            summary.print();
+            return Ok(());
+        }
+        Some(Command::Selftest { directory, keep }) => {
+            return selftest::run_selftest(directory.as_deref(), keep)
+        }
+        Some(Command::VerifyBundle { directory }) => return bundle::verify_bundle(&directory),
+        Some(Command::Merge { files, json }) => return results::merge_results(&files, json),
+        Some(Command::Completions { shell }) => {
+            Options::clap().gen_completions_to("battlesearch", shell, &mut std::io::stdout());
+            return Ok(());
+        }
+        None => (),
     }
 
-    for directory in &(options.directories) {
-        handle_dir(directory, &senders)?;
+    if options.batch {
+        // there's no username, so the first positional argument is a directory too
+        let mut directories = vec![];
+        if let Some(ref arg) = options.username {
+            directories.push(ArchiveRoot::parse(arg).map_err(BattleSearchError::Path)?);
+        }
+        directories.extend(options.directories);
+        if directories.is_empty() {
+            Error::with_description(
+                "at least one directory is required",
+                ErrorKind::MissingRequiredArgument,
+            )
+            .exit();
+        }
+        batch::run_batch(directories, options.threads)?.print();
+        return Ok(());
     }
 
-    for sender in senders {
-        sender.send(ToSend::Done)?;
+    let username = match options.username {
+        Some(ref username) if !options.directories.is_empty() || options.root.is_some() => {
+            username.clone()
+        }
+        _ => Error::with_description(
+            "a username and at least one directory (or --root) are required",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit(),
+    };
+    // the rest of battlesearch converts names the modern way, which leaves legacy ids as
+    // they are
+    let pattern = IdPattern::parse(&username);
+    let username = match options.id_scheme {
+        IdScheme::Modern => username,
+        _ if pattern.is_some() => username,
+        scheme => scheme.to_id(&username),
+    };
+
+    let renames = match options.renames {
+        Some(ref path) => Some(Renames::load(path)?),
+        None => None,
+    };
+    let previous_ids = match renames {
+        Some(ref renames) => renames.previous_ids(&search::str_to_id(&username)),
+        None => vec![],
+    };
+
+    let keep_teams = options.usage || options.usage_json.is_some() || options.team_clusters;
+    let rooms = match options.rooms_file {
+        Some(ref path) => Some(runner::read_room_list(path)?),
+        None => None,
+    };
+    let dates = DateRange {
+        from: options.from_date.clone(),
+        until: options.until_date.clone(),
+    };
+    let mut directories = options.directories.clone();
+    if let Some(ref root) = options.root {
+        let room_formats: Option<HashSet<String>> = rooms.as_ref().map(|rooms| {
+            rooms
+                .iter()
+                .filter_map(|room| RoomId::parse(room))
+                .map(|room| room.format.id())
+                .collect()
+        });
+        let no_randoms = options.no_randoms;
+        let include_format = |name: &str| {
+            let format = formats::normalize_format(name).unwrap_or_default();
+            !(no_randoms && formats::is_randomized(&format))
+                && room_formats
+                    .as_ref()
+                    .is_none_or(|room_formats| room_formats.contains(&format))
+        };
+        directories.extend(layout::discover_roots(root, &dates, include_format).with_path(root)?);
     }
+    // everything that affects which matches are found
+    let parameters = serde_json::json!({
+        "user": match pattern {
+            Some(ref pattern) => String::from(pattern.as_str()),
+            None => search::str_to_id(&username),
+        },
+        "previous_ids": previous_ids,
+        "directories": directories
+            .iter()
+            .map(|root| format!("{:?}", root))
+            .collect::<Vec<_>>(),
+        "wins_only": options.wins_only,
+        "forfeits_only": options.forfeits_only,
+        "insta_forfeits": options.insta_forfeits,
+        "no_randoms": options.no_randoms,
+        "filter": options.filter,
+        "include_spectators": options.include_spectators,
+        "move": options.move_name,
+        "room_after": options.room_after,
+        "room_before": options.room_before,
+        "from": options.from_date,
+        "until": options.until_date,
+        "rooms": rooms.as_ref().map(|rooms| {
+            let mut rooms: Vec<&String> = rooms.iter().collect();
+            rooms.sort();
+            rooms
+        }),
+        // cached matches only have teams or inconsistencies if they were looked for
+        "verify": options.verify,
+        "min_score_diff": options.min_score_diff,
+        "sample": options.sample.map(|sample| sample.to_string()),
+        "restrict_to_root": options.restrict_to_root,
+        "id_scheme": options.id_scheme.name(),
+        "match_display_name": options.match_display_name,
+        "keep_teams": keep_teams,
+        "modified_since": options.modified_since.map(|since| {
+            since.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+        }),
+    });
+    let cache = match options.cache_dir {
+        // a resumed search only finds part of the results, and cached results don't say which
+        // logs are invalid
+        Some(ref cache_dir) if options.resume.is_none() && !options.validate => Some(
+            ResultCache::new(cache_dir, &parameters.to_string(), &directories)?,
+        ),
+        _ => None,
+    };
 
-    for handle in join_handles {
-        handle.join()?;
+    let mut usernames = vec![username.clone()];
+    usernames.extend(previous_ids.iter().cloned());
+    let filter = match options.filter {
+        // the user's previous ids count as them
+        Some(ref filter) => Some(matcher::parse_filter(filter, &usernames)?),
+        None => None,
+    };
+    let mut job = SearchJob::new_multi(usernames, directories);
+    job.wins_only = options.wins_only;
+    job.forfeits_only = options.forfeits_only;
+    job.insta_forfeits = options.insta_forfeits;
+    job.no_randoms = options.no_randoms;
+    job.filter = filter;
+    job.include_spectators = options.include_spectators;
+    job.move_name = options.move_name.clone();
+    job.room_after = options.room_after;
+    job.room_before = options.room_before;
+    job.dates = dates.clone();
+    job.modified_since = options.modified_since;
+    job.keep_teams = keep_teams;
+    job.rooms = rooms;
+    job.verify = options.verify;
+    job.validate = options.validate;
+    job.sample = options.sample;
+    job.min_score_diff = options.min_score_diff;
+    job.id_scheme = options.id_scheme;
+    job.match_display_name = options.match_display_name;
+    job.threads = options.threads;
+    job.decompress_threads = options.decompress_threads;
+    job.queue_depth = options.queue_depth.unwrap_or(runner::DEFAULT_QUEUE_DEPTH);
+    job.fail_fast = options.fail_fast;
+    job.restrict_to_root = options.restrict_to_root;
+    job.max_bytes = options.max_bytes;
+    job.retry = runner::Retry {
+        retries: options.retries,
+        delay: Duration::from_millis(options.retry_delay),
+    };
+    job.file_limits = runner::FileLimits {
+        timeout: options.file_timeout,
+        max_size: options.max_file_size,
+    };
+    job.profile = options.profile;
+    let max_mbps = match options.max_mbps {
+        None if options.nice => Some(nice::MAX_MBPS),
+        max_mbps => max_mbps,
+    };
+    if let Some(max_mbps) = max_mbps {
+        job.throttle = Some(Arc::new(Throttle::new(max_mbps)));
+    }
+    if options.nice {
+        // before the search starts any threads, so they're all lowered
+        nice::lower_priority();
+        job.threads = job.threads.min(nice::max_threads());
+        job.yield_between_batches = true;
+    }
+    if let Some(ref path) = options.resume {
+        let resumed = checkpoint::load_processed(path)?;
+        job.already_processed = resumed.processed;
+        job.resumed_matches = resumed.matches;
+    }
+    if let Some(ref path) = options.checkpoint {
+        job.checkpoint = Some(Arc::new(Checkpoint::new(path)?));
     }
 
-    Ok(())
+    let verbose = options.verbose;
+    // lines written to files are left as they are
+    let to_terminal = options.split_output_by.is_none() || options.output_dir.is_none();
+    let columns = match terminal::stdout_width() {
+        Some(width) if to_terminal => Some(output::Columns {
+            width: if options.wide { None } else { Some(width) },
+        }),
+        _ => None,
+    };
+    let style = if options.porcelain {
+        output::Style::Porcelain
+    } else {
+        output::Style::Human {
+            verbose,
+            columns,
+            timestamps: options.timestamps,
+        }
+    };
+    let manifest_path = options.manifest.as_deref();
+    let perf_log = options.perf_log.as_deref();
+    let (threads, decompress_threads) = (options.threads, options.decompress_threads);
+    let finish_search = |summary: SearchSummary, matched: &[PathBuf]| {
+        if verbose {
+            eprintln!("Read {} bytes of logs", summary.bytes_read);
+        }
+        summary.print();
+        if let Some(path) = perf_log.filter(|_| !summary.from_cache) {
+            manifest::append_perf_log(path, &summary, threads, decompress_threads)?;
+        }
+        match manifest_path {
+            Some(path) => manifest::write_manifest(path, &parameters, &summary, matched),
+            None => Ok(()),
+        }
+    };
+
+    let hasher = options
+        .hash_usernames
+        .as_deref()
+        .map(output::UsernameHasher::new);
+    let notes = match options.notes {
+        Some(ref path) => Some(Notes::load(path)?),
+        None => None,
+    };
+    let replays = if options.replays {
+        Some(Replays::fetch(&username)?)
+    } else {
+        None
+    };
+    let annotate = |m: Match| {
+        let m = match notes {
+            Some(ref notes) => notes.annotate(m),
+            None => m,
+        };
+        match replays {
+            Some(ref replays) => replays.annotate(m),
+            None => m,
+        }
+    };
+    let anonymize = |m: Match| {
+        let m = match renames {
+            Some(ref renames) => renames.apply(m),
+            None => m,
+        };
+        match hasher {
+            Some(ref hasher) => hasher.apply(m),
+            None => m,
+        }
+    };
+
+    let mut writer = match (options.split_output_by, options.output_dir) {
+        (Some(by), Some(directory)) => MatchWriter::split(by, directory)?,
+        _ => MatchWriter::Stdout,
+    };
+
+    let mut bundle = match options.export_bundle {
+        Some(ref directory) => Some(Bundle::new(directory)?),
+        None => None,
+    };
+
+    let sorted = options.sort || options.max_gap_days.is_some();
+    let aggregated = sorted
+        || options.one_per_day
+        || options.stats
+        || options.rate_of_play
+        || options.streaks
+        || options.link_bo3
+        || options.timeline.is_some()
+        || options.activity_profile
+        || options.top_opponents.is_some()
+        || options.heatmap
+        || options.heatmap_json.is_some()
+        || options.usage
+        || options.usage_json.is_some()
+        || options.team_clusters
+        || options.accounts_db.is_some()
+        || options.ladder_snapshot.is_some()
+        || options.max_per_opponent.is_some()
+        || options.results_json.is_some();
+    // before renames and hashing, which would change the ids
+    let mut pattern_ids = pattern.map(PatternIds::new);
+    if !aggregated {
+        let mut write_result = Ok(());
+        let mut matched = vec![];
+        let mut found_rooms = HashSet::new();
+        let summary = run_search(job, cache.as_ref(), |m| {
+            if let Some(ref mut pattern_ids) = pattern_ids {
+                pattern_ids.add(&m);
+            }
+            let m = anonymize(annotate(m));
+            if manifest_path.is_some() {
+                matched.push(m.path.clone());
+            }
+            if replays.is_some() {
+                found_rooms.insert(m.room.clone());
+            }
+            if write_result.is_ok() {
+                write_result = writer
+                    .write(&m, &style.format(&m))
+                    .map_err(BattleSearchError::from);
+            }
+            if let (Some(ref mut bundle), Ok(())) = (&mut bundle, &write_result) {
+                write_result = bundle.add(&m);
+            }
+        })?;
+        write_result?;
+        if let Some(ref replays) = replays {
+            let found = found_rooms.iter().map(String::as_str).collect();
+            for m in replays.replay_only(&found, &dates) {
+                let m = anonymize(m);
+                writer.write(&m, &style.format(&m))?;
+            }
+        }
+        writer.finish()?;
+        if let Some(bundle) = bundle {
+            bundle.finish(&parameters)?;
+        }
+        if let Some(ref pattern_ids) = pattern_ids {
+            pattern_ids.print();
+        }
+        return finish_search(summary, &matched);
+    }
+
+    let mut aggregator = if options.one_per_day {
+        output::Aggregator::one_per_day()
+    } else {
+        output::Aggregator::new()
+    };
+    let accounts = match options.accounts_db {
+        Some(ref path) => Some(AccountsDb::load(path)?),
+        None => None,
+    };
+    let ladder = match options.ladder_snapshot {
+        Some(ref path) => Some(LadderSnapshot::load(path)?),
+        None => None,
+    };
+    let summary = run_search(job, cache.as_ref(), |m| {
+        if let Some(ref mut pattern_ids) = pattern_ids {
+            pattern_ids.add(&m);
+        }
+        // before hashing, which would hide whose accounts they are
+        let m = match accounts {
+            Some(ref accounts) => accounts.annotate(m),
+            None => m,
+        };
+        let m = match ladder {
+            Some(ref ladder) => ladder.annotate(m),
+            None => m,
+        };
+        aggregator.add(anonymize(annotate(m)))
+    })?;
+    if let Some(ref replays) = replays {
+        let found = aggregator
+            .matches()
+            .iter()
+            .map(|m| m.room.as_str())
+            .collect();
+        for m in replays.replay_only(&found, &dates) {
+            aggregator.add(anonymize(m));
+        }
+    }
+    output::print_room_collisions(&aggregator);
+    if let Some(ref path) = options.results_json {
+        results::write_results(path, &parameters, aggregator.matches())?;
+    }
+    if let Some(ref pattern_ids) = pattern_ids {
+        pattern_ids.print();
+    }
+    if let Some(mut bundle) = bundle {
+        for m in aggregator.matches() {
+            bundle.add(m)?;
+        }
+        bundle.finish(&parameters)?;
+    }
+    if options.heatmap || options.heatmap_json.is_some() {
+        let counts = heatmap::count_by_day(aggregator.matches().iter());
+        if let Some(ref path) = options.heatmap_json {
+            heatmap::write_heatmap_json(&counts, path)?;
+        }
+        if options.heatmap {
+            heatmap::print_heatmap(&counts);
+        }
+    }
+    // matches have players' current ids, once renames are applied
+    let user = match renames {
+        Some(ref renames) => String::from(renames.current_id(&search::str_to_id(&username))),
+        None => username,
+    };
+    let user = match hasher {
+        Some(ref hasher) => hasher.hash(&search::str_to_id(&user)),
+        None => user,
+    };
+    if options.usage || options.usage_json.is_some() {
+        let counts = usage::count_usage(&user, aggregator.matches().iter());
+        if let Some(ref path) = options.usage_json {
+            usage::write_usage_json(&counts, path)?;
+        }
+        if options.usage {
+            usage::print_usage(&counts);
+        }
+    }
+    let matched: Vec<PathBuf> = match manifest_path {
+        Some(_) => aggregator
+            .matches()
+            .iter()
+            .map(|m| m.path.clone())
+            .collect(),
+        None => vec![],
+    };
+    if options.team_clusters {
+        teams::print_team_clusters(&user, aggregator.matches().iter());
+    }
+    if options.stats {
+        stats::print_stats(&user, aggregator.matches().iter(), options.min_games);
+    }
+    if options.rate_of_play {
+        rate::print_rate_of_play(aggregator.matches().iter(), options.max_games_per_hour);
+    }
+    if options.streaks {
+        streaks::print_streaks(&user, aggregator.sort());
+    }
+    if options.link_bo3 {
+        sets::print_sets(aggregator.sort());
+    }
+    if let Some(ref opponent) = options.timeline {
+        let opponent = search::str_to_id(opponent);
+        let opponent = match renames {
+            Some(ref renames) => String::from(renames.current_id(&opponent)),
+            None => opponent,
+        };
+        let opponent = match hasher {
+            Some(ref hasher) => hasher.hash(&opponent),
+            None => opponent,
+        };
+        timeline::print_timeline(&search::str_to_id(&user), &opponent, aggregator.sort());
+    }
+    if let Some(top) = options.top_opponents {
+        stats::print_top_opponents(&user, aggregator.matches().iter(), top);
+    }
+    if options.activity_profile {
+        activity::print_activity_profile(aggregator.matches().iter());
+    }
+    if !(options.heatmap
+        || options.stats
+        || options.rate_of_play
+        || options.streaks
+        || options.link_bo3
+        || options.timeline.is_some()
+        || options.activity_profile
+        || options.top_opponents.is_some()
+        || options.usage
+        || options.team_clusters)
+    {
+        let user_id = search::str_to_id(&user);
+        if sorted {
+            output::print_sorted(
+                aggregator,
+                options.max_gap_days,
+                &user_id,
+                options.max_per_opponent,
+                style,
+                &mut writer,
+            )?;
+        } else {
+            let matches = aggregator.matches().iter();
+            for (m, omitted) in
+                output::limit_per_opponent(matches, &user_id, options.max_per_opponent)
+            {
+                output::write_limited(&mut writer, m, &style.format(m), omitted, &user_id, style)?;
+            }
+        }
+    }
+    writer.finish()?;
+    finish_search(summary, &matched)
 }
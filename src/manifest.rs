@@ -0,0 +1,105 @@
+/// A record of how a search was run, to accompany exported results so they can be reproduced
+/// and audited later
+use crate::bundle;
+use crate::runner::SearchSummary;
+use crate::search::{BattleSearchError, WithPath};
+use battle_tools::date;
+use serde_json::{json, Value};
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The current time in UTC, like `2021-05-01T12:34:56Z`
+fn now() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
+    date::format_timestamp(seconds as i64)
+}
+
+/// Writes the manifest for a finished search. `parameters` describes what was searched for,
+/// and `matched` lists the logs that matched, whose checksums are recorded.
+pub fn write_manifest(
+    path: &Path,
+    parameters: &Value,
+    summary: &SearchSummary,
+    matched: &[PathBuf],
+) -> Result<(), BattleSearchError> {
+    // logs inside archives can't be read on their own, so they have no checksum
+    let checksums: Vec<Value> = matched
+        .iter()
+        .map(|log| json!({"path": log, "sha256": bundle::checksum_file(log).ok()}))
+        .collect();
+    let skipped: Vec<Value> = summary
+        .skipped_directories
+        .iter()
+        .map(|(path, error)| json!({"path": path, "error": error}))
+        .collect();
+    let invalid: Vec<Value> = summary
+        .invalid_logs
+        .iter()
+        .map(|(path, problems)| json!({"path": path, "problems": problems}))
+        .collect();
+    let manifest = json!({
+        "arguments": env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "run_at": now(),
+        "parameters": parameters,
+        "from_cache": summary.from_cache,
+        "files_checked": summary.files_checked,
+        "bytes_read": summary.bytes_read,
+        "errors": {
+            "skipped_directories": skipped,
+            "failed_files": summary.failed_files,
+            "panicked_files": summary.panicked_files,
+            "incomplete_files": summary.incomplete_files,
+            "invalid_logs": invalid,
+            "abandoned_files": summary.abandoned_files,
+            "missing_rooms": summary.missing_rooms,
+        },
+        "hit_byte_limit": summary.hit_byte_limit,
+        "matches": matched.len(),
+        "matched_files": checksums,
+    });
+    fs::write(path, serde_json::to_string_pretty(&manifest).unwrap()).with_path(path)?;
+    Ok(())
+}
+
+/// Appends a line of JSON to the performance log at `path`, recording how fast a search read
+/// logs with `threads` worker threads and `decompress_threads` decompressor threads, so search
+/// times can be followed as archives grow and hardware changes
+pub fn append_perf_log(
+    path: &Path,
+    summary: &SearchSummary,
+    threads: u32,
+    decompress_threads: u32,
+) -> Result<(), BattleSearchError> {
+    let seconds = summary.elapsed.as_secs_f64();
+    let per_second = |count: f64| if seconds > 0.0 { count / seconds } else { 0.0 };
+    let record = json!({
+        "run_at": now(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "seconds": seconds,
+        "files_checked": summary.files_checked,
+        "bytes_read": summary.bytes_read,
+        "files_per_second": per_second(summary.files_checked as f64),
+        "megabytes_per_second": per_second(summary.bytes_read as f64 / 1_000_000.0),
+        "threads": threads,
+        "decompress_threads": decompress_threads,
+        // serde_json only takes over for the rare logs pikkr can't parse
+        "parser": "pikkr",
+    });
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_path(path)?;
+    writeln!(file, "{}", record).with_path(path)?;
+    Ok(())
+}
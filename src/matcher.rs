@@ -0,0 +1,405 @@
+/// Composable predicates deciding which of the searched user's battles are displayed
+use crate::search::{str_to_id, BattleMeta, BattleSearchError, IdPattern, Winner};
+
+pub trait Matcher: Send + Sync {
+    fn matches(&self, battle: &BattleMeta) -> bool;
+}
+
+/// Battles won by the given user
+pub struct Wins {
+    pub user_id: String,
+}
+
+impl Matcher for Wins {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.winner.player() == Some(&self.user_id)
+    }
+}
+
+/// Battles won by a user whose id fits the pattern
+pub struct PatternWins {
+    pub pattern: IdPattern,
+}
+
+impl Matcher for PatternWins {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle
+            .winner
+            .player()
+            .is_some_and(|winner| self.pattern.matches(winner))
+    }
+}
+
+/// Battles won by any of the given users, such as a user and their ids before renames, or by
+/// anyone whose id fits one of them that's a pattern
+pub fn wins(usernames: &[String]) -> Box<dyn Matcher> {
+    Box::new(Or(usernames
+        .iter()
+        .map(|name| -> Box<dyn Matcher> {
+            match IdPattern::parse(name) {
+                Some(pattern) => Box::new(PatternWins { pattern }),
+                None => Box::new(Wins {
+                    user_id: str_to_id(name),
+                }),
+            }
+        })
+        .collect()))
+}
+
+/// Battles another player won against the given users
+pub struct Losses {
+    /// Matches battles the users won, as from `wins`
+    pub wins: Box<dyn Matcher>,
+}
+
+impl Matcher for Losses {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.winner.player().is_some() && !self.wins.matches(battle)
+    }
+}
+
+/// Battles with no winner; not those whose winner is unknown
+pub struct Tie;
+
+impl Matcher for Tie {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.winner == Winner::Nobody
+    }
+}
+
+/// Battles whose log doesn't record a winner at all
+pub struct UnknownWinner;
+
+impl Matcher for UnknownWinner {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.winner == Winner::Unknown
+    }
+}
+
+/// Battles whose log doesn't record how the battle ended
+pub struct UnknownEndType;
+
+impl Matcher for UnknownEndType {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.end_type.is_none()
+    }
+}
+
+/// Battles that ended with one player forfeiting
+pub struct Forfeit;
+
+impl Matcher for Forfeit {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.end_type.as_deref() == Some("forfeit")
+    }
+}
+
+/// Battles forfeited on or before the first turn, by the log's turn counter, which is typical
+/// of win trading. Battles whose log has no turn counter never match.
+pub struct InstaForfeit;
+
+impl Matcher for InstaForfeit {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        Forfeit.matches(battle) && battle.turns.is_some_and(|turns| turns <= 1)
+    }
+}
+
+/// Battles whose log records a ladder error
+pub struct LadderError;
+
+impl Matcher for LadderError {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.has_ladder_error
+    }
+}
+
+/// Battles in randomized formats, such as Random Battles and Battle Factory
+pub struct RandomFormat;
+
+impl Matcher for RandomFormat {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle
+            .format
+            .as_deref()
+            .is_some_and(battle_tools::formats::is_randomized)
+    }
+}
+
+/// Battles the given user played in
+pub struct Player {
+    pub user_id: String,
+}
+
+impl Matcher for Player {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.p1 == self.user_id || battle.p2 == self.user_id
+    }
+}
+
+/// Battles in the given format, by id as normalized by `formats::normalize_format`
+pub struct Format {
+    pub format_id: String,
+}
+
+impl Matcher for Format {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.format.as_deref() == Some(self.format_id.as_str())
+    }
+}
+
+/// Battles with the given `endType`, such as "normal" or "forfeit"
+pub struct EndType {
+    pub end_type: String,
+}
+
+impl Matcher for EndType {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle
+            .end_type
+            .as_deref()
+            .is_some_and(|end_type| end_type.eq_ignore_ascii_case(&self.end_type))
+    }
+}
+
+/// Rated or unrated battles
+pub struct Rated(pub bool);
+
+impl Matcher for Rated {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle.rated == self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    pub fn holds<T: Ord>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// Battles whose date compares to a (possibly partial) `YYYY-MM-DD` date as given, so
+/// `>= 2021-01` includes all of January. Battles only dated by month, as in monthly archives,
+/// match if any day of their month would. Battles with unknown dates never match.
+pub struct DateCompare {
+    pub comparison: Comparison,
+    pub date: String,
+}
+
+impl Matcher for DateCompare {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        if !battle_tools::date::is_date_prefix(&battle.date) {
+            return false;
+        }
+        let len = battle.date.len().min(self.date.len());
+        let (date, bound) = (&battle.date[..len], &self.date[..len]);
+        if battle.date.len() < self.date.len() && date == bound {
+            return true;
+        }
+        self.comparison.holds(date, bound)
+    }
+}
+
+/// Battles whose number of turns compares to a number as given
+pub struct TurnsCompare {
+    pub comparison: Comparison,
+    pub turns: u32,
+}
+
+impl Matcher for TurnsCompare {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        battle
+            .turns
+            .is_some_and(|turns| self.comparison.holds(turns, self.turns))
+    }
+}
+
+/// Matches if every inner matcher does; an empty `And` matches everything.
+pub struct And(pub Vec<Box<dyn Matcher>>);
+
+impl Matcher for And {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        self.0.iter().all(|m| m.matches(battle))
+    }
+}
+
+/// Matches if any inner matcher does
+pub struct Or(pub Vec<Box<dyn Matcher>>);
+
+impl Matcher for Or {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        self.0.iter().any(|m| m.matches(battle))
+    }
+}
+
+pub struct Not(pub Box<dyn Matcher>);
+
+impl Matcher for Not {
+    fn matches(&self, battle: &BattleMeta) -> bool {
+        !self.0.matches(battle)
+    }
+}
+
+/// The names usable in filter expressions
+pub const FILTER_NAMES: &[&str] = &[
+    "wins",
+    "losses",
+    "tie",
+    "forfeit",
+    "insta-forfeit",
+    "ladder-error",
+    "random",
+    "unknown-winner",
+    "unknown-end-type",
+];
+
+fn named_matcher(name: &str, usernames: &[String]) -> Option<Box<dyn Matcher>> {
+    Some(match name {
+        "wins" | "win" => wins(usernames),
+        "losses" | "loss" => Box::new(Losses {
+            wins: wins(usernames),
+        }),
+        "tie" | "ties" => Box::new(Tie),
+        "forfeit" | "forfeits" => Box::new(Forfeit),
+        "insta-forfeit" | "insta-forfeits" => Box::new(InstaForfeit),
+        "ladder-error" => Box::new(LadderError),
+        "random" | "randoms" => Box::new(RandomFormat),
+        "unknown-winner" => Box::new(UnknownWinner),
+        "unknown-end-type" => Box::new(UnknownEndType),
+        _ => return None,
+    })
+}
+
+fn tokenize(expression: &str) -> Vec<String> {
+    expression
+        .replace('(', " ( ")
+        .replace(')', " ) ")
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Turns a token that isn't a keyword or parenthesis into a matcher
+pub type AtomParser<'a> = dyn Fn(&str) -> Result<Box<dyn Matcher>, BattleSearchError> + 'a;
+
+/// Recursive-descent parser for boolean expressions, where `not` binds tighter than `and`,
+/// which binds tighter than `or`. Keywords are case-insensitive; other tokens are turned into
+/// matchers by `atom`.
+pub struct ExpressionParser<'a> {
+    tokens: Vec<String>,
+    position: usize,
+    atom: &'a AtomParser<'a>,
+}
+
+impl<'a> ExpressionParser<'a> {
+    pub fn new(tokens: Vec<String>, atom: &'a AtomParser) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            atom,
+        }
+    }
+
+    /// Parses the whole expression
+    pub fn parse(mut self) -> Result<Box<dyn Matcher>, BattleSearchError> {
+        let matcher = self.parse_or()?;
+        match self.next() {
+            None => Ok(matcher),
+            Some(token) => Err(BattleSearchError::Filter(format!("unexpected '{}'", token))),
+        }
+    }
+
+    /// The next token, if it's the given keyword
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        self.tokens
+            .get(self.position)
+            .is_some_and(|token| token.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Box<dyn Matcher>, BattleSearchError> {
+        let mut operands = vec![self.parse_and()?];
+        while self.peek_keyword("or") {
+            self.position += 1;
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.pop().unwrap()
+        } else {
+            Box::new(Or(operands))
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Box<dyn Matcher>, BattleSearchError> {
+        let mut operands = vec![self.parse_not()?];
+        while self.peek_keyword("and") {
+            self.position += 1;
+            operands.push(self.parse_not()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.pop().unwrap()
+        } else {
+            Box::new(And(operands))
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Box<dyn Matcher>, BattleSearchError> {
+        if self.peek_keyword("not") {
+            self.position += 1;
+            return Ok(Box::new(Not(self.parse_not()?)));
+        }
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<Box<dyn Matcher>, BattleSearchError> {
+        match self.next() {
+            Some(token) if token == "(" => {
+                let inner = self.parse_or()?;
+                match self.next() {
+                    Some(token) if token == ")" => Ok(inner),
+                    _ => Err(BattleSearchError::Filter(String::from("expected ')'"))),
+                }
+            }
+            Some(token) => (self.atom)(&token),
+            None => Err(BattleSearchError::Filter(String::from(
+                "expression ended unexpectedly",
+            ))),
+        }
+    }
+}
+
+/// Parses a filter expression like "wins and not forfeit", relative to the searched users,
+/// who are counted as one: a win for any of them is a win
+pub fn parse_filter(
+    expression: &str,
+    usernames: &[String],
+) -> Result<Box<dyn Matcher>, BattleSearchError> {
+    let atom = |token: &str| {
+        named_matcher(token, usernames).ok_or_else(|| {
+            BattleSearchError::Filter(format!(
+                "unknown filter '{}' (expected one of: {})",
+                token,
+                FILTER_NAMES.join(", ")
+            ))
+        })
+    };
+    ExpressionParser::new(tokenize(expression), &atom).parse()
+}
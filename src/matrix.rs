@@ -0,0 +1,71 @@
+/// Head-to-head matrices of games played between each pair of a set of users
+use crate::search::{str_to_id, Match};
+use std::collections::HashMap;
+
+#[derive(Default, Clone, Copy)]
+struct Record {
+    games: usize,
+    wins: usize,
+    losses: usize,
+}
+
+/// Prints an NxN table where each cell is the number of games the row's user played against
+/// the column's user, with the row user's wins and losses.
+pub fn print_matrix<'a>(usernames: &[String], matches: impl Iterator<Item = &'a Match>) {
+    let ids: Vec<String> = usernames.iter().map(|name| str_to_id(name)).collect();
+    let mut records: HashMap<(usize, usize), Record> = HashMap::new();
+    for m in matches {
+        let p1 = ids.iter().position(|id| **id == *m.p1);
+        let p2 = ids.iter().position(|id| **id == *m.p2);
+        if let (Some(p1), Some(p2)) = (p1, p2) {
+            for (player, opponent) in [(p1, p2), (p2, p1)] {
+                let record = records.entry((player, opponent)).or_default();
+                record.games += 1;
+                match m.winner.player() {
+                    Some(winner) if **winner == *ids[player] => record.wins += 1,
+                    Some(winner) if **winner == *ids[opponent] => record.losses += 1,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    let cells: Vec<Vec<String>> = (0..ids.len())
+        .map(|row| {
+            (0..ids.len())
+                .map(|column| {
+                    if row == column {
+                        return String::from("-");
+                    }
+                    let record = records.get(&(row, column)).copied().unwrap_or_default();
+                    format!("{} ({}-{})", record.games, record.wins, record.losses)
+                })
+                .collect()
+        })
+        .collect();
+
+    let name_width = usernames.iter().map(|name| name.len()).max().unwrap_or(0);
+    let column_widths: Vec<usize> = (0..ids.len())
+        .map(|column| {
+            cells
+                .iter()
+                .map(|row| row[column].len())
+                .chain(std::iter::once(usernames[column].len()))
+                .max()
+                .unwrap()
+        })
+        .collect();
+
+    let mut header = " ".repeat(name_width);
+    for (name, width) in usernames.iter().zip(&column_widths) {
+        header.push_str(&format!("  {:>width$}", name, width = width));
+    }
+    println!("{}", header);
+    for (name, row) in usernames.iter().zip(&cells) {
+        let mut line = format!("{:<width$}", name, width = name_width);
+        for (cell, width) in row.iter().zip(&column_widths) {
+            line.push_str(&format!("  {:>width$}", cell, width = width));
+        }
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,79 @@
+/// Running searches in the background on a busy machine, like the live log server, without
+/// slowing down the processes it's there for
+use std::{collections::HashSet, fs, thread};
+
+/// How much to lower the process's CPU priority by, out of the 19 it can be
+#[cfg(unix)]
+const NICENESS: i32 = 10;
+/// Reading is throttled to this many megabytes per second, unless a rate is given
+pub const MAX_MBPS: f64 = 50.0;
+
+/// Lowers the CPU and I/O priority of this thread and those it starts afterwards, so it
+/// should be called before any are started
+#[cfg(unix)]
+pub fn lower_priority() {
+    // SAFETY: these only change scheduling attributes, and failing to leaves them as they were
+    unsafe {
+        if libc::setpriority(libc::PRIO_PROCESS, 0, NICENESS) != 0 {
+            eprintln!(
+                "Couldn't lower the CPU priority: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        #[cfg(target_os = "linux")]
+        {
+            // the lowest priority of the best-effort class (IOPRIO_CLASS_BE), for this process
+            // (IOPRIO_WHO_PROCESS)
+            const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+            const IOPRIO_CLASS_BE: libc::c_long = 2;
+            const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+            const LOWEST_LEVEL: libc::c_long = 7;
+            let priority = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | LOWEST_LEVEL;
+            if libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority) != 0 {
+                eprintln!(
+                    "Couldn't lower the I/O priority: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn lower_priority() {}
+
+/// How many physical cores there are, going by /proc/cpuinfo where there is one, or else the
+/// number of threads that can run at once
+fn physical_cores() -> usize {
+    let logical = thread::available_parallelism().map_or(1, usize::from);
+    let cpuinfo = match fs::read_to_string("/proc/cpuinfo") {
+        Ok(cpuinfo) => cpuinfo,
+        Err(_) => return logical,
+    };
+    // each core has a core id unique within its physical package
+    let mut cores = HashSet::new();
+    let mut package = "";
+    for line in cpuinfo.lines() {
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        match key {
+            "physical id" => package = value,
+            "core id" => {
+                cores.insert((package, value));
+            }
+            _ => (),
+        }
+    }
+    if cores.is_empty() {
+        logical
+    } else {
+        cores.len()
+    }
+}
+
+/// The most threads to search with, which leaves a core for everything else
+pub fn max_threads() -> u32 {
+    (physical_cores().saturating_sub(1).max(1)) as u32
+}
@@ -0,0 +1,50 @@
+use crate::search::{BattleSearchError, Match, WithPath};
+/// Notes on particular battles, kept between searches over the course of an investigation and
+/// shown with the battles when they're found again
+use battle_tools::formats;
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+pub struct Notes {
+    /// Notes by room id, as in `gen8ou-1234`
+    notes: HashMap<String, Arc<str>>,
+}
+
+impl Notes {
+    /// Loads notes written one per line as a room and then the note, like
+    /// `gen8ou-1234 confirmed wintrade`. Rooms can be given as in `--rooms-file`, and several
+    /// notes on the same room are joined. Blank lines and lines starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Self, BattleSearchError> {
+        let contents = fs::read_to_string(path).with_path(path)?;
+        let mut notes: HashMap<String, Vec<&str>> = HashMap::new();
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (room, note) = match line.split_once(char::is_whitespace) {
+                Some((room, note)) if !note.trim().is_empty() => (room, note.trim()),
+                _ => {
+                    return Err(BattleSearchError::Path(format!(
+                        "line {} has a room but no note",
+                        idx + 1
+                    ))
+                    .at(path))
+                }
+            };
+            let room = formats::strip_room(room.rsplit('/').next().unwrap_or(""));
+            notes.entry(String::from(room)).or_default().push(note);
+        }
+        Ok(Self {
+            notes: notes
+                .into_iter()
+                .map(|(room, notes)| (room, Arc::from(notes.join("; "))))
+                .collect(),
+        })
+    }
+
+    /// Adds the note on a match's room to it, if there is one
+    pub fn annotate(&self, mut m: Match) -> Match {
+        m.note = self.notes.get(&m.room).cloned();
+        m
+    }
+}
@@ -0,0 +1,678 @@
+use crate::intern::Interner;
+use crate::search::{LadderStanding, Match, PublicReplay, Winner};
+use crate::stats;
+/// Aggregation of matches before they're printed
+use battle_tools::date;
+use battle_tools::formats::room_number;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    ptr,
+    str::FromStr,
+    sync::Arc,
+};
+
+/// Collects matches for output modes that need to see all of them at once.
+/// Dates and player ids are interned, since they repeat across huge numbers of matches.
+#[derive(Default)]
+pub struct Aggregator {
+    interner: Interner,
+    matches: Vec<Match>,
+    /// When keeping only one match per date, where each date's match is in `matches`
+    dates: Option<HashMap<Arc<str>, usize>>,
+    /// The dates each room was found under, and where, to spot rooms found under several.
+    /// Rooms are keyed along with their archive's source label, since each server numbers
+    /// its rooms separately.
+    rooms: HashMap<(Option<Arc<str>>, String), Vec<Sighting>>,
+}
+
+/// A date a room was found under, and where
+pub type Sighting = (Arc<str>, PathBuf);
+
+impl Aggregator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps only the earliest match from each date (see `ended_before`), for establishing
+    /// which days a user was active on without listing every game
+    pub fn one_per_day() -> Self {
+        Self {
+            dates: Some(HashMap::new()),
+            ..Self::default()
+        }
+    }
+
+    pub fn add(&mut self, mut m: Match) {
+        m.date = self.interner.intern(&m.date);
+        m.p1 = self.interner.intern(&m.p1);
+        m.p2 = self.interner.intern(&m.p2);
+        m.winner = m.winner.map(|winner| self.interner.intern(&winner));
+        m.p1_name = self.interner.intern(&m.p1_name);
+        m.p2_name = self.interner.intern(&m.p2_name);
+        m.p3 = m.p3.map(|p3| self.interner.intern(&p3));
+        m.p4 = m.p4.map(|p4| self.interner.intern(&p4));
+        m.p3_name = m.p3_name.map(|p3_name| self.interner.intern(&p3_name));
+        m.p4_name = m.p4_name.map(|p4_name| self.interner.intern(&p4_name));
+        m.winner_name = m.winner_name.map(|winner| self.interner.intern(&winner));
+        m.format = m.format.map(|format| self.interner.intern(&format));
+        let sightings = self
+            .rooms
+            .entry((m.source_label.clone(), m.room.clone()))
+            .or_default();
+        if sightings.iter().all(|(date, _)| *date != m.date) {
+            sightings.push((m.date.clone(), m.path.clone()));
+        }
+        if let Some(ref mut dates) = self.dates {
+            if let Some(&idx) = dates.get(&m.date) {
+                if ended_before(&m, &self.matches[idx]) {
+                    self.matches[idx] = m;
+                }
+                return;
+            }
+            dates.insert(m.date.clone(), self.matches.len());
+        }
+        self.matches.push(m);
+    }
+
+    pub fn matches(&self) -> &[Match] {
+        &self.matches
+    }
+
+    /// Rooms found under more than one date in the same archive, with the archive's source
+    /// label and each date and where it was found, sorted by room. A room only happens once on
+    /// a server, so this means an archive is corrupted or has duplicated backups in it.
+    pub fn room_collisions(&self) -> Vec<(Option<&str>, &str, &[Sighting])> {
+        let mut collisions: Vec<(Option<&str>, &str, &[Sighting])> = self
+            .rooms
+            .iter()
+            .filter(|(_, sightings)| sightings.len() > 1)
+            .map(|((label, room), sightings)| {
+                (label.as_deref(), room.as_str(), sightings.as_slice())
+            })
+            .collect();
+        collisions.sort_by_key(|&(label, room, _)| (room_number(room), room, label));
+        collisions
+    }
+
+    /// Sorts the matches chronologically: by when they ended, or their date if their log has
+    /// no timestamp, then by room number
+    pub fn sort(&mut self) -> &[Match] {
+        self.matches.sort_by_cached_key(|m| {
+            (
+                m.timestamp().unwrap_or_else(|| String::from(&*m.date)),
+                room_number(&m.room),
+                m.room.clone(),
+                m.source_label.clone(),
+            )
+        });
+        &self.matches
+    }
+
+    /// The matches, sorted as by `sort`
+    pub fn into_sorted(mut self) -> Vec<Match> {
+        self.sort();
+        self.matches
+    }
+}
+
+/// Warns about rooms found under more than one date, listing where each was found
+pub fn print_room_collisions(aggregator: &Aggregator) {
+    let collisions = aggregator.room_collisions();
+    if collisions.is_empty() {
+        return;
+    }
+    eprintln!(
+        "Found {} room{} under more than one date, which may mean an archive is corrupted or \
+         has duplicated backups:",
+        collisions.len(),
+        if collisions.len() == 1 { "" } else { "s" }
+    );
+    for (label, room, sightings) in collisions {
+        match label {
+            Some(label) => eprintln!("    {} (in {}):", room, label),
+            None => eprintln!("    {}:", room),
+        }
+        let mut sightings: Vec<&Sighting> = sightings.iter().collect();
+        sightings.sort();
+        for (date, path) in sightings {
+            eprintln!("        {}: {}", date, path.display());
+        }
+    }
+}
+
+/// Replaces player names with salted hashes of their ids, so results can be shared without
+/// revealing who played while still showing which games involve the same players
+pub struct UsernameHasher {
+    salt: String,
+}
+
+impl UsernameHasher {
+    pub fn new(salt: &str) -> Self {
+        Self {
+            salt: String::from(salt),
+        }
+    }
+
+    /// A hex hash of a player id; the same id always gets the same hash for the same salt.
+    pub fn hash(&self, user_id: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.salt.as_bytes())
+            .chain_update([0])
+            .chain_update(user_id.as_bytes())
+            .finalize();
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn apply(&self, mut m: Match) -> Match {
+        m.p1 = Arc::from(self.hash(&m.p1));
+        m.p2 = Arc::from(self.hash(&m.p2));
+        m.p1_name = m.p1.clone();
+        m.p2_name = m.p2.clone();
+        m.p3 = m.p3.map(|p3| Arc::from(self.hash(&p3)));
+        m.p4 = m.p4.map(|p4| Arc::from(self.hash(&p4)));
+        m.p3_name = m.p3.clone();
+        m.p4_name = m.p4.clone();
+        m.winner = m.winner.map(|winner| Arc::from(self.hash(&winner)));
+        m.winner_name = m.winner.player().cloned();
+        // a rename would give away who the hash is
+        m.p1_previous_id = None;
+        m.p2_previous_id = None;
+        m
+    }
+}
+
+/// Formats a match for display, with extra details about how the battle ended if `verbose`
+pub fn format_match(m: &Match, verbose: bool) -> String {
+    format!("{}{}", m, format_details(m, verbose))
+}
+
+/// What's shown after a match's players and outcome: verbose details and annotations
+fn format_details(m: &Match, verbose: bool) -> String {
+    let mut line = if verbose {
+        format!(" [{}]", m.verbose_details())
+    } else {
+        String::new()
+    };
+    let registrations: Vec<String> = [
+        (&m.p1_name, &m.p1_registered),
+        (&m.p2_name, &m.p2_registered),
+    ]
+    .iter()
+    .filter_map(|(name, registered)| {
+        let registered = registered.as_ref()?;
+        Some(match registration_age(&m.date, registered) {
+            Some(days) => format!("{} registered {}, {} days before", name, registered, days),
+            None => format!("{} registered {}", name, registered),
+        })
+    })
+    .collect();
+    let renames: Vec<String> = [
+        (&m.p1_name, &m.p1_previous_id, &m.p1),
+        (&m.p2_name, &m.p2_previous_id, &m.p2),
+    ]
+    .iter()
+    .filter(|(_, previous, _)| previous.is_some())
+    .map(|(name, _, current)| format!("{} is now {}", name, current))
+    .collect();
+    if !renames.is_empty() {
+        line.push_str(&format!(" ({})", renames.join("; ")));
+    }
+    if !registrations.is_empty() {
+        line.push_str(&format!(" ({})", registrations.join("; ")));
+    }
+    let standings: Vec<String> = [(&m.p1_name, m.p1_ladder), (&m.p2_name, m.p2_ladder)]
+        .iter()
+        .filter_map(|(name, standing)| match (*standing)? {
+            LadderStanding::Above(percentile) => Some(format!(
+                "{} is rated above {}% of the ladder snapshot",
+                name, percentile
+            )),
+            LadderStanding::Unranked => Some(format!("{} isn't in the ladder snapshot", name)),
+        })
+        .collect();
+    if !standings.is_empty() {
+        line.push_str(&format!(" ({})", standings.join("; ")));
+    }
+    if !m.inconsistencies.is_empty() {
+        line.push_str(&format!(
+            " [INCONSISTENT: {}]",
+            m.inconsistencies.join("; ")
+        ));
+    }
+    if let Some(ref label) = m.source_label {
+        line.push_str(&format!(" [from {}]", label));
+    }
+    if let Some(ref note) = m.note {
+        line.push_str(&format!(" [note: {}]", note));
+    }
+    match m.public_replay {
+        Some(PublicReplay::Uploaded) => line.push_str(" [public replay]"),
+        Some(PublicReplay::ReplayOnly) => line.push_str(" [public replay; no log found]"),
+        None => (),
+    }
+    line
+}
+
+/// The version of `--porcelain` output, which is the first field of every line. Lines of a
+/// version never change, so a new field or different formatting means a new version.
+pub const PORCELAIN_VERSION: &str = "v1";
+
+/// Escapes a porcelain field so it can't contain tabs or line breaks
+fn porcelain_field(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Formats a match for scripts, as tab-separated fields:
+///
+/// 1. the porcelain version, `v1`
+/// 2. the date, usually `YYYY-MM-DD`
+/// 3. the room, like `gen8ou-1234`
+/// 4. p1's id
+/// 5. p2's id
+/// 6. the winner's id; `-` if there was no winner, or `?` if the log doesn't say
+/// 7. the end type, like `normal` or `forfeit`; empty if the log doesn't say
+/// 8. the format id, like `gen8ou`; empty if it's unknown
+/// 9. `s` if the searched user only spectated, otherwise `p`
+/// 10. the path of the log
+///
+/// Backslashes, tabs, and line breaks within fields are escaped as `\\`, `\t`, `\n`, and `\r`.
+pub fn format_porcelain(m: &Match) -> String {
+    let winner = match m.winner {
+        Winner::Player(ref winner) => winner,
+        Winner::Nobody => "-",
+        Winner::Unknown => "?",
+    };
+    let fields = [
+        PORCELAIN_VERSION,
+        &m.date,
+        &m.room,
+        &m.p1,
+        &m.p2,
+        winner,
+        m.end_type.as_deref().unwrap_or(""),
+        m.format.as_deref().unwrap_or(""),
+        if m.is_spectator { "s" } else { "p" },
+        &m.path.to_string_lossy(),
+    ];
+    let fields: Vec<String> = fields.iter().map(|field| porcelain_field(field)).collect();
+    fields.join("\t")
+}
+
+/// Usernames are at most 18 characters on Showdown
+const NAME_WIDTH: usize = 18;
+/// Enough for most rooms, like `<<gen8randombattle-1234567>>`
+const ROOM_WIDTH: usize = 28;
+/// The narrowest the room and name columns get on narrow terminals
+const MIN_ROOM_WIDTH: usize = 16;
+const MIN_NAME_WIDTH: usize = 8;
+/// Room kept on each line for the outcome, like `(Annika won by forfeit)`, before columns
+/// are narrowed
+const OUTCOME_WIDTH: usize = 24;
+const ELLIPSIS: char = '…';
+
+/// Lays out human-readable matches in columns, as on a terminal
+#[derive(Debug, Clone, Copy)]
+pub struct Columns {
+    /// The terminal's width, which fields and lines are cut off to fit; `None` with `--wide`
+    pub width: Option<usize>,
+}
+
+impl Columns {
+    /// Cuts `text` off with an ellipsis if it's longer than `width` characters
+    fn truncate(text: &str, width: usize) -> String {
+        if text.chars().count() <= width {
+            return String::from(text);
+        }
+        let mut truncated: String = text.chars().take(width.saturating_sub(1)).collect();
+        truncated.push(ELLIPSIS);
+        truncated
+    }
+
+    /// A field padded to `width`, and cut off to fit it unless lines are wide
+    fn field(&self, text: &str, width: usize) -> String {
+        let text = match self.width {
+            Some(_) => Self::truncate(text, width),
+            None => String::from(text),
+        };
+        let padding = width.saturating_sub(text.chars().count());
+        format!("{}{}", text, " ".repeat(padding))
+    }
+
+    /// A room's column, cut off within its format if need be, so the room number still shows
+    fn room_field(&self, room: &str, width: usize) -> String {
+        let room = match (self.width, room.rsplit_once('-')) {
+            (Some(_), Some((format, number))) if "<<>>".len() + room.chars().count() > width => {
+                let format_width = width.saturating_sub("<<->>".len() + number.chars().count());
+                format!("{}-{}", Self::truncate(format, format_width), number)
+            }
+            _ => String::from(room),
+        };
+        self.field(&format!("<<{}>>", room), width)
+    }
+
+    /// How wide the room and name columns are, narrowed to leave room for the outcome on
+    /// narrow terminals
+    fn widths(&self, date: &str) -> (usize, usize) {
+        let width = match self.width {
+            Some(width) => width,
+            None => return (ROOM_WIDTH, NAME_WIDTH),
+        };
+        // everything on the line but the room and names
+        let rest = "() ".len() + date.chars().count() + " ".len() + " vs. ".len() + " ".len();
+        let available = width.saturating_sub(rest + OUTCOME_WIDTH);
+        let room = (available * 2 / 5).clamp(MIN_ROOM_WIDTH, ROOM_WIDTH);
+        let name = ((available.saturating_sub(room)) / 2).clamp(MIN_NAME_WIDTH, NAME_WIDTH);
+        (room, name)
+    }
+
+    /// Formats a match with its date, room, and players aligned, followed by `note`
+    fn format(&self, m: &Match, verbose: bool, note: &str) -> String {
+        let (room_width, name_width) = self.widths(&m.date);
+        let [p1_side, p2_side] = m.side_names();
+        let line = format!(
+            "({}) {} {} vs. {} {}{}{}",
+            m.date,
+            self.room_field(&m.room, room_width),
+            self.field(&p1_side, name_width),
+            self.field(&p2_side, name_width),
+            m.outcome(),
+            format_details(m, verbose),
+            note
+        );
+        match self.width {
+            Some(width) => Self::truncate(&line, width),
+            None => line,
+        }
+    }
+}
+
+/// How matches are written out
+#[derive(Debug, Clone, Copy)]
+pub enum Style {
+    /// For people; free to change between releases. Laid out in columns if there are some.
+    Human {
+        verbose: bool,
+        columns: Option<Columns>,
+        /// Whether to show when each battle ended in place of its date, if its log says
+        timestamps: bool,
+    },
+    /// For scripts; see `format_porcelain`
+    Porcelain,
+}
+
+impl Style {
+    pub fn format(self, m: &Match) -> String {
+        self.format_noted(m, "")
+    }
+
+    /// Formats a match followed by a note for people, which porcelain output leaves out
+    pub fn format_noted(self, m: &Match, note: &str) -> String {
+        if let (
+            Style::Human {
+                timestamps: true, ..
+            },
+            Some(timestamp),
+        ) = (self, m.timestamp())
+        {
+            let mut m = m.clone();
+            m.date = Arc::from(timestamp);
+            return self.format_dated(&m, note);
+        }
+        self.format_dated(m, note)
+    }
+
+    fn format_dated(self, m: &Match, note: &str) -> String {
+        match self {
+            Style::Human {
+                verbose,
+                columns: Some(columns),
+                ..
+            } => columns.format(m, verbose, note),
+            Style::Human { verbose, .. } => format!("{}{}", format_match(m, verbose), note),
+            Style::Porcelain => format_porcelain(m),
+        }
+    }
+}
+
+/// How many days before a battle an account was registered
+fn registration_age(battle_date: &str, registered: &str) -> Option<i64> {
+    Some(date::parse_date(battle_date)? - date::parse_date(registered)?)
+}
+
+/// Whether `a` ended before `b`, going by their logs' timestamps if both have one, or else by
+/// room number, which is only comparable within a format
+fn ended_before(a: &Match, b: &Match) -> bool {
+    match (a.ended_at, b.ended_at) {
+        (Some(a), Some(b)) => a < b,
+        _ => a.format == b.format && room_number(&a.room) < room_number(&b.room),
+    }
+}
+
+/// The day a match is sorted under (see `Aggregator::sort`): the day it ended, if its log's
+/// timestamp could be read, or else its date
+fn sorted_day(m: &Match) -> Option<i64> {
+    match m.ended_at {
+        Some(ended_at) => Some(ended_at.div_euclid(86400)),
+        None => date::parse_date(&m.date),
+    }
+}
+
+/// Days between two matches, by the days they're sorted under, if both are known
+pub fn gap_days(earlier: &Match, later: &Match) -> Option<i64> {
+    Some(sorted_day(later)? - sorted_day(earlier)?)
+}
+
+/// How output can be split into several files
+#[derive(Debug, Clone, Copy)]
+pub enum SplitBy {
+    Month,
+}
+
+impl FromStr for SplitBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "month" => Ok(SplitBy::Month),
+            _ => Err(format!("can't split output by '{}' (expected month)", s)),
+        }
+    }
+}
+
+/// Where formatted matches go: standard output, or one file per period
+pub enum MatchWriter {
+    Stdout,
+    Split {
+        by: SplitBy,
+        directory: PathBuf,
+        files: HashMap<String, BufWriter<File>>,
+    },
+}
+
+impl MatchWriter {
+    /// Writes matches to files in `directory`, such as `2021-05.txt`, creating it if needed
+    pub fn split(by: SplitBy, directory: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        Ok(MatchWriter::Split {
+            by,
+            directory,
+            files: HashMap::new(),
+        })
+    }
+
+    pub fn write(&mut self, m: &Match, line: &str) -> io::Result<()> {
+        match self {
+            MatchWriter::Stdout => {
+                println!("{}", line);
+                Ok(())
+            }
+            MatchWriter::Split {
+                by,
+                directory,
+                files,
+            } => {
+                let name = match by {
+                    SplitBy::Month if date::parse_date(&m.date).is_some() => {
+                        String::from(&m.date[..7])
+                    }
+                    SplitBy::Month => String::from("unknown-date"),
+                };
+                let file = match files.get_mut(&name) {
+                    Some(file) => file,
+                    None => {
+                        let path = directory.join(format!("{}.txt", name));
+                        files
+                            .entry(name)
+                            .or_insert(BufWriter::new(File::create(path)?))
+                    }
+                };
+                writeln!(file, "{}", line)
+            }
+        }
+    }
+
+    /// Flushes any files written to
+    pub fn finish(self) -> io::Result<()> {
+        if let MatchWriter::Split { files, .. } = self {
+            for (_, mut file) in files {
+                file.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The searched user's opponent's id and name in a match, as `stats::opponent` gives them;
+/// `None` if they only spectated it
+fn opponent(m: &Match, user_id: &str) -> Option<(String, String)> {
+    if m.is_spectator {
+        None
+    } else {
+        Some(stats::opponent(m, m.user_side(user_id)))
+    }
+}
+
+/// Keeps only the first `max` matches against each of the user's opponents, if given.
+/// Each kept match comes with how many were left out after it, which is only nonzero for the
+/// last match kept against an opponent.
+pub fn limit_per_opponent<'a>(
+    matches: impl Iterator<Item = &'a Match>,
+    user_id: &str,
+    max: Option<usize>,
+) -> Vec<(&'a Match, usize)> {
+    let max = match max {
+        Some(max) => max,
+        None => return matches.map(|m| (m, 0)).collect(),
+    };
+    let mut kept: Vec<(&Match, usize)> = vec![];
+    // the number of matches seen against each opponent, and where the last kept one is
+    let mut seen: HashMap<String, (usize, usize)> = HashMap::new();
+    for m in matches {
+        let opponent = match opponent(m, user_id) {
+            Some((opponent, _)) => opponent,
+            None => {
+                kept.push((m, 0));
+                continue;
+            }
+        };
+        let (count, last_kept) = seen.entry(opponent).or_insert((0, 0));
+        *count += 1;
+        if *count <= max {
+            *last_kept = kept.len();
+            kept.push((m, 0));
+        } else {
+            kept[*last_kept].1 += 1;
+        }
+    }
+    kept
+}
+
+/// The line noting how many matches against the opponent in `m` were left out
+pub fn format_omitted(m: &Match, user_id: &str, omitted: usize) -> String {
+    let name = opponent(m, user_id).map_or_else(String::new, |(_, name)| name);
+    format!(
+        "    ... and {} more game{} against {}",
+        omitted,
+        if omitted == 1 { "" } else { "s" },
+        name
+    )
+}
+
+/// Writes a match, followed by how many against the same opponent were left out, if any.
+/// Porcelain output leaves out the note, so that every line is a match.
+pub fn write_limited(
+    writer: &mut MatchWriter,
+    m: &Match,
+    line: &str,
+    omitted: usize,
+    user_id: &str,
+    style: Style,
+) -> io::Result<()> {
+    writer.write(m, line)?;
+    match style {
+        Style::Human { .. } if omitted > 0 => writer.write(m, &format_omitted(m, user_id, omitted)),
+        _ => Ok(()),
+    }
+}
+
+/// Writes matches in chronological order, annotating each with the gap since the previous match.
+///
+/// If `max_gap_days` is given, only matches within that many days of the previous or next match
+/// are written, which picks out clusters of rapid consecutive games. If `max_per_opponent` is,
+/// only that many of the earliest of those against each opponent of `user_id` are written.
+/// Gaps are measured between all the matches, including those that aren't written.
+pub fn print_sorted(
+    aggregator: Aggregator,
+    max_gap_days: Option<i64>,
+    user_id: &str,
+    max_per_opponent: Option<usize>,
+    style: Style,
+    writer: &mut MatchWriter,
+) -> io::Result<()> {
+    let sorted = aggregator.into_sorted();
+    // gaps and clusters go by all of the user's games, not just those that are written
+    let gaps: Vec<Option<i64>> = (0..sorted.len())
+        .map(|idx| {
+            idx.checked_sub(1)
+                .and_then(|prev| gap_days(&sorted[prev], &sorted[idx]))
+        })
+        .collect();
+    let in_cluster = |idx: usize| match max_gap_days {
+        Some(max_gap) => {
+            let within = |gap: Option<i64>| gap.is_some_and(|gap| gap <= max_gap);
+            within(gaps[idx]) || within(gaps.get(idx + 1).copied().flatten())
+        }
+        None => true,
+    };
+    let shown: Vec<(&Match, Option<i64>)> = sorted
+        .iter()
+        .enumerate()
+        .filter(|&(idx, _)| in_cluster(idx))
+        .map(|(idx, m)| (m, gaps[idx]))
+        .collect();
+    let matches = limit_per_opponent(shown.iter().map(|&(m, _)| m), user_id, max_per_opponent);
+    // the kept matches are in the same order as those shown
+    let mut shown = shown.into_iter();
+    for (m, omitted) in matches {
+        let gap_before = shown
+            .find(|&(shown, _)| ptr::eq(shown, m))
+            .and_then(|(_, gap)| gap);
+        let note = match gap_before {
+            None => String::new(),
+            Some(0) => String::from(" [same day as previous match]"),
+            Some(1) => String::from(" [1 day since previous match]"),
+            Some(gap) => format!(" [{} days since previous match]", gap),
+        };
+        let line = style.format_noted(m, &note);
+        write_limited(writer, m, &line, omitted, user_id, style)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use crate::search::{str_to_id, Match};
+/// Finding the times two accounts were playing at once, which one person playing both is
+/// unlikely to manage, unlike accounts whose play never overlaps
+use battle_tools::date;
+
+/// A stretch of time in seconds since 1970, from `start` up to `end`
+#[derive(Clone, Copy)]
+struct Window {
+    start: i64,
+    end: i64,
+}
+
+/// Merges the times a user was playing into sessions, given their games' end times (sorted)
+/// and how long each game is taken to have lasted
+fn sessions(ends: &[i64], game_length: i64) -> Vec<Window> {
+    let mut sessions: Vec<Window> = vec![];
+    for &end in ends {
+        let start = end - game_length;
+        match sessions.last_mut() {
+            Some(session) if start <= session.end => session.end = end,
+            _ => sessions.push(Window { start, end }),
+        }
+    }
+    sessions
+}
+
+/// The times in both lists of sessions, each of which is sorted and doesn't overlap itself
+fn intersect(a: &[Window], b: &[Window]) -> Vec<Window> {
+    let (mut a_idx, mut b_idx) = (0, 0);
+    let mut both = vec![];
+    while a_idx < a.len() && b_idx < b.len() {
+        let start = a[a_idx].start.max(b[b_idx].start);
+        let end = a[a_idx].end.min(b[b_idx].end);
+        if start < end {
+            both.push(Window { start, end });
+        }
+        if a[a_idx].end < b[b_idx].end {
+            a_idx += 1;
+        } else {
+            b_idx += 1;
+        }
+    }
+    both
+}
+
+/// How many of the games ending at `ends` (sorted) were being played during `window`
+fn games_during(ends: &[i64], window: Window, game_length: i64) -> usize {
+    let first = ends.partition_point(|&end| end <= window.start);
+    let past = ends.partition_point(|&end| end - game_length < window.end);
+    past.saturating_sub(first)
+}
+
+/// Prints the windows of time in which both users were playing, taking each game to have
+/// lasted `game_minutes` before it ended. Games between the two users are left out, since
+/// they're the same game, as are games whose log has no readable timestamp.
+pub fn print_overlap<'a>(
+    username_a: &str,
+    username_b: &str,
+    matches: impl Iterator<Item = &'a Match>,
+    game_minutes: u32,
+) {
+    let (id_a, id_b) = (str_to_id(username_a), str_to_id(username_b));
+    let (mut ends_a, mut ends_b) = (vec![], vec![]);
+    let mut untimed = 0;
+    for m in matches {
+        let players = [&*m.p1, &*m.p2];
+        let (has_a, has_b) = (players.contains(&&*id_a), players.contains(&&*id_b));
+        if has_a == has_b {
+            continue;
+        }
+        match m.ended_at {
+            Some(end) if has_a => ends_a.push(end),
+            Some(end) => ends_b.push(end),
+            None => untimed += 1,
+        }
+    }
+    ends_a.sort_unstable();
+    ends_b.sort_unstable();
+
+    let game_length = game_minutes as i64 * 60;
+    let minutes_plural = if game_minutes == 1 { "" } else { "s" };
+    let both = intersect(
+        &sessions(&ends_a, game_length),
+        &sessions(&ends_b, game_length),
+    );
+    if both.is_empty() {
+        println!(
+            "{} and {} were never playing at the same time, taking each game to last {} minute{}.",
+            username_a, username_b, game_minutes, minutes_plural
+        );
+    } else {
+        println!(
+            "Times both {} and {} were playing (in UTC), taking each game to last {} minute{}:",
+            username_a, username_b, game_minutes, minutes_plural
+        );
+        for &window in &both {
+            let games_a = games_during(&ends_a, window, game_length);
+            println!(
+                "    {} to {}: {} game{} by {}, {} by {}",
+                date::format_time(window.start),
+                date::format_time(window.end),
+                games_a,
+                if games_a == 1 { "" } else { "s" },
+                username_a,
+                games_during(&ends_b, window, game_length),
+                username_b
+            );
+        }
+        let minutes: i64 = both.iter().map(|w| w.end - w.start).sum::<i64>() / 60;
+        println!(
+            "{} window{} of overlapping play, {} minutes in all.",
+            both.len(),
+            if both.len() == 1 { "" } else { "s" },
+            minutes
+        );
+    }
+    if untimed > 0 {
+        println!(
+            "{} game{} had no readable timestamp and {} left out.",
+            untimed,
+            if untimed == 1 { "" } else { "s" },
+            if untimed == 1 { "was" } else { "were" }
+        );
+    }
+}
@@ -0,0 +1,69 @@
+/// Per-thread timing of the stages of checking logs, for `--profile`
+use std::time::{Duration, Instant};
+
+/// Where a worker thread spent its time
+#[derive(Debug, Default, Clone)]
+pub struct ThreadProfile {
+    pub files: usize,
+    /// Opening and reading log files
+    pub reading: Duration,
+    /// Parsing logs' JSON into battle metadata
+    pub parsing: Duration,
+    /// Deciding whether battles match
+    pub matching: Duration,
+    /// From the thread starting to it finishing, including waiting for files
+    pub total: Duration,
+}
+
+impl ThreadProfile {
+    /// Adds the time since `start` to `stage`, returning the current time to time the next stage.
+    pub fn record(stage: &mut Duration, start: Instant) -> Instant {
+        let now = Instant::now();
+        *stage += now - start;
+        now
+    }
+}
+
+fn seconds_and_share(stage: Duration, total: Duration) -> String {
+    let share = if total.is_zero() {
+        0.0
+    } else {
+        stage.as_secs_f64() / total.as_secs_f64() * 100.0
+    };
+    format!("{:.3}s ({:.0}%)", stage.as_secs_f64(), share)
+}
+
+/// Prints a breakdown of each thread's time to stderr
+pub fn print_profiles(profiles: &[ThreadProfile]) {
+    eprintln!("Time spent by each worker thread:");
+    for (idx, profile) in profiles.iter().enumerate() {
+        let busy = profile.reading + profile.parsing + profile.matching;
+        eprintln!(
+            "    thread {}: {} files; reading {}, parsing {}, matching {}, waiting {}",
+            idx + 1,
+            profile.files,
+            seconds_and_share(profile.reading, profile.total),
+            seconds_and_share(profile.parsing, profile.total),
+            seconds_and_share(profile.matching, profile.total),
+            seconds_and_share(profile.total.saturating_sub(busy), profile.total),
+        );
+    }
+}
+
+/// Prints the most batches that waited for each thread of a kind at once, to stderr
+pub fn print_queue_depths(kind: &str, depths: &[usize], capacity: usize) {
+    if depths.is_empty() {
+        return;
+    }
+    let depths: Vec<String> = depths
+        .iter()
+        .enumerate()
+        .map(|(idx, depth)| format!("{}: {}", idx + 1, depth))
+        .collect();
+    eprintln!(
+        "Most batches waiting for each {} thread (of {}): {}",
+        kind,
+        capacity,
+        depths.join(", ")
+    );
+}
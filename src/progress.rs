@@ -0,0 +1,95 @@
+/// Progress snapshots printed when battlesearch is sent SIGUSR1, as dd does, so a long search
+/// can be checked on without having been started with anything special
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Set by the signal handler; doing anything more there isn't safe
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn request(_signal: libc::c_int) {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Handles SIGUSR1 by asking for a snapshot, instead of exiting as it otherwise would
+#[cfg(unix)]
+pub fn listen() {
+    let handler = request as extern "C" fn(libc::c_int);
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn listen() {}
+
+/// Whether a snapshot has been asked for since this was last called
+pub fn take_request() -> bool {
+    REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// How far a search has got, shared between its threads
+pub struct Progress {
+    files: AtomicU64,
+    matches: AtomicU64,
+    /// The directory of the log each worker thread is checking
+    directories: Vec<Mutex<Option<PathBuf>>>,
+}
+
+impl Progress {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            files: AtomicU64::new(0),
+            matches: AtomicU64::new(0),
+            directories: (0..workers).map(|_| Mutex::new(None)).collect(),
+        }
+    }
+
+    /// Notes that a worker thread is checking the log at `path`
+    pub fn checking(&self, worker: usize, path: &Path) {
+        if let Some(directory) = self.directories.get(worker) {
+            let mut directory = directory.lock().unwrap_or_else(|e| e.into_inner());
+            // most logs are in the same directory as the last one
+            if directory.as_deref() != path.parent() {
+                *directory = path.parent().map(Path::to_path_buf);
+            }
+        }
+    }
+
+    pub fn checked(&self) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn matched(&self) {
+        self.matches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Prints a one-line snapshot to stderr
+    pub fn print(&self, elapsed: Duration, bytes_read: u64) {
+        let directories: Vec<String> = self
+            .directories
+            .iter()
+            .map(
+                |directory| match *directory.lock().unwrap_or_else(|e| e.into_inner()) {
+                    Some(ref directory) => directory.display().to_string(),
+                    None => String::from("idle"),
+                },
+            )
+            .collect();
+        eprintln!(
+            "After {}s: {} logs checked ({} bytes read), {} matches; threads at {}",
+            elapsed.as_secs(),
+            self.files.load(Ordering::Relaxed),
+            bytes_read,
+            self.matches.load(Ordering::Relaxed),
+            directories.join(", ")
+        );
+    }
+}
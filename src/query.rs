@@ -0,0 +1,146 @@
+use crate::matcher::{self, Comparison, ExpressionParser, Matcher};
+use crate::search::{str_to_id, BattleSearchError};
+/// A query language for searching all battles, such as
+/// `player:heartofetheria AND (winner:heartofetheria OR endtype:forfeit) AND date>=2021-01`
+///
+/// Terms are `key:value` or `key OPERATOR value`, with OPERATOR one of `=`, `<`, `<=`, `>`, `>=`;
+/// values with spaces can be quoted. Terms combine with AND, OR, NOT, and parentheses.
+use battle_tools::{date, formats};
+
+/// The keys usable in query terms
+pub const QUERY_KEYS: &[&str] = &[
+    "player", "winner", "loser", "format", "endtype", "rated", "date", "turns",
+];
+
+/// Splits a query into words and parentheses, keeping quoted text (without its quotes) together
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | ')' if !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Splits a term into its key, comparison, and value
+fn split_term(term: &str) -> Option<(&str, Comparison, &str)> {
+    let idx = term.find([':', '=', '<', '>'])?;
+    let (key, rest) = term.split_at(idx);
+    let (comparison, length) = if rest.starts_with(":") || rest.starts_with("=") {
+        (Comparison::Equal, 1)
+    } else if rest.starts_with("<=") {
+        (Comparison::LessOrEqual, 2)
+    } else if rest.starts_with(">=") {
+        (Comparison::GreaterOrEqual, 2)
+    } else if rest.starts_with('<') {
+        (Comparison::Less, 1)
+    } else {
+        (Comparison::Greater, 1)
+    };
+    Some((key, comparison, &rest[length..]))
+}
+
+fn invalid_term(term: &str, reason: &str) -> BattleSearchError {
+    BattleSearchError::Query(format!("in '{}': {}", term, reason))
+}
+
+fn parse_term(term: &str) -> Result<Box<dyn Matcher>, BattleSearchError> {
+    let (key, comparison, value) = match split_term(term) {
+        Some(parts) => parts,
+        None => {
+            return Err(invalid_term(
+                term,
+                &format!(
+                    "expected key:value, with a key from {}",
+                    QUERY_KEYS.join(", ")
+                ),
+            ))
+        }
+    };
+    let key = key.to_ascii_lowercase();
+    if comparison != Comparison::Equal && !matches!(key.as_str(), "date" | "turns") {
+        return Err(invalid_term(term, "only date and turns can be compared"));
+    }
+
+    Ok(match key.as_str() {
+        "player" => Box::new(matcher::Player {
+            user_id: str_to_id(value),
+        }),
+        "winner" => Box::new(matcher::Wins {
+            user_id: str_to_id(value),
+        }),
+        // losing a battle one played in
+        "loser" => Box::new(matcher::And(vec![
+            Box::new(matcher::Player {
+                user_id: str_to_id(value),
+            }),
+            Box::new(matcher::Losses {
+                wins: Box::new(matcher::Wins {
+                    user_id: str_to_id(value),
+                }),
+            }),
+        ])),
+        "format" => Box::new(matcher::Format {
+            format_id: formats::normalize_format(value).unwrap_or_default(),
+        }),
+        "endtype" => Box::new(matcher::EndType {
+            end_type: String::from(value),
+        }),
+        "rated" => match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" => Box::new(matcher::Rated(true)),
+            "false" | "no" => Box::new(matcher::Rated(false)),
+            _ => return Err(invalid_term(term, "rated must be true or false")),
+        },
+        "date" => {
+            if !date::is_date_prefix(value) {
+                return Err(invalid_term(
+                    term,
+                    "dates look like 2021, 2021-05, or 2021-05-01",
+                ));
+            }
+            Box::new(matcher::DateCompare {
+                comparison,
+                date: String::from(value),
+            })
+        }
+        "turns" => match value.parse() {
+            Ok(turns) => Box::new(matcher::TurnsCompare { comparison, turns }),
+            Err(_) => return Err(invalid_term(term, "turns must be a number")),
+        },
+        _ => {
+            return Err(invalid_term(
+                term,
+                &format!("unknown key (expected one of: {})", QUERY_KEYS.join(", ")),
+            ))
+        }
+    })
+}
+
+/// Parses a query into a matcher for any battle
+pub fn parse_query(query: &str) -> Result<Box<dyn Matcher>, BattleSearchError> {
+    ExpressionParser::new(tokenize(query), &parse_term)
+        .parse()
+        .map_err(|e| match e {
+            // the expression parser reports its errors as filter errors
+            BattleSearchError::Filter(message) => BattleSearchError::Query(message),
+            e => e,
+        })
+}
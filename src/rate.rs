@@ -0,0 +1,111 @@
+use crate::search::Match;
+/// Spotting stretches of play too fast for a person, which suggest a bot is playing
+use battle_tools::date;
+
+const HOUR: i64 = 60 * 60;
+
+/// A stretch of time in which every game was part of an hour with too many games
+struct BusyPeriod {
+    /// The indices of the first and last games, in order of when they ended
+    first: usize,
+    last: usize,
+    /// The most games in any one hour of the period
+    peak: usize,
+}
+
+/// For each game (by end time, sorted), how many games ended in the hour starting with it
+fn games_in_following_hour(times: &[i64]) -> Vec<usize> {
+    let mut window_end = 0;
+    times
+        .iter()
+        .enumerate()
+        .map(|(idx, &start)| {
+            window_end = window_end.max(idx);
+            while window_end < times.len() && times[window_end] < start + HOUR {
+                window_end += 1;
+            }
+            window_end - idx
+        })
+        .collect()
+}
+
+/// Merges the hours with more than `max_per_hour` games into periods, where overlapping busy
+/// hours count as one sustained period
+fn busy_periods(times: &[i64], max_per_hour: usize) -> Vec<BusyPeriod> {
+    let mut periods: Vec<BusyPeriod> = vec![];
+    for (idx, count) in games_in_following_hour(times).into_iter().enumerate() {
+        if count <= max_per_hour {
+            continue;
+        }
+        let last = idx + count - 1;
+        match periods.last_mut() {
+            Some(period) if idx <= period.last => {
+                period.last = period.last.max(last);
+                period.peak = period.peak.max(count);
+            }
+            _ => periods.push(BusyPeriod {
+                first: idx,
+                last,
+                peak: count,
+            }),
+        }
+    }
+    periods
+}
+
+/// Prints the periods in which the searched user played more than `max_per_hour` games in an
+/// hour, going by when each game ended. Games whose log has no readable timestamp are left out.
+pub fn print_rate_of_play<'a>(matches: impl Iterator<Item = &'a Match>, max_per_hour: usize) {
+    let mut untimed = 0;
+    let mut times: Vec<i64> = matches
+        .filter_map(|m| {
+            if m.ended_at.is_none() {
+                untimed += 1;
+            }
+            m.ended_at
+        })
+        .collect();
+    times.sort_unstable();
+
+    let periods = busy_periods(&times, max_per_hour);
+    if periods.is_empty() {
+        let busiest = games_in_following_hour(&times)
+            .into_iter()
+            .enumerate()
+            // the earliest of the busiest hours
+            .max_by(|(a_idx, a), (b_idx, b)| a.cmp(b).then(b_idx.cmp(a_idx)));
+        match busiest {
+            Some((idx, count)) => println!(
+                "No hour had more than {} games; the busiest had {}, starting {} UTC.",
+                max_per_hour,
+                count,
+                date::format_time(times[idx])
+            ),
+            None => println!("No games with timestamps found."),
+        }
+    } else {
+        println!(
+            "Periods with more than {} games in an hour (times in UTC):",
+            max_per_hour
+        );
+        for period in &periods {
+            let (start, end) = (times[period.first], times[period.last]);
+            println!(
+                "    {} to {}: {} games over {:.1} hours, at most {} in one hour",
+                date::format_time(start),
+                date::format_time(end),
+                period.last - period.first + 1,
+                (end - start) as f64 / HOUR as f64,
+                period.peak
+            );
+        }
+    }
+    if untimed > 0 {
+        println!(
+            "{} game{} had no readable timestamp and {} left out.",
+            untimed,
+            if untimed == 1 { "" } else { "s" },
+            if untimed == 1 { "was" } else { "were" }
+        );
+    }
+}
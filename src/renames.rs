@@ -0,0 +1,87 @@
+use crate::search::{str_to_id, BattleSearchError, Match, Winner, WithPath};
+/// Showdown account renames, so that a search for a user's current name also finds games they
+/// played under names they've since left
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+pub struct Renames {
+    /// The id each old id was renamed to
+    renamed_to: HashMap<String, String>,
+}
+
+impl Renames {
+    /// Loads renames written one per line as `Old Name -> New Name`. Blank lines and lines
+    /// starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Self, BattleSearchError> {
+        let contents = fs::read_to_string(path).with_path(path)?;
+        let mut renamed_to = HashMap::new();
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (old, new) = match line.split_once("->") {
+                Some((old, new)) => (str_to_id(old), str_to_id(new)),
+                None => {
+                    return Err(BattleSearchError::Path(format!(
+                        "line {} isn't of the form 'old -> new'",
+                        idx + 1
+                    ))
+                    .at(path))
+                }
+            };
+            if !old.is_empty() && !new.is_empty() && old != new {
+                renamed_to.insert(old, new);
+            }
+        }
+        Ok(Self { renamed_to })
+    }
+
+    /// The id a user goes by now, following chains of renames
+    pub fn current_id<'a>(&'a self, user_id: &'a str) -> &'a str {
+        let mut current = user_id;
+        // a cycle would otherwise never end; give up after visiting every rename once
+        for _ in 0..self.renamed_to.len() {
+            match self.renamed_to.get(current) {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        current
+    }
+
+    /// Every id that was later renamed to `user_id`, directly or through other names,
+    /// sorted so they're the same from run to run
+    pub fn previous_ids(&self, user_id: &str) -> Vec<String> {
+        let current = self.current_id(user_id);
+        let mut previous: Vec<String> = self
+            .renamed_to
+            .keys()
+            .filter(|&old| old != current && self.current_id(old) == current)
+            .cloned()
+            .collect();
+        previous.sort();
+        previous
+    }
+
+    /// Replaces old player ids in a match with current ones, so games under different names
+    /// count as the same player's. The names shown stay as they were in the battle.
+    pub fn apply(&self, mut m: Match) -> Match {
+        let current = |id: &Arc<str>| -> Option<Arc<str>> {
+            match self.current_id(id) {
+                current if current == &**id => None,
+                current => Some(Arc::from(current)),
+            }
+        };
+        if let Some(p1) = current(&m.p1) {
+            m.p1_previous_id = Some(std::mem::replace(&mut m.p1, p1));
+        }
+        if let Some(p2) = current(&m.p2) {
+            m.p2_previous_id = Some(std::mem::replace(&mut m.p2, p2));
+        }
+        let winner = m.winner.player().and_then(current);
+        if let Some(winner) = winner {
+            m.winner = Winner::Player(winner);
+        }
+        m
+    }
+}
@@ -0,0 +1,130 @@
+use crate::search::{str_to_id, BattleSearchError, Match, PublicReplay};
+/// Public replays from replay.pokemonshowdown.com, for flagging which battles found in logs
+/// have been uploaded, and listing uploaded battles whose logs weren't found
+use battle_tools::{date, layout::DateRange};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+#[cfg(feature = "online")]
+use std::{io::Read, time::Duration};
+
+/// A replay as listed by the replay site's search API
+#[derive(Deserialize)]
+struct ReplayResult {
+    /// The battle's room, like `gen8ou-1234`
+    id: String,
+    uploadtime: i64,
+    format: String,
+    players: Vec<String>,
+}
+
+pub struct Replays {
+    /// Replays by room
+    replays: HashMap<String, ReplayResult>,
+}
+
+#[cfg(feature = "online")]
+impl Replays {
+    const SEARCH_URL: &'static str = "https://replay.pokemonshowdown.com/search.json";
+    /// How many replays the site lists at a time; it lists one more if there are more to come
+    const PAGE_SIZE: usize = 50;
+    /// Stops paging through a user's replays after this many pages
+    const MAX_PAGES: usize = 100;
+
+    /// Fetches a page of replays, uploaded before `before` if it's given
+    fn fetch_page(
+        user_id: &str,
+        before: Option<i64>,
+    ) -> Result<Vec<ReplayResult>, BattleSearchError> {
+        let mut url = format!("{}?user={}", Self::SEARCH_URL, user_id);
+        if let Some(before) = before {
+            url.push_str(&format!("&before={}", before));
+        }
+        let mut body = vec![];
+        ureq::get(&url)
+            .timeout(Duration::from_secs(30))
+            .call()
+            // the error names the URL
+            .map_err(|e| BattleSearchError::Path(format!("couldn't search replays: {}", e)))?
+            .into_reader()
+            .read_to_end(&mut body)?;
+        // responses may start with `]`, to keep them from being run as scripts
+        let body = body.strip_prefix(b"]").unwrap_or(&body);
+        serde_json::from_slice(body)
+            .map_err(|e| BattleSearchError::FaultyJSON(format!("replays from {}: {}", url, e)))
+    }
+
+    /// Fetches every public replay of a user's battles
+    pub fn fetch(username: &str) -> Result<Self, BattleSearchError> {
+        let user_id = str_to_id(username);
+        let mut replays = HashMap::new();
+        let mut before = None;
+        for _ in 0..Self::MAX_PAGES {
+            let mut page = Self::fetch_page(&user_id, before)?;
+            let more = page.len() > Self::PAGE_SIZE;
+            page.truncate(Self::PAGE_SIZE);
+            before = page.last().map(|replay| replay.uploadtime);
+            replays.extend(page.into_iter().map(|replay| (replay.id.clone(), replay)));
+            if !more {
+                break;
+            }
+        }
+        Ok(Self { replays })
+    }
+}
+
+#[cfg(not(feature = "online"))]
+impl Replays {
+    pub fn fetch(_username: &str) -> Result<Self, BattleSearchError> {
+        Err(BattleSearchError::Path(String::from(
+            "searching replays needs battlesearch to be built with the online feature",
+        )))
+    }
+}
+
+impl Replays {
+    /// Flags a match if its battle has a public replay
+    pub fn annotate(&self, mut m: Match) -> Match {
+        if self.replays.contains_key(&m.room) {
+            m.public_replay = Some(PublicReplay::Uploaded);
+        }
+        m
+    }
+
+    /// Matches for the replays uploaded in `dates` whose battles aren't among the rooms
+    /// `found`, in the order they were uploaded. They're dated by when they were uploaded, and
+    /// only have what the replay site lists, which doesn't include who won.
+    pub fn replay_only(&self, found: &HashSet<&str>, dates: &DateRange) -> Vec<Match> {
+        let mut replays: Vec<&ReplayResult> = self
+            .replays
+            .values()
+            .filter(|replay| !found.contains(replay.id.as_str()))
+            .collect();
+        replays.sort_by_key(|replay| replay.uploadtime);
+        replays
+            .into_iter()
+            .filter_map(|replay| {
+                let uploaded = date::format_date(replay.uploadtime.div_euclid(24 * 60 * 60));
+                if !dates.overlaps(&uploaded) {
+                    return None;
+                }
+                let player =
+                    |idx: usize| Arc::from(replay.players.get(idx).map_or("", |p| p.as_str()));
+                let (p1_name, p2_name): (Arc<str>, Arc<str>) = (player(0), player(1));
+                Some(Match {
+                    date: Arc::from(uploaded),
+                    room: replay.id.clone(),
+                    p1: Arc::from(str_to_id(&p1_name)),
+                    p2: Arc::from(str_to_id(&p2_name)),
+                    p1_name,
+                    p2_name,
+                    format: Some(Arc::from(str_to_id(&replay.format))),
+                    public_replay: Some(PublicReplay::ReplayOnly),
+                    ..Match::default()
+                })
+            })
+            .collect()
+    }
+}
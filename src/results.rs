@@ -0,0 +1,102 @@
+use crate::output::{self, Aggregator};
+use crate::search::{BattleSearchError, Match, WithPath};
+/// Results files from `--results-json`, for combining searches run separately, as over
+/// archives sharded across machines
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashSet, fs, path::Path, path::PathBuf, sync::Arc};
+
+/// What a results file holds: what was searched for, and what was found
+#[derive(Deserialize)]
+struct ResultsFile {
+    parameters: Value,
+    matches: Vec<Match>,
+}
+
+/// Writes a search's matches to a results file, along with `parameters`, which describes what
+/// was searched for
+pub fn write_results(
+    path: &Path,
+    parameters: &Value,
+    matches: &[Match],
+) -> Result<(), BattleSearchError> {
+    let results = json!({"parameters": parameters, "matches": matches});
+    fs::write(path, serde_json::to_vec(&results).unwrap()).with_path(path)
+}
+
+/// The parameters that should agree between searches being merged; which directories were
+/// searched is expected to differ
+fn compared_parameters(parameters: &Value) -> Value {
+    let mut parameters = parameters.clone();
+    if let Some(parameters) = parameters.as_object_mut() {
+        parameters.remove("directories");
+    }
+    parameters
+}
+
+/// Combines results files into one chronological report, counting a battle found by more
+/// than one search (by its archive's source label, room, and date) once. Prints the combined
+/// matches, or with `json`, a results file of them.
+pub fn merge_results(paths: &[PathBuf], json: bool) -> Result<(), BattleSearchError> {
+    let mut aggregator = Aggregator::new();
+    let mut seen: HashSet<(Option<Arc<str>>, String, Arc<str>)> = HashSet::new();
+    let mut duplicates = 0;
+    let mut all_parameters: Vec<(&Path, Value)> = vec![];
+    for path in paths {
+        let contents = fs::read(path).with_path(path)?;
+        let results: ResultsFile = serde_json::from_slice(&contents)
+            .map_err(|e| BattleSearchError::FaultyJSON(e.to_string()))
+            .with_path(path)?;
+        let compared = compared_parameters(&results.parameters);
+        if let Some((first_path, first)) = all_parameters.first() {
+            if compared != compared_parameters(first) {
+                eprintln!(
+                    "Warning: {} wasn't searched for the same things as {}",
+                    path.display(),
+                    first_path.display()
+                );
+            }
+        }
+        all_parameters.push((path.as_path(), results.parameters));
+        for m in results.matches {
+            if seen.insert((m.source_label.clone(), m.room.clone(), m.date.clone())) {
+                aggregator.add(m);
+            } else {
+                duplicates += 1;
+            }
+        }
+    }
+
+    output::print_room_collisions(&aggregator);
+    let matches = aggregator.into_sorted();
+    if json {
+        // the first file's parameters, with every file's directories, so the merged file can
+        // itself be merged
+        let mut parameters = all_parameters
+            .first()
+            .map_or(Value::Null, |(_, parameters)| parameters.clone());
+        let directories: Vec<&Value> = all_parameters
+            .iter()
+            .filter_map(|(_, parameters)| parameters.get("directories")?.as_array())
+            .flatten()
+            .collect();
+        if let Some(parameters) = parameters.as_object_mut() {
+            parameters.insert(String::from("directories"), json!(directories));
+        }
+        let results = json!({"parameters": parameters, "matches": matches});
+        println!("{}", results);
+        return Ok(());
+    }
+    for m in &matches {
+        println!("{}", output::format_match(m, false));
+    }
+    eprintln!(
+        "Merged {} match{} from {} file{}, leaving out {} found more than once",
+        matches.len(),
+        if matches.len() == 1 { "" } else { "es" },
+        paths.len(),
+        if paths.len() == 1 { "" } else { "s" },
+        duplicates
+    );
+    Ok(())
+}
@@ -0,0 +1,1461 @@
+/// Directory traversal and the worker threads that check logs
+use crate::checkpoint::Checkpoint;
+use crate::logcache::{CacheKey, LogCache};
+use crate::matcher::{self, Matcher};
+use crate::profile::{self, ThreadProfile};
+use crate::progress::{self, Progress};
+use crate::search::{BattleSearchError, BattleSearcher, Match, SearchState, ToSend, WithPath};
+use crate::source::{self, LogData, LogVisitor, SourceLog};
+use crate::throttle::Throttle;
+use battle_tools::formats::{self, room_number};
+use battle_tools::layout::{self, ArchiveRoot, DateRange};
+use battle_tools::IdScheme;
+use flate2::read::GzDecoder;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{self, ErrorKind, Read},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+const PIKKR_TRAINING_ROUNDS: usize = 2;
+/// How many files are sent to a worker thread at once; archives hold millions of ~2KB logs,
+/// so per-file channel messages add up.
+const BATCH_SIZE: usize = 64;
+/// How many batches can wait for each thread by default; bounding this keeps memory flat when
+/// traversal outpaces the workers, as it does on directories with millions of entries.
+pub const DEFAULT_QUEUE_DEPTH: usize = 16;
+/// How much of a log is read at once when there are `FileLimits` to check in between
+const READ_CHUNK: u64 = 1 << 20;
+/// How often to check whether a progress snapshot has been asked for, while waiting for matches
+const PROGRESS_POLL: Duration = Duration::from_millis(250);
+
+/// How many bytes of logs all the workers have read, shared between threads
+struct ByteCounter {
+    read: AtomicU64,
+    limit: Option<u64>,
+    /// Set to stop reading logs whatever the limit, as `SearchJob::cancel` is
+    cancel: Option<Arc<AtomicBool>>,
+    /// Whether any files went unread because of the limit
+    exceeded: AtomicBool,
+}
+
+impl ByteCounter {
+    fn add(&self, bytes: usize) {
+        self.read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Whether the next file should be skipped; call only when there is one.
+    fn should_stop(&self) -> bool {
+        if self
+            .cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+        {
+            return true;
+        }
+        let stop = self
+            .limit
+            .is_some_and(|limit| self.read.load(Ordering::Relaxed) >= limit);
+        if stop {
+            self.exceeded.store(true, Ordering::Relaxed);
+        }
+        stop
+    }
+}
+
+/// What the threads reading logs share: how much has been read, how fast they may read, and
+/// how to retry failed reads
+#[derive(Clone, Copy)]
+struct LogReader<'a> {
+    throttle: Option<&'a Throttle>,
+    bytes: &'a ByteCounter,
+    retry: Retry,
+    limits: FileLimits,
+}
+
+impl LogReader<'_> {
+    /// Reads a log with `read_log`, retrying transient errors and counting what was read
+    fn read(&self, path: &Path, buffer: &mut Vec<u8>) -> std::io::Result<usize> {
+        let mut delay = self.retry.delay;
+        let mut attempts = 0;
+        loop {
+            match read_log(path, buffer, self.limits) {
+                Ok(read) => {
+                    self.bytes.add(read);
+                    return Ok(read);
+                }
+                Err(e) if attempts < self.retry.retries && is_transient(&e) => {
+                    attempts += 1;
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Waits until `read` more bytes are within the bandwidth limit, if there is one
+    fn throttle(&self, read: usize) {
+        if let Some(throttle) = self.throttle {
+            throttle.consume(read);
+        }
+    }
+}
+
+/// How many batches are waiting in a thread's channel, and the most there have been
+#[derive(Default)]
+struct QueueDepth {
+    waiting: AtomicUsize,
+    max: AtomicUsize,
+}
+
+/// The sending end of a thread's channel, keeping track of how full it gets
+#[derive(Clone)]
+struct Queue {
+    sender: mpsc::SyncSender<ToSend>,
+    capacity: usize,
+    depth: Arc<QueueDepth>,
+}
+
+impl Queue {
+    /// A bounded channel holding up to `capacity` batches
+    fn new(capacity: usize) -> (Self, mpsc::Receiver<ToSend>, Arc<QueueDepth>) {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let depth = Arc::new(QueueDepth::default());
+        let queue = Self {
+            sender,
+            capacity,
+            depth: depth.clone(),
+        };
+        (queue, receiver, depth)
+    }
+
+    /// Sends a batch, blocking while the channel is full
+    fn send(&self, data: ToSend) -> Result<(), mpsc::SendError<ToSend>> {
+        // a batch blocked on a full channel isn't in it yet
+        let waiting = self.depth.waiting.fetch_add(1, Ordering::Relaxed) + 1;
+        self.depth
+            .max
+            .fetch_max(waiting.min(self.capacity), Ordering::Relaxed);
+        self.sender.send(data).inspect_err(|_| {
+            self.depth.waiting.fetch_sub(1, Ordering::Relaxed);
+        })
+    }
+}
+
+impl QueueDepth {
+    /// Notes that the receiving thread took a batch
+    fn received(&self) {
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Takes the logs sources find, handing them out to the worker threads in turn
+struct Traversal<'a> {
+    senders: &'a [Queue],
+    next_sender: usize,
+    already_processed: &'a HashSet<PathBuf>,
+    room_after: Option<u64>,
+    room_before: Option<u64>,
+    modified_since: Option<SystemTime>,
+    /// Only rooms in this list are checked, and which of them have been found
+    rooms: Option<(&'a HashSet<String>, HashSet<String>)>,
+    sample: Option<Sample>,
+    /// How many logs were sampled in each directory, out of how many, when sampling
+    sampled: HashMap<PathBuf, (usize, usize)>,
+    /// For skipping directories whose player index doesn't list the searched users
+    searcher: &'a BattleSearcher,
+    /// How many directories were skipped that way
+    pruned: usize,
+    /// The canonical paths of the roots, if symlinks may only lead to files within them
+    restricted_to: Option<Vec<PathBuf>>,
+    fail_fast: bool,
+    bytes: &'a ByteCounter,
+    /// Limits on the logs read during traversal, from archives
+    limits: FileLimits,
+    /// Logs in archives abandoned for going past `limits`
+    abandoned: Vec<PathBuf>,
+    /// Directories that couldn't be read, with why
+    skipped: Vec<(PathBuf, String)>,
+    /// Files for workers to read, all from `files_date`, waiting for a full batch
+    files: Vec<PathBuf>,
+    files_date: String,
+    /// Logs sources already read, all from `logs_date`, waiting for a full batch
+    logs: Vec<(PathBuf, Vec<u8>)>,
+    logs_date: String,
+}
+
+impl<'a> Traversal<'a> {
+    fn is_room_in_range(&self, filename: &str) -> bool {
+        if self.room_after.is_none() && self.room_before.is_none() {
+            return true;
+        }
+        match room_number(filename) {
+            Some(n) => {
+                self.room_after.is_none_or(|after| n > after)
+                    && self.room_before.is_none_or(|before| n < before)
+            }
+            None => false,
+        }
+    }
+
+    /// Whether a file is for a listed room, if only some rooms are to be checked
+    fn is_listed_room(&mut self, filename: &str) -> bool {
+        match self.rooms {
+            Some((listed, ref mut found)) => {
+                let room = formats::room_from_filename(filename);
+                if listed.contains(room) {
+                    found.insert(String::from(room));
+                    true
+                } else {
+                    false
+                }
+            }
+            None => true,
+        }
+    }
+
+    fn next_sender(&mut self) -> &Queue {
+        let sender = &self.senders[self.next_sender];
+        self.next_sender = (self.next_sender + 1) % self.senders.len();
+        sender
+    }
+
+    fn send_files(&mut self) {
+        let batch = std::mem::replace(&mut self.files, Vec::with_capacity(BATCH_SIZE));
+        let date = self.files_date.clone();
+        self.next_sender()
+            .send(ToSend::Files(batch, date))
+            .unwrap_or_else(|e| {
+                eprintln!("Error sending files to a worker thread: {}", e);
+            });
+    }
+
+    fn send_logs(&mut self) {
+        let batch = std::mem::replace(&mut self.logs, Vec::with_capacity(BATCH_SIZE));
+        let date = self.logs_date.clone();
+        self.next_sender()
+            .send(ToSend::Logs(batch, date))
+            .unwrap_or_else(|e| {
+                eprintln!("Error sending logs to a worker thread: {}", e);
+            });
+    }
+
+    /// Sends the batches that aren't full yet
+    fn flush(&mut self) {
+        if !self.files.is_empty() {
+            self.send_files();
+        }
+        if !self.logs.is_empty() {
+            self.send_logs();
+        }
+    }
+}
+
+impl LogVisitor for Traversal<'_> {
+    fn is_fresh(&self, modified: &dyn Fn() -> Option<SystemTime>) -> bool {
+        // files whose modification time can't be read are kept, so reading them reports why
+        self.modified_since
+            .is_none_or(|since| modified().is_none_or(|modified| modified >= since))
+    }
+
+    fn wants(&mut self, path: &Path) -> bool {
+        let filename = match path.file_name() {
+            Some(filename) => filename.to_string_lossy(),
+            None => return false,
+        };
+        !self.already_processed.contains(path)
+            && self.is_room_in_range(&filename)
+            && self.is_listed_room(&filename)
+    }
+
+    fn is_sampling(&self) -> bool {
+        self.sample.is_some()
+    }
+
+    fn sample_size(&mut self, directory: &Path, logs: usize) -> usize {
+        let sample = match self.sample {
+            Some(sample) => sample,
+            None => return logs,
+        };
+        let size = sample.size(logs);
+        if logs > 0 {
+            self.sampled.insert(directory.to_path_buf(), (size, logs));
+        }
+        size
+    }
+
+    fn wants_players(&mut self, players: &str) -> bool {
+        let wanted = self.searcher.may_have_played(players);
+        if !wanted {
+            self.pruned += 1;
+        }
+        wanted
+    }
+
+    /// Symlinks that can't be resolved are followed, so reading them reports why.
+    fn allows_symlink(&mut self, path: &Path) -> bool {
+        let roots = match self.restricted_to {
+            Some(ref roots) => roots,
+            None => return true,
+        };
+        match path.canonicalize() {
+            Ok(target) if !roots.iter().any(|root| target.starts_with(root)) => {
+                eprintln!(
+                    "Not reading {}, which leads outside the searched directories to {}",
+                    path.display(),
+                    target.display()
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn visit(&mut self, log: SourceLog) {
+        // batches share a date, which only varies within a directory for flat archives
+        match log.data {
+            LogData::File => {
+                if log.date != self.files_date && !self.files.is_empty() {
+                    self.send_files();
+                }
+                self.files_date = log.date;
+                self.files.push(log.path);
+                if self.files.len() == BATCH_SIZE {
+                    self.send_files();
+                }
+            }
+            LogData::Read(data) => {
+                if log.date != self.logs_date && !self.logs.is_empty() {
+                    self.send_logs();
+                }
+                self.logs_date = log.date;
+                self.logs.push((log.path, data));
+                if self.logs.len() == BATCH_SIZE {
+                    self.send_logs();
+                }
+            }
+        }
+    }
+
+    fn count_read(&mut self, bytes: usize) {
+        self.bytes.add(bytes);
+    }
+
+    fn file_limits(&self) -> FileLimits {
+        self.limits
+    }
+
+    fn abandon(&mut self, path: &Path, reason: &str) {
+        eprintln!("Abandoned {}: {}", path.display(), reason);
+        self.abandoned.push(path.to_path_buf());
+    }
+
+    fn should_stop(&self) -> bool {
+        self.bytes.should_stop()
+    }
+
+    /// Unless failing fast, the error is logged and the path skipped.
+    fn skip(&mut self, path: &Path, error: BattleSearchError) -> Result<(), BattleSearchError> {
+        if self.fail_fast {
+            return Err(error.at(path));
+        }
+        eprintln!("Skipping {}: {}", path.display(), error);
+        self.skipped.push((path.to_path_buf(), error.to_string()));
+        Ok(())
+    }
+
+    fn skip_too_long(&mut self, path: &Path) {
+        eprintln!(
+            "Skipping {}, whose path is too long to open",
+            path.display()
+        );
+        self.skipped.push((
+            path.to_path_buf(),
+            String::from("its path is too long to open"),
+        ));
+    }
+}
+
+/// How many times to retry reading a log after an I/O error that may not happen again, as on a
+/// network filesystem, and how long to wait before the first retry. Each wait doubles the last.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retry {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+/// Limits on a single log, past which it's abandoned rather than letting one pathological file
+/// stall a worker thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileLimits {
+    /// How long reading and decompressing a log may take; parsing it isn't interrupted
+    pub timeout: Option<Duration>,
+    /// The most bytes a log may have, on disk or once decompressed
+    pub max_size: Option<u64>,
+}
+
+/// Why a log was abandoned for going past its `FileLimits`
+#[derive(Debug)]
+struct Abandoned(String);
+
+impl fmt::Display for Abandoned {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Abandoned {}
+
+/// Whether a read failed because the log went past its `FileLimits`
+pub fn is_abandoned(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<Abandoned>())
+}
+
+impl FileLimits {
+    fn too_large(&self, max_size: u64) -> io::Error {
+        io::Error::other(Abandoned(format!("it's over {} bytes", max_size)))
+    }
+
+    /// When a log whose reading starts now must be read by
+    pub fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Reads all of `reader` into `buffer`, a chunk at a time if there are limits to check,
+    /// giving up once `deadline` passes or the data goes over `max_size`
+    pub fn read(
+        &self,
+        mut reader: impl Read,
+        buffer: &mut Vec<u8>,
+        deadline: Option<Instant>,
+    ) -> io::Result<usize> {
+        if deadline.is_none() && self.max_size.is_none() {
+            return reader.read_to_end(buffer);
+        }
+        let mut total = 0;
+        loop {
+            let chunk = match self.max_size {
+                // one byte past the limit is enough to know it's over
+                Some(max_size) => READ_CHUNK.min(max_size + 1 - total as u64),
+                None => READ_CHUNK,
+            };
+            let read = (&mut reader).take(chunk).read_to_end(buffer)?;
+            if read == 0 {
+                return Ok(total);
+            }
+            total += read;
+            if let Some(max_size) = self.max_size.filter(|&max| total as u64 > max) {
+                return Err(self.too_large(max_size));
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Err(io::Error::other(Abandoned(format!(
+                    "reading it took over {:?}",
+                    self.timeout.unwrap_or_default()
+                ))));
+            }
+        }
+    }
+}
+
+/// Whether an error reading a log could go away by reading it again
+fn is_transient(error: &std::io::Error) -> bool {
+    !is_abandoned(error)
+        && !matches!(
+            error.kind(),
+            ErrorKind::NotFound
+            | ErrorKind::PermissionDenied
+            | ErrorKind::InvalidData
+            | ErrorKind::InvalidInput
+            | ErrorKind::IsADirectory
+            // a gzipped log that's still being written
+            | ErrorKind::UnexpectedEof
+        )
+}
+
+/// Reads a log into `buffer`, decompressing it if it's gzipped (ending in `.gz`).
+/// Returns how many bytes were read from disk.
+pub fn read_log(path: &Path, buffer: &mut Vec<u8>, limits: FileLimits) -> std::io::Result<usize> {
+    buffer.clear();
+    let file = fs::File::open(path)?;
+    if let Some(max_size) = limits.max_size {
+        if file.metadata()?.len() > max_size {
+            return Err(limits.too_large(max_size));
+        }
+    }
+    let deadline = limits.deadline();
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        let mut compressed = vec![];
+        limits.read(file, &mut compressed, deadline)?;
+        limits.read(GzDecoder::new(compressed.as_slice()), buffer, deadline)?;
+        Ok(compressed.len())
+    } else {
+        limits.read(file, buffer, deadline)
+    }
+}
+
+/// Whether a log is empty or cut off, as happens when it's read while being written
+fn is_incomplete(data: &[u8]) -> bool {
+    data.iter().rev().find(|byte| !byte.is_ascii_whitespace()) != Some(&b'}')
+}
+
+/// Checks a log that's been read, unless it's incomplete
+fn check_data(
+    searcher: &BattleSearcher,
+    state: &mut SearchState,
+    data: &[u8],
+    path: &Path,
+    date: &str,
+) -> Result<Option<Match>, BattleSearchError> {
+    if is_incomplete(data) {
+        return Err(BattleSearchError::Incomplete);
+    }
+    searcher.check_log(state, data, path, date).with_path(path)
+}
+
+/// Reads and checks a single log, reusing `buffer` to avoid an allocation per file.
+/// With a cache, logs parsed before are checked without being read again.
+fn check_file(
+    searcher: &BattleSearcher,
+    state: &mut SearchState,
+    reader: LogReader,
+    log_cache: Option<&LogCache>,
+    buffer: &mut Vec<u8>,
+    path: &Path,
+    date: &str,
+) -> Result<Option<Match>, BattleSearchError> {
+    let cached = match log_cache {
+        Some(log_cache) => {
+            let key = CacheKey::new(path, &fs::metadata(path).with_path(path)?);
+            if let Some(fields) = log_cache.get(&key) {
+                return searcher
+                    .check_fields(state, &fields, path, date)
+                    .with_path(path);
+            }
+            Some((log_cache, key))
+        }
+        None => None,
+    };
+
+    let start = Instant::now();
+    let read = match reader.read(path, buffer) {
+        Ok(read) => read,
+        // a gzipped log that's still being written
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+            return Err(BattleSearchError::Incomplete)
+        }
+        Err(e) if is_abandoned(&e) => return Err(BattleSearchError::Abandoned(e.to_string())),
+        Err(e) => return Err(BattleSearchError::from(e).at(path)),
+    };
+    if let Some(ref mut profile) = state.profile {
+        ThreadProfile::record(&mut profile.reading, start);
+    }
+    reader.throttle(read);
+    match cached {
+        Some((log_cache, key)) => {
+            if is_incomplete(buffer) {
+                return Err(BattleSearchError::Incomplete);
+            }
+            let fields = Arc::new(searcher.parse_log(state, buffer).with_path(path)?);
+            log_cache.insert(key, fields.clone());
+            searcher
+                .check_fields(state, &fields, path, date)
+                .with_path(path)
+        }
+        None => check_data(searcher, state, buffer, path, date),
+    }
+}
+
+/// Runs `check` on one log, returning `None` if it panicked, so that a parser edge case
+/// costs only that log rather than the rest of the worker's share.
+fn catch_panic<F: FnOnce() -> Result<Option<Match>, BattleSearchError>>(
+    check: F,
+) -> Option<Result<Option<Match>, BattleSearchError>> {
+    panic::catch_unwind(AssertUnwindSafe(check)).ok()
+}
+
+/// Reads and decompresses batches of logs for the worker threads, which then only parse them.
+/// Handed-off batches alternate between workers, starting with `first_worker`.
+/// Returns the logs that were abandoned for going past their limits.
+fn decompress(
+    receiver: mpsc::Receiver<ToSend>,
+    depth: &QueueDepth,
+    workers: &[Queue],
+    first_worker: usize,
+    reader: LogReader,
+) -> Vec<PathBuf> {
+    let mut abandoned = vec![];
+    let mut next_worker = first_worker % workers.len();
+    for data in receiver {
+        depth.received();
+        let (paths, date) = match data {
+            ToSend::Files(paths, date) => (paths, date),
+            // from a zip archive, so there's nothing left to do but hand them on
+            logs @ ToSend::Logs(..) => {
+                workers[next_worker].send(logs).unwrap_or(());
+                next_worker = (next_worker + 1) % workers.len();
+                continue;
+            }
+            ToSend::Done => break,
+        };
+        let mut logs = Vec::with_capacity(paths.len());
+        for path in paths {
+            if reader.bytes.should_stop() {
+                break;
+            }
+            let mut buffer = vec![];
+            match reader.read(&path, &mut buffer) {
+                Ok(read) => {
+                    reader.throttle(read);
+                    logs.push((path, buffer));
+                }
+                // left empty, so the worker counts it as incomplete
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => logs.push((path, vec![])),
+                Err(e) if is_abandoned(&e) => {
+                    eprintln!("Abandoned {}: {}", path.display(), e);
+                    abandoned.push(path);
+                }
+                Err(e) => eprintln!("Error reading {}", BattleSearchError::from(e).at(&path)),
+            }
+        }
+        if !logs.is_empty() {
+            // workers only hang up if the search is being abandoned
+            workers[next_worker]
+                .send(ToSend::Logs(logs, date))
+                .unwrap_or(());
+            next_worker = (next_worker + 1) % workers.len();
+        }
+    }
+    abandoned
+}
+
+/// Reads a list of room ids, one per line, as they'd be named in log filenames.
+/// Replay URLs and `battle-` prefixes are accepted, and blank lines are ignored.
+pub fn read_room_list(path: &Path) -> Result<HashSet<String>, BattleSearchError> {
+    let contents = fs::read_to_string(path).with_path(path)?;
+    Ok(contents
+        .lines()
+        .map(|line| formats::strip_room(line.trim().rsplit('/').next().unwrap_or("")))
+        .filter(|room| !room.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// What a worker thread reports about its share of the files, apart from matches
+#[derive(Default)]
+struct WorkerReport {
+    profile: Option<ThreadProfile>,
+    /// Logs that caused a panic
+    panicked: Vec<PathBuf>,
+    /// How many logs were empty or partly written
+    incomplete: usize,
+    /// How many logs were checked, including ones that couldn't be
+    checked: u64,
+    /// How many logs couldn't be checked because of an error
+    failed: usize,
+    /// Logs that didn't conform to the schema, with how
+    invalid: Vec<(PathBuf, Vec<String>)>,
+    /// Logs abandoned for going past their limits
+    abandoned: Vec<PathBuf>,
+}
+
+/// A single search over a set of directories
+pub struct SearchJob {
+    /// The searched users; battles involving any of them are checked
+    pub usernames: Vec<String>,
+    pub wins_only: bool,
+    pub forfeits_only: bool,
+    /// Only battles forfeited on or before turn 1
+    pub insta_forfeits: bool,
+    /// Skip battles in randomized formats
+    pub no_randoms: bool,
+    /// Further restricts which battles are displayed, on top of the flags
+    pub filter: Option<Box<dyn Matcher>>,
+    /// Also report battles the user joined or chatted in without playing
+    pub include_spectators: bool,
+    /// Only report battles where the searched user used this move
+    pub move_name: Option<String>,
+    /// Only check battles whose room number is greater than this
+    pub room_after: Option<u64>,
+    /// Only check battles whose room number is less than this
+    pub room_before: Option<u64>,
+    /// Only report battles from these dates
+    pub dates: DateRange,
+    /// Only check files modified at or after this time, skipping the rest during traversal
+    pub modified_since: Option<SystemTime>,
+    /// Only check logs for these rooms (as in `gen8ou-1234`), reporting any that aren't found
+    pub rooms: Option<HashSet<String>>,
+    pub threads: u32,
+    /// Threads dedicated to reading and decompressing logs for the worker threads to check;
+    /// with none, the worker threads read logs themselves.
+    pub decompress_threads: u32,
+    /// How many batches of files can wait for each thread before traversal blocks
+    pub queue_depth: usize,
+    pub directories: Vec<ArchiveRoot>,
+    /// Files to skip, because a previous run already processed them
+    pub already_processed: HashSet<PathBuf>,
+    /// Matches found by the run being resumed, in the files in `already_processed`, which
+    /// are reported as if they had been found again
+    pub resumed_matches: Vec<Match>,
+    pub checkpoint: Option<Arc<Checkpoint>>,
+    /// Limits the aggregate read bandwidth of all worker threads
+    pub throttle: Option<Arc<Throttle>>,
+    /// Parsed logs kept from earlier searches, for worker threads reading logs themselves
+    pub log_cache: Option<Arc<LogCache>>,
+    /// Retries for logs that couldn't be read, before they count as failed
+    pub retry: Retry,
+    /// Limits past which a log is abandoned, whether it is read from disk or out of an archive
+    pub file_limits: FileLimits,
+    /// Abort the search if a directory can't be read, instead of skipping it
+    pub fail_fast: bool,
+    /// Don't read files through symlinks that lead outside `directories`
+    pub restrict_to_root: bool,
+    /// Stop reading logs once this many bytes have been read, reporting what was found so far
+    pub max_bytes: Option<u64>,
+    /// Stop reading logs once this is set, as when nothing wants the matches anymore
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Time each worker thread's reading, parsing, and matching
+    pub profile: bool,
+    /// Have worker threads yield to other processes after each batch of logs
+    pub yield_between_batches: bool,
+    /// Several queries to run in the same search; see `BattleSearcher::queries`
+    pub queries: Vec<Box<dyn Matcher>>,
+    /// Include the species on each team in matches
+    pub keep_teams: bool,
+    /// Check matches' logs against their metadata
+    pub verify: bool,
+    /// Check every log against the schema, reporting those that don't conform
+    pub validate: bool,
+    /// Only search a random sample of each directory's logs
+    pub sample: Option<Sample>,
+    /// See `BattleSearcher::min_score_diff`
+    pub min_score_diff: Option<u32>,
+    /// How players' names are converted to ids
+    pub id_scheme: IdScheme,
+    /// Match players by their display names, ignoring case, instead of by id
+    pub match_display_name: bool,
+}
+
+/// How many of each directory's logs to search, for a quick estimate of what a full search
+/// would find
+#[derive(Debug, Clone, Copy)]
+pub enum Sample {
+    /// This fraction of the logs, rounded up
+    Fraction(f64),
+    /// This many logs, or all of them in directories with fewer
+    PerDirectory(usize),
+}
+
+impl Sample {
+    fn size(self, logs: usize) -> usize {
+        match self {
+            Sample::Fraction(fraction) => ((logs as f64 * fraction).ceil() as usize).min(logs),
+            Sample::PerDirectory(count) => count.min(logs),
+        }
+    }
+}
+
+impl FromStr for Sample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(count) = s.strip_suffix("-per-dir") {
+            return match count.parse() {
+                Ok(count) if count > 0 => Ok(Sample::PerDirectory(count)),
+                _ => Err(format!("'{}' isn't a positive number of logs", count)),
+            };
+        }
+        match s.parse() {
+            Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(Sample::Fraction(fraction)),
+            _ => Err(format!(
+                "can't sample '{}' (expected a fraction like 0.1, or a count like 100-per-dir)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sample::Fraction(fraction) => write!(f, "{}", fraction),
+            Sample::PerDirectory(count) => write!(f, "{}-per-dir", count),
+        }
+    }
+}
+
+/// How much of the archive a sampled search covered, and what that suggests a full search
+/// would find
+#[derive(Debug, Default)]
+pub struct SampleReport {
+    /// How many logs were sampled, out of how many there are
+    pub sampled: usize,
+    pub total: usize,
+    /// How many matches were found in the sample
+    pub found: usize,
+    /// How many matches a full search would find, scaling each directory's up by how much of
+    /// it was sampled
+    pub estimated: f64,
+    /// How many logs were sampled in each directory, out of how many
+    directories: HashMap<PathBuf, (usize, usize)>,
+}
+
+impl SampleReport {
+    fn new(directories: HashMap<PathBuf, (usize, usize)>) -> Self {
+        let mut report = Self::default();
+        for (sampled, total) in directories.values() {
+            report.sampled += sampled;
+            report.total += total;
+        }
+        report.directories = directories;
+        report
+    }
+
+    /// Counts the matches found in each directory, estimating how many there are in all
+    fn count(&mut self, found_in: HashMap<PathBuf, usize>) {
+        for (directory, found) in found_in {
+            self.found += found;
+            // logs that weren't sampled, like those in archives, stand for themselves
+            self.estimated += match self.directories.get(&directory) {
+                Some(&(sampled, total)) => (found * total) as f64 / sampled as f64,
+                None => found as f64,
+            };
+        }
+    }
+}
+
+/// What happened during a search, apart from the matches
+#[derive(Debug, Default)]
+pub struct SearchSummary {
+    /// Directories (or entries) that couldn't be read, with why
+    pub skipped_directories: Vec<(PathBuf, String)>,
+    /// The total size of the logs read
+    pub bytes_read: u64,
+    /// Whether the search stopped early because of `max_bytes`, so the results are partial
+    pub hit_byte_limit: bool,
+    /// Where each worker thread spent its time, if profiling
+    pub thread_profiles: Vec<ThreadProfile>,
+    /// Logs that were skipped because checking them panicked
+    pub panicked_files: Vec<PathBuf>,
+    /// How many logs were checked, including any that couldn't be
+    pub files_checked: u64,
+    /// How many logs couldn't be checked because of an error, other than those that panicked
+    pub failed_files: usize,
+    /// Whether the matches came from `--cache-dir` rather than a search
+    pub from_cache: bool,
+    /// Listed rooms whose logs weren't found
+    pub missing_rooms: Vec<String>,
+    /// How many logs were skipped for being empty or partly written, as when still being written
+    pub incomplete_files: usize,
+    /// Logs that didn't conform to the schema, with how, when validating
+    pub invalid_logs: Vec<(PathBuf, Vec<String>)>,
+    /// Logs that were too large or took too long to read, going by `SearchJob::file_limits`
+    pub abandoned_files: Vec<PathBuf>,
+    /// The most batches that waited for each worker thread at once, and then each decompressor
+    /// thread, out of `queue_capacity`. Full queues mean the threads couldn't keep up with
+    /// traversal; near-empty ones mean they were waiting on it.
+    pub worker_queue_depths: Vec<usize>,
+    pub decompressor_queue_depths: Vec<usize>,
+    pub queue_capacity: usize,
+    /// How much was searched, if only a sample was
+    pub sample: Option<SampleReport>,
+    /// How many directories were skipped because their player index didn't list the
+    /// searched users
+    pub pruned_directories: usize,
+    /// How long the search took
+    pub elapsed: Duration,
+}
+
+impl SearchSummary {
+    /// Prints anything noteworthy to stderr, so it doesn't mix with matches
+    pub fn print(&self) {
+        if !self.skipped_directories.is_empty() {
+            eprintln!(
+                "Skipped {} unreadable director{}:",
+                self.skipped_directories.len(),
+                if self.skipped_directories.len() == 1 {
+                    "y"
+                } else {
+                    "ies"
+                }
+            );
+            for (path, error) in &self.skipped_directories {
+                eprintln!("    {}: {}", path.display(), error);
+            }
+        }
+        if !self.panicked_files.is_empty() {
+            eprintln!(
+                "Skipped {} log{} that caused a crash while being checked:",
+                self.panicked_files.len(),
+                if self.panicked_files.len() == 1 {
+                    ""
+                } else {
+                    "s"
+                }
+            );
+            for path in &self.panicked_files {
+                eprintln!("    {}", path.display());
+            }
+        }
+        if !self.missing_rooms.is_empty() {
+            eprintln!(
+                "Couldn't find logs for {} listed room{}:",
+                self.missing_rooms.len(),
+                if self.missing_rooms.len() == 1 {
+                    ""
+                } else {
+                    "s"
+                }
+            );
+            for room in &self.missing_rooms {
+                eprintln!("    {}", room);
+            }
+        }
+        if self.incomplete_files > 0 {
+            eprintln!(
+                "Skipped {} empty or partly-written log{}",
+                self.incomplete_files,
+                if self.incomplete_files == 1 { "" } else { "s" }
+            );
+        }
+        if !self.abandoned_files.is_empty() {
+            let count = self.abandoned_files.len();
+            eprintln!(
+                "Abandoned {} log{} that {} too large or took too long to read:",
+                count,
+                if count == 1 { "" } else { "s" },
+                if count == 1 { "was" } else { "were" }
+            );
+            for path in &self.abandoned_files {
+                eprintln!("    {}", path.display());
+            }
+        }
+        if !self.invalid_logs.is_empty() {
+            let count = self.invalid_logs.len();
+            eprintln!(
+                "Found {} log{} that {} conform to the schema:",
+                count,
+                if count == 1 { "" } else { "s" },
+                if count == 1 { "doesn't" } else { "don't" }
+            );
+            for (path, problems) in &self.invalid_logs {
+                eprintln!("    {}: {}", path.display(), problems.join("; "));
+            }
+        }
+        if let Some(ref sample) = self.sample {
+            let rate = if sample.total == 0 {
+                0.0
+            } else {
+                sample.sampled as f64 / sample.total as f64
+            };
+            eprintln!(
+                "Sampled {} of {} logs ({:.1}%), finding {} matching battle{}; \
+                 a full search would find about {:.0}",
+                sample.sampled,
+                sample.total,
+                rate * 100.0,
+                sample.found,
+                if sample.found == 1 { "" } else { "s" },
+                sample.estimated
+            );
+        }
+        if self.hit_byte_limit {
+            eprintln!(
+                "Stopped after reading {} bytes of logs because of --max-bytes; \
+                 results are partial",
+                self.bytes_read
+            );
+        }
+        if self.pruned_directories > 0 {
+            eprintln!(
+                "Skipped {} director{} whose players.txt didn't list the searched users",
+                self.pruned_directories,
+                if self.pruned_directories == 1 {
+                    "y"
+                } else {
+                    "ies"
+                }
+            );
+        }
+        if !self.thread_profiles.is_empty() {
+            profile::print_profiles(&self.thread_profiles);
+            profile::print_queue_depths("worker", &self.worker_queue_depths, self.queue_capacity);
+            profile::print_queue_depths(
+                "decompressor",
+                &self.decompressor_queue_depths,
+                self.queue_capacity,
+            );
+        }
+    }
+
+    /// Whether the results are all there are, so they can be reused
+    pub fn is_complete(&self) -> bool {
+        self.skipped_directories.is_empty()
+            && self.panicked_files.is_empty()
+            && self.incomplete_files == 0
+            && self.failed_files == 0
+            && self.abandoned_files.is_empty()
+            // they may just not have been written yet
+            && self.missing_rooms.is_empty()
+            && !self.hit_byte_limit
+            && self.sample.is_none()
+    }
+}
+
+impl SearchJob {
+    pub fn new(username: &str, directories: Vec<ArchiveRoot>) -> Self {
+        Self::new_multi(vec![String::from(username)], directories)
+    }
+
+    /// A search for battles involving any of several users
+    pub fn new_multi(usernames: Vec<String>, directories: Vec<ArchiveRoot>) -> Self {
+        Self {
+            usernames,
+            wins_only: false,
+            forfeits_only: false,
+            insta_forfeits: false,
+            no_randoms: false,
+            filter: None,
+            include_spectators: false,
+            move_name: None,
+            room_after: None,
+            room_before: None,
+            dates: DateRange::default(),
+            modified_since: None,
+            rooms: None,
+            threads: 2,
+            decompress_threads: 0,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            directories,
+            already_processed: HashSet::new(),
+            resumed_matches: vec![],
+            checkpoint: None,
+            throttle: None,
+            log_cache: None,
+            retry: Retry::default(),
+            file_limits: FileLimits::default(),
+            fail_fast: false,
+            restrict_to_root: false,
+            max_bytes: None,
+            cancel: None,
+            profile: false,
+            yield_between_batches: false,
+            queries: vec![],
+            keep_teams: false,
+            verify: false,
+            validate: false,
+            sample: None,
+            min_score_diff: None,
+            id_scheme: IdScheme::default(),
+            match_display_name: false,
+        }
+    }
+
+    /// Combines the flag-based filters and `filter` into one matcher
+    fn build_matcher(&mut self) -> Arc<dyn Matcher> {
+        let mut matchers: Vec<Box<dyn Matcher>> = vec![];
+        if self.wins_only {
+            // any of the searched users winning
+            matchers.push(matcher::wins(&self.usernames));
+        }
+        if self.forfeits_only {
+            matchers.push(Box::new(matcher::Forfeit));
+        }
+        if self.insta_forfeits {
+            matchers.push(Box::new(matcher::InstaForfeit));
+        }
+        if self.no_randoms {
+            matchers.push(Box::new(matcher::Not(Box::new(matcher::RandomFormat))));
+        }
+        if let Some(from) = self.dates.from.take() {
+            matchers.push(Box::new(matcher::DateCompare {
+                comparison: matcher::Comparison::GreaterOrEqual,
+                date: from,
+            }));
+        }
+        if let Some(until) = self.dates.until.take() {
+            matchers.push(Box::new(matcher::DateCompare {
+                comparison: matcher::Comparison::LessOrEqual,
+                date: until,
+            }));
+        }
+        if let Some(filter) = self.filter.take() {
+            matchers.push(filter);
+        }
+        Arc::new(matcher::And(matchers))
+    }
+
+    /// The searcher the worker threads share
+    pub(crate) fn build_searcher(&mut self) -> BattleSearcher {
+        let matcher = self.build_matcher();
+        let mut searcher = BattleSearcher::new(
+            &self.usernames,
+            matcher,
+            self.include_spectators,
+            self.move_name.as_deref(),
+            self.id_scheme,
+        );
+        searcher.queries = Arc::new(std::mem::take(&mut self.queries));
+        searcher.keep_teams = self.keep_teams;
+        searcher.verify = self.verify;
+        searcher.validate = self.validate;
+        searcher.min_score_diff = self.min_score_diff;
+        if self.match_display_name {
+            searcher.display_names = Some(
+                self.usernames
+                    .iter()
+                    .map(|name| name.to_lowercase())
+                    .collect(),
+            );
+        }
+        searcher
+    }
+
+    /// Runs the search, calling `on_match` on the current thread for each match as it is found.
+    pub fn run<F: FnMut(Match)>(mut self, on_match: F) -> Result<SearchSummary, BattleSearchError> {
+        let searcher = Arc::new(self.build_searcher());
+        self.run_with(searcher, on_match)
+    }
+
+    /// Runs the search as `run` does, checking logs with `searcher` instead of one built from
+    /// the job's options, which only say where and how to read logs then
+    pub(crate) fn run_with<F: FnMut(Match)>(
+        mut self,
+        searcher: Arc<BattleSearcher>,
+        mut on_match: F,
+    ) -> Result<SearchSummary, BattleSearchError> {
+        let bytes = Arc::new(ByteCounter {
+            read: AtomicU64::new(0),
+            limit: self.max_bytes,
+            cancel: self.cancel.take(),
+            exceeded: AtomicBool::new(false),
+        });
+        let started = Instant::now();
+        let progress = Arc::new(Progress::new(self.threads as usize));
+        let queue_capacity = self.queue_depth.max(1);
+        // bounded like the queues, so workers wait while matches are handled slowly, instead
+        // of piling them up
+        let (match_sender, match_receiver) = mpsc::sync_channel(queue_capacity * BATCH_SIZE);
+        let mut senders = vec![];
+        let mut worker_depths = vec![];
+        let mut decompressor_depths = vec![];
+        let mut join_handles = vec![];
+        for worker in 0..self.threads as usize {
+            let (sender, receiver, depth) = Queue::new(queue_capacity);
+            worker_depths.push(depth.clone());
+            let progress = progress.clone();
+            let searcher = searcher.clone();
+            let checkpoint = self.checkpoint.clone();
+            let throttle = self.throttle.clone();
+            let log_cache = self.log_cache.clone();
+            let retry = self.retry;
+            let file_limits = self.file_limits;
+            let bytes = bytes.clone();
+            let profile = self.profile;
+            let yield_between_batches = self.yield_between_batches;
+            let match_sender = match_sender.clone();
+            join_handles.push(thread::spawn(move || {
+                let started = Instant::now();
+                let mut state = SearchState::new(
+                    PIKKR_TRAINING_ROUNDS,
+                    if profile {
+                        Some(ThreadProfile::default())
+                    } else {
+                        None
+                    },
+                );
+                let mut report = WorkerReport::default();
+                let finish_file = |report: &mut WorkerReport,
+                                   result: Result<Option<Match>, BattleSearchError>,
+                                   path: &Path| {
+                    report.checked += 1;
+                    match result {
+                        // the receiver only hangs up if the search is being abandoned
+                        Ok(Some(m)) => {
+                            if let Some(ref checkpoint) = checkpoint {
+                                if let Err(e) = checkpoint.record(path, Some(&m)) {
+                                    eprintln!("Error writing checkpoint: {}", e);
+                                }
+                            }
+                            match_sender.send(m).unwrap_or(());
+                            return;
+                        }
+                        Ok(None) => (),
+                        // left out of the checkpoint, so resuming checks it once it's written
+                        Err(BattleSearchError::Incomplete) => {
+                            report.incomplete += 1;
+                            return;
+                        }
+                        Err(BattleSearchError::Abandoned(reason)) => {
+                            eprintln!("Abandoned {}: {}", path.display(), reason);
+                            report.abandoned.push(path.to_path_buf());
+                        }
+                        // left out of the checkpoint, so resuming retries it
+                        Err(e) => {
+                            report.failed += 1;
+                            eprintln!("Error checking {}", e);
+                            return;
+                        }
+                    }
+                    if let Some(ref checkpoint) = checkpoint {
+                        if let Err(e) = checkpoint.record(path, None) {
+                            eprintln!("Error writing checkpoint: {}", e);
+                        }
+                    }
+                };
+                let mut buffer = vec![];
+                // the channel also closes when decompressor threads feeding this one finish
+                for data in receiver {
+                    depth.received();
+                    match data {
+                        ToSend::Files(paths, date) => {
+                            for path in paths {
+                                if bytes.should_stop() {
+                                    // keep draining batches until the traversal is done
+                                    break;
+                                }
+                                progress.checking(worker, &path);
+                                let result = catch_panic(|| {
+                                    let reader = LogReader {
+                                        throttle: throttle.as_deref(),
+                                        bytes: &bytes,
+                                        retry,
+                                        limits: file_limits,
+                                    };
+                                    check_file(
+                                        &searcher,
+                                        &mut state,
+                                        reader,
+                                        log_cache.as_deref(),
+                                        &mut buffer,
+                                        &path,
+                                        &date,
+                                    )
+                                });
+                                match result {
+                                    Some(result) => finish_file(&mut report, result, &path),
+                                    None => {
+                                        // left out of the checkpoint, so resuming retries it
+                                        report.checked += 1;
+                                        report.panicked.push(path);
+                                        state.reset_parser();
+                                    }
+                                }
+                                progress.checked();
+                            }
+                        }
+                        ToSend::Logs(logs, date) => {
+                            for (path, data) in logs {
+                                progress.checking(worker, &path);
+                                let result = catch_panic(|| {
+                                    check_data(&searcher, &mut state, &data, &path, &date)
+                                });
+                                match result {
+                                    Some(result) => finish_file(&mut report, result, &path),
+                                    None => {
+                                        report.checked += 1;
+                                        report.panicked.push(path);
+                                        state.reset_parser();
+                                    }
+                                }
+                                progress.checked();
+                            }
+                        }
+                        ToSend::Done => break,
+                    }
+                    if yield_between_batches {
+                        thread::yield_now();
+                    }
+                }
+                report.invalid = state.invalid_logs;
+                report.profile = state.profile.map(|mut profile| {
+                    profile.total = started.elapsed();
+                    profile
+                });
+                report
+            }));
+            senders.push(sender);
+        }
+        // only the workers' clones should keep the match channel open
+        drop(match_sender);
+
+        if self.decompress_threads > 0 {
+            let worker_senders = std::mem::take(&mut senders);
+            for idx in 0..self.decompress_threads {
+                let (sender, receiver, depth) = Queue::new(queue_capacity);
+                decompressor_depths.push(depth.clone());
+                let worker_senders = worker_senders.clone();
+                let throttle = self.throttle.clone();
+                let retry = self.retry;
+                let file_limits = self.file_limits;
+                let bytes = bytes.clone();
+                join_handles.push(thread::spawn(move || {
+                    let abandoned = decompress(
+                        receiver,
+                        &depth,
+                        &worker_senders,
+                        idx as usize,
+                        LogReader {
+                            throttle: throttle.as_deref(),
+                            bytes: &bytes,
+                            retry,
+                            limits: file_limits,
+                        },
+                    );
+                    WorkerReport {
+                        abandoned,
+                        ..WorkerReport::default()
+                    }
+                }));
+                senders.push(sender);
+            }
+        }
+
+        // Traverse on a separate thread so matches can be handled while traversal is ongoing.
+        let directories = layout::dedupe_roots(self.directories);
+        // matches are tagged with the label of the root they were found under
+        let labels: Vec<(PathBuf, Arc<str>)> = directories
+            .iter()
+            .filter_map(|root| Some((root.path.clone(), Arc::from(root.label.as_deref()?))))
+            .collect();
+        let already_processed = self.already_processed;
+        let resumed_matches = self.resumed_matches;
+        let (room_after, room_before) = (self.room_after, self.room_before);
+        let modified_since = self.modified_since;
+        let rooms = self.rooms;
+        let sample = self.sample;
+        let fail_fast = self.fail_fast;
+        let file_limits = self.file_limits;
+        let restricted_to = if self.restrict_to_root {
+            Some(directories.iter().map(|root| root.path.clone()).collect())
+        } else {
+            None
+        };
+        let traversal_bytes = bytes.clone();
+        let traversal_searcher = searcher.clone();
+        let traversal = thread::spawn(move || -> Result<SearchSummary, BattleSearchError> {
+            let mut traversal = Traversal {
+                senders: &senders,
+                next_sender: 0,
+                already_processed: &already_processed,
+                room_after,
+                room_before,
+                modified_since,
+                rooms: rooms.as_ref().map(|rooms| (rooms, HashSet::new())),
+                sample,
+                sampled: HashMap::new(),
+                searcher: &traversal_searcher,
+                pruned: 0,
+                restricted_to,
+                fail_fast,
+                bytes: &traversal_bytes,
+                limits: file_limits,
+                abandoned: vec![],
+                skipped: vec![],
+                files: Vec::with_capacity(BATCH_SIZE),
+                files_date: String::new(),
+                logs: Vec::with_capacity(BATCH_SIZE),
+                logs_date: String::new(),
+            };
+            let mut result = Ok(());
+            for root in &directories {
+                result = source::open(root).walk(&mut traversal);
+                if result.is_err() {
+                    break;
+                }
+            }
+            traversal.flush();
+            let skipped_directories = traversal.skipped;
+            let sampled = traversal.sampled;
+            let pruned_directories = traversal.pruned;
+            let abandoned_files = traversal.abandoned;
+            let mut missing_rooms: Vec<String> = match traversal.rooms {
+                Some((listed, found)) => listed.difference(&found).cloned().collect(),
+                None => vec![],
+            };
+            missing_rooms.sort();
+            for sender in senders {
+                sender.send(ToSend::Done)?;
+            }
+            result.map(|_| SearchSummary {
+                skipped_directories,
+                missing_rooms,
+                sample: sample.map(|_| SampleReport::new(sampled)),
+                pruned_directories,
+                abandoned_files,
+                ..SearchSummary::default()
+            })
+        });
+
+        // how many matches were found in each directory, to scale up a sample's
+        let mut found_in: HashMap<PathBuf, usize> = HashMap::new();
+        let mut found = |mut m: Match| {
+            progress.matched();
+            m.source_label = labels
+                .iter()
+                .find(|(root, _)| m.path.starts_with(root))
+                .map(|(_, label)| label.clone());
+            if sample.is_some() {
+                let directory = m.path.parent().unwrap_or(Path::new(""));
+                *found_in.entry(directory.to_path_buf()).or_default() += 1;
+            }
+            on_match(m);
+        };
+        for m in resumed_matches {
+            found(m);
+        }
+        loop {
+            match match_receiver.recv_timeout(PROGRESS_POLL) {
+                Ok(m) => found(m),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if progress::take_request() {
+                progress.print(started.elapsed(), bytes.read.load(Ordering::Relaxed));
+            }
+        }
+
+        let mut summary = traversal.join()??;
+        if let Some(ref mut report) = summary.sample {
+            report.count(found_in);
+        }
+        for handle in join_handles {
+            let report = handle.join()?;
+            summary.thread_profiles.extend(report.profile);
+            summary.panicked_files.extend(report.panicked);
+            summary.incomplete_files += report.incomplete;
+            summary.files_checked += report.checked;
+            summary.failed_files += report.failed;
+            summary.invalid_logs.extend(report.invalid);
+            summary.abandoned_files.extend(report.abandoned);
+        }
+        let max_depths = |depths: Vec<Arc<QueueDepth>>| -> Vec<usize> {
+            depths
+                .iter()
+                .map(|depth| depth.max.load(Ordering::Relaxed))
+                .collect()
+        };
+        summary.worker_queue_depths = max_depths(worker_depths);
+        summary.decompressor_queue_depths = max_depths(decompressor_depths);
+        summary.queue_capacity = queue_capacity;
+        summary.bytes_read = bytes.read.load(Ordering::Relaxed);
+        summary.elapsed = started.elapsed();
+        summary.hit_byte_limit = bytes.exceeded.load(Ordering::Relaxed);
+
+        if let Some(checkpoint) = self.checkpoint {
+            checkpoint.flush()?;
+        }
+
+        Ok(summary)
+    }
+}
@@ -0,0 +1,53 @@
+/// Room for what checking a log allocates only until it's checked, like the lines of its `log`
+/// array and the ids of players compared against patterns. With the arena feature, that's a
+/// bump arena reset before each log, so searching millions of them doesn't go through the
+/// allocator for each line; without it, the heap.
+use battle_tools::IdScheme;
+use std::ops::Deref;
+
+/// A parsed `log` array, borrowing from the scratch space it was parsed into
+#[cfg(feature = "arena")]
+pub type Log<'a> = battle_tools::protocol::ArenaLog<'a>;
+#[cfg(not(feature = "arena"))]
+pub type Log<'a> = battle_tools::protocol::BattleLog;
+
+#[derive(Default)]
+pub struct Scratch {
+    #[cfg(feature = "arena")]
+    arena: bumpalo::Bump,
+}
+
+impl Scratch {
+    /// Frees everything allocated since the last reset, keeping the arena's memory for the
+    /// next log
+    pub fn reset(&mut self) {
+        #[cfg(feature = "arena")]
+        self.arena.reset();
+    }
+
+    /// Parses the raw JSON of a `log` array; `None` if it isn't an array of strings
+    #[cfg(feature = "arena")]
+    pub fn log<'a>(&'a self, json: &'a [u8]) -> Option<Log<'a>> {
+        Log::from_json(json, &self.arena)
+    }
+
+    #[cfg(not(feature = "arena"))]
+    pub fn log<'a>(&'a self, json: &'a [u8]) -> Option<Log<'a>> {
+        Log::from_json(json)
+    }
+
+    /// A raw name's id under `id_scheme`
+    #[cfg(feature = "arena")]
+    pub fn id<'a>(&'a self, id_scheme: IdScheme, name: &[u8]) -> impl Deref<Target = str> + 'a {
+        let mut id = bumpalo::collections::String::with_capacity_in(name.len(), &self.arena);
+        id_scheme
+            .write_id(&String::from_utf8_lossy(name), &mut id)
+            .unwrap();
+        id
+    }
+
+    #[cfg(not(feature = "arena"))]
+    pub fn id<'a>(&'a self, id_scheme: IdScheme, name: &[u8]) -> impl Deref<Target = str> + 'a {
+        id_scheme.to_id(&String::from_utf8_lossy(name))
+    }
+}
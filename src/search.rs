@@ -1,27 +1,82 @@
-use lazy_static::*;
-use regex::Regex;
+use crate::matcher::Matcher;
+use crate::profile::ThreadProfile;
+use crate::scratch::Scratch;
+use crate::validate;
+use battle_tools::date;
+use battle_tools::formats::{self, RoomId};
+use battle_tools::protocol::{LogEvent, PokemonIdent};
+use battle_tools::IdScheme;
+use serde::{Deserialize, Serialize};
 /// Battlesearch code for Pokémon Showdown battle logs
-use std::{any::Any, fs, path::PathBuf};
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use thiserror::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum BattleSearchError {
+    #[error("faulty JSON: {0}")]
     FaultyJSON(String),
+    #[error("invalid filter: {0}")]
+    Filter(String),
+    #[error("invalid query: {0}")]
+    Query(String),
+    #[error("{0}")]
     Path(String),
-    IO(std::io::Error),
+    #[error(transparent)]
+    IO(#[from] std::io::Error),
+    #[error("a worker thread stopped unexpectedly")]
     Thread(std::sync::mpsc::SendError<ToSend>),
+    #[error("a thread panicked")]
     Join(Box<dyn Any + Send>),
+    /// The log is empty or cut off, as when it's still being written
+    #[error("the log is empty or only partly written")]
+    Incomplete,
+    /// The log went past the search's `FileLimits`, so it wasn't checked
+    #[error("{0}")]
+    Abandoned(String),
+    /// An error relating to a particular file or directory
+    #[error("{}: {source}", path.display())]
+    AtPath {
+        path: PathBuf,
+        source: Box<BattleSearchError>,
+    },
 }
 
-pub enum ToSend {
-    File(PathBuf, String),
-    Done,
+impl BattleSearchError {
+    /// Notes the file or directory the error relates to
+    pub fn at(self, path: &Path) -> Self {
+        BattleSearchError::AtPath {
+            path: path.to_path_buf(),
+            source: Box::new(self),
+        }
+    }
+}
+
+/// Adds the relevant path to errors, as with [`BattleSearchError::at`]
+pub trait WithPath<T> {
+    fn with_path(self, path: &Path) -> Result<T, BattleSearchError>;
 }
 
-impl From<std::io::Error> for BattleSearchError {
-    fn from(err: std::io::Error) -> Self {
-        BattleSearchError::IO(err)
+impl<T, E: Into<BattleSearchError>> WithPath<T> for Result<T, E> {
+    fn with_path(self, path: &Path) -> Result<T, BattleSearchError> {
+        self.map_err(|e| e.into().at(path))
     }
 }
+
+pub enum ToSend {
+    /// A batch of files from a single directory, with the date they're from
+    Files(Vec<PathBuf>, String),
+    /// A batch of logs that have already been read (and decompressed), with their paths
+    Logs(Vec<(PathBuf, Vec<u8>)>, String),
+    Done,
+}
+
 impl From<std::sync::mpsc::SendError<ToSend>> for BattleSearchError {
     fn from(err: std::sync::mpsc::SendError<ToSend>) -> Self {
         BattleSearchError::Thread(err)
@@ -33,122 +88,1085 @@ impl From<Box<dyn Any + Send>> for BattleSearchError {
     }
 }
 
-lazy_static! {
-    static ref ID_REGEX: Regex = Regex::new(r"[^A-Za-z0-9]").unwrap();
+pub use battle_tools::str_to_id;
+
+/// A glob-style pattern for ids, like `annika*` for "annika" and its numbered alts: `*` matches
+/// any run of characters, and `?` any one character
+#[derive(Debug, Clone)]
+pub struct IdPattern {
+    /// The pattern as an id, apart from its wildcards
+    pattern: String,
+}
+
+impl IdPattern {
+    /// The pattern in a username, or `None` if it has no wildcards
+    pub fn parse(name: &str) -> Option<Self> {
+        if !name.contains(['*', '?']) {
+            return None;
+        }
+        let pattern = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '*' || *c == '?')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        Some(Self { pattern })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn matches(&self, id: &str) -> bool {
+        let (pattern, id) = (self.pattern.as_bytes(), id.as_bytes());
+        let (mut p_idx, mut id_idx) = (0, 0);
+        // where to pick up after the last `*`, if what followed it stops matching
+        let mut backtrack = None;
+        while id_idx < id.len() {
+            match pattern.get(p_idx) {
+                Some(b'*') => {
+                    p_idx += 1;
+                    backtrack = Some((p_idx, id_idx));
+                }
+                Some(&c) if c == b'?' || c == id[id_idx] => {
+                    p_idx += 1;
+                    id_idx += 1;
+                }
+                _ => match backtrack {
+                    // the `*` matches one more character
+                    Some((after_star, star_end)) => {
+                        p_idx = after_star;
+                        id_idx = star_end + 1;
+                        backtrack = Some((after_star, star_end + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        pattern[p_idx..].iter().all(|&c| c == b'*')
+    }
+}
+
+/// The ids a pattern matched in a search, with how many matching battles each played in
+pub struct PatternIds {
+    pattern: IdPattern,
+    ids: BTreeMap<String, usize>,
+}
+
+impl PatternIds {
+    pub fn new(pattern: IdPattern) -> Self {
+        Self {
+            pattern,
+            ids: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, m: &Match) {
+        let players = [Some(&m.p1), Some(&m.p2), m.p3.as_ref(), m.p4.as_ref()];
+        for player in players.iter().flatten() {
+            if self.pattern.matches(player) {
+                *self.ids.entry(player.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Prints the ids that matched to stderr, so they don't mix with matches
+    pub fn print(&self) {
+        let ids: Vec<String> = self
+            .ids
+            .iter()
+            .map(|(id, games)| {
+                format!(
+                    "{} ({} game{})",
+                    id,
+                    games,
+                    if *games == 1 { "" } else { "s" }
+                )
+            })
+            .collect();
+        eprintln!(
+            "{} matched {} id{}{}{}",
+            self.pattern.as_str(),
+            ids.len(),
+            if ids.len() == 1 { "" } else { "s" },
+            if ids.is_empty() { "" } else { ": " },
+            ids.join(", ")
+        );
+    }
 }
 
-// Taken from https://github.com/AnnikaCodes/anonbattle/blob/main/src/anonymizer.rs#L36
-// Perhaps I should share code somehow in the future; perhaps with a battle-tools library crate?
-fn str_to_id(str: &str) -> String {
-    (*ID_REGEX.replace_all(str, "")).to_lowercase()
+fn bytes_to_id(bytes: &Option<&[u8]>, id_scheme: IdScheme) -> Option<String> {
+    bytes.map(|b| id_scheme.to_id(&String::from_utf8_lossy(b)))
 }
 
-fn bytes_to_id(bytes: &Option<&[u8]>) -> Option<String> {
+/// The raw value of a JSON string, without quotes; `None` if it's missing or null
+fn bytes_to_string(bytes: &Option<&[u8]>) -> Option<String> {
     match bytes {
-        Some(b) => Some(str_to_id(&String::from_utf8_lossy(b))),
-        None => None,
+        Some(b"null") | None => None,
+        Some(b) => Some(
+            // decode escapes properly, but don't fail outright on odd values
+            serde_json::from_slice(b)
+                .unwrap_or_else(|_| String::from(String::from_utf8_lossy(b).trim_matches('"'))),
+        ),
     }
 }
 
-pub struct BattleSearcher<'a> {
-    user_id: String,
-    json_parser: pikkr_annika::Pikkr<'a>,
-    wins_only: bool,
-    forfeits_only: bool,
+/// Whether a JSON value is present and truthy as in JavaScript: not `false`, `null`, 0, or ""
+fn is_truthy(bytes: &Option<&[u8]>) -> bool {
+    !matches!(
+        bytes,
+        None | Some(b"false") | Some(b"null") | Some(b"0") | Some(b"\"\"")
+    )
 }
 
-impl<'a> BattleSearcher<'a> {
-    pub fn new(
-        username: &str,
-        pikkr_training_rounds: usize,
-        wins_only: bool,
-        forfeits_only: bool,
-    ) -> Self {
-        let json_parser = pikkr_annika::Pikkr::new(
-            &vec![
-                "$.p1".as_bytes(),      // p1 name - idx 0
-                "$.p2".as_bytes(),      // p2 name - idx 1
-                "$.winner".as_bytes(),  // winner - idx 2
-                "$.endType".as_bytes(), // end type - idx 3
-            ],
-            pikkr_training_rounds,
+/// Deserializes a JSON value; `None` if it's missing, null, or not the expected type
+fn bytes_to_value<T: serde::de::DeserializeOwned>(bytes: &Option<&[u8]>) -> Option<T> {
+    match bytes {
+        Some(b"null") | None => None,
+        Some(b) => serde_json::from_slice(b).ok(),
+    }
+}
+
+/// Whether someone `is_searched_user` joined or chatted in a battle, according to its `log`
+/// array
+fn user_spectated(log: &[u8], scratch: &Scratch, is_searched_user: impl Fn(&[u8]) -> bool) -> bool {
+    let log = match scratch.log(log) {
+        Some(log) => log,
+        None => return false,
+    };
+    let spectated = log.lines().any(|event| match event {
+        LogEvent::Chat { user, .. } | LogEvent::Join(user) => {
+            // users are shown with their rank, like "+Annika", or a space for none
+            let name = user
+                .strip_prefix(|c: char| !c.is_alphanumeric())
+                .unwrap_or(user);
+            is_searched_user(name.as_bytes())
+        }
+        _ => false,
+    });
+    spectated
+}
+
+/// Ways a battle's `log` array disagrees with its top-level `p1`, `p2`, and `winner` fields,
+/// which would suggest the log is corrupted or has been tampered with
+fn log_inconsistencies(
+    log: Option<&[u8]>,
+    scratch: &Scratch,
+    p1: &str,
+    p2: &str,
+    winner: &Winner<String>,
+    id_scheme: IdScheme,
+) -> Vec<String> {
+    let log = match log.and_then(|log| scratch.log(log)) {
+        Some(log) => log,
+        None => return vec![String::from("there is no readable log to check against")],
+    };
+    let mut inconsistencies = vec![];
+    let mut result = None;
+    for event in log.lines() {
+        match event {
+            LogEvent::Player { side, name } if !name.is_empty() => {
+                let expected = match side {
+                    "p1" => p1,
+                    "p2" => p2,
+                    _ => continue,
+                };
+                let problem = format!(
+                    "{} is {} in the log, but {} in the metadata",
+                    side, name, expected
+                );
+                if *scratch.id(id_scheme, name.as_bytes()) != *expected
+                    && !inconsistencies.contains(&problem)
+                {
+                    inconsistencies.push(problem);
+                }
+            }
+            LogEvent::Win(user) => result = Some(Winner::Player(id_scheme.to_id(user))),
+            LogEvent::Tie => result = Some(Winner::Nobody),
+            _ => (),
+        }
+    }
+    match (winner, result) {
+        // old logs don't record a winner in their metadata
+        (Winner::Unknown, _) => (),
+        (_, None) => inconsistencies.push(String::from("the log never says how the battle ended")),
+        (Winner::Player(expected), Some(Winner::Player(actual))) if *expected != actual => {
+            inconsistencies.push(format!(
+                "{} won according to the log, but {} did according to the metadata",
+                actual, expected
+            ))
+        }
+        (Winner::Player(expected), Some(Winner::Nobody)) => inconsistencies.push(format!(
+            "the log says it was a tie, but the metadata says {} won",
+            expected
+        )),
+        (Winner::Nobody, Some(Winner::Player(actual))) => inconsistencies.push(format!(
+            "{} won according to the log, but the metadata says there was no winner",
+            actual
+        )),
+        _ => (),
+    }
+    inconsistencies
+}
+
+/// How many Pokémon each player had left at the end of a battle, counted from its `log` array
+/// for logs without a `score`. `None` if the log doesn't say how many each player brought.
+fn score_from_log(log: &[u8], scratch: &Scratch) -> Option<[u32; 2]> {
+    let log = scratch.log(log)?;
+    let (mut sizes, mut fainted) = ([None; 2], [0; 2]);
+    for event in log.lines() {
+        let side = match event {
+            LogEvent::TeamSize { side, .. } | LogEvent::Faint(PokemonIdent { side, .. }) => side,
+            _ => continue,
+        };
+        let idx = match side {
+            "p1" => 0,
+            "p2" => 1,
+            _ => continue,
+        };
+        match event {
+            LogEvent::TeamSize { size, .. } => sizes[idx] = Some(size),
+            _ => fainted[idx] += 1,
+        }
+    }
+    Some([
+        sizes[0]?.saturating_sub(fainted[0]),
+        sizes[1]?.saturating_sub(fainted[1]),
+    ])
+}
+
+/// The turns on which a Pokémon on one of `sides` used the move `move_id`, according to a
+/// battle's `log` array; moves before the first turn (such as with Pursuit) are on turn 0.
+fn move_turns(log: &[u8], scratch: &Scratch, sides: &[&str], move_id: &str) -> Vec<u32> {
+    let log = match scratch.log(log) {
+        Some(log) => log,
+        None => return vec![],
+    };
+    let mut turn = 0;
+    let mut turns = vec![];
+    for event in log.lines() {
+        match event {
+            LogEvent::Turn(n) => turn = n,
+            LogEvent::Move { pokemon, name, .. }
+                if sides.contains(&pokemon.side)
+                    && *scratch.id(IdScheme::Modern, name.as_bytes()) == *move_id
+                    && turns.last() != Some(&turn) =>
+            {
+                turns.push(turn)
+            }
+            _ => (),
+        }
+    }
+    turns
+}
+
+/// Who won a battle. Old logs may not record it at all, which is distinct from a tie.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Winner<S> {
+    Player(S),
+    /// The battle ended in a tie, or without either player winning
+    Nobody,
+    /// The log has no `winner` field
+    Unknown,
+}
+
+/// Nothing is known of who won until a log says
+impl<S> Default for Winner<S> {
+    fn default() -> Self {
+        Winner::Unknown
+    }
+}
+
+impl<S> Winner<S> {
+    pub fn player(&self) -> Option<&S> {
+        match self {
+            Winner::Player(player) => Some(player),
+            _ => None,
+        }
+    }
+
+    pub fn map<T, F: FnOnce(S) -> T>(self, f: F) -> Winner<T> {
+        match self {
+            Winner::Player(player) => Winner::Player(f(player)),
+            Winner::Nobody => Winner::Nobody,
+            Winner::Unknown => Winner::Unknown,
+        }
+    }
+}
+
+/// Whether a battle has a public replay on replay.pokemonshowdown.com, from `--replays`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicReplay {
+    /// The battle's log was found, and it was uploaded
+    Uploaded,
+    /// The battle was uploaded, but its log wasn't found
+    ReplayOnly,
+}
+
+/// Where a player stood on a format's ladder, from `--ladder-snapshot`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LadderStanding {
+    /// Rated above this percentage of the players in the snapshot
+    Above(u8),
+    /// Not among the players in the snapshot
+    Unranked,
+}
+
+/// The fields of a log that are checked, in the order `check_parsed_log` expects them
+const LOG_FIELDS: [&str; 17] = [
+    "$.p1",          // p1 name - idx 0
+    "$.p2",          // p2 name - idx 1
+    "$.winner",      // winner - idx 2
+    "$.endType",     // end type - idx 3
+    "$.ladderError", // ladder error - idx 4
+    "$.inputLog",    // input log - idx 5
+    "$.log",         // log - idx 6
+    "$.format",      // format - idx 7
+    "$.rated",       // rated - idx 8
+    "$.turns",       // turns - idx 9
+    "$.timestamp",   // timestamp - idx 10
+    "$.score",       // score - idx 11
+    "$.seed",        // seed - idx 12
+    "$.p1team",      // p1 team - idx 13
+    "$.p2team",      // p2 team - idx 14
+    "$.p3",          // p3 name, in multi battles - idx 15
+    "$.p4",          // p4 name, in multi battles - idx 16
+];
+
+/// A log's `LOG_FIELDS` as raw JSON, owned so they can outlive the log's contents
+pub type ParsedFields = Vec<Option<Vec<u8>>>;
+
+/// Extracts `LOG_FIELDS` as raw JSON, like pikkr does, but with a full serde_json parse
+fn parse_with_serde(data: &[u8]) -> Result<ParsedFields, BattleSearchError> {
+    let log: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(data).map_err(|e| BattleSearchError::FaultyJSON(e.to_string()))?;
+    Ok(LOG_FIELDS
+        .iter()
+        .map(|field| {
+            log.get(field.trim_start_matches("$."))
+                .map(|value| serde_json::to_vec(value).unwrap())
+        })
+        .collect())
+}
+
+/// A Pokémon on a player's team, as recorded in `p1team`/`p2team`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PokemonSet {
+    pub species: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub item: Option<String>,
+    #[serde(default)]
+    pub ability: Option<String>,
+    #[serde(default)]
+    pub moves: Vec<String>,
+}
+
+/// What's known about a battle from its log's top-level fields, for matchers and reports.
+/// Fields not every log has are `None` when missing.
+pub struct BattleMeta {
+    /// The date from the archive's layout, usually `YYYY-MM-DD`
+    pub date: String,
+    pub p1: String,
+    pub p2: String,
+    /// The winner's id
+    pub winner: Winner<String>,
+    /// `None` if the log has no `endType`, as with old logs
+    pub end_type: Option<String>,
+    pub has_ladder_error: bool,
+    pub has_input_log: bool,
+    /// The format id, like "gen8ou", from the log or else the room name
+    pub format: Option<String>,
+    pub rated: bool,
+    pub turns: Option<u32>,
+    /// When the battle ended, as the server wrote it (a JavaScript date string)
+    pub timestamp: Option<String>,
+    /// The number of Pokémon each player had left
+    pub score: Option<Vec<u32>>,
+    /// The battle's PRNG seed, as raw JSON since its form has changed over time
+    pub seed: Option<String>,
+    pub p1_team: Option<Vec<PokemonSet>>,
+    pub p2_team: Option<Vec<PokemonSet>>,
+}
+
+/// A battle in which the searched user played and which passed all filters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Match {
+    pub date: Arc<str>,
+    pub room: String,
+    /// Player ids, which are what's matched on
+    pub p1: Arc<str>,
+    pub p2: Arc<str>,
+    pub winner: Winner<Arc<str>>,
+    /// Player names as they appear in the log, which are what's displayed
+    pub p1_name: Arc<str>,
+    pub p2_name: Arc<str>,
+    pub winner_name: Option<Arc<str>>,
+    pub is_forfeit: bool,
+    /// The raw `endType` of the battle, such as "normal" or "forfeit"; `None` if it's unknown
+    pub end_type: Option<String>,
+    /// Whether the log records a `ladderError`
+    pub has_ladder_error: bool,
+    /// Whether the log includes an `inputLog`
+    pub has_input_log: bool,
+    /// Whether the searched user only joined or chatted in the battle, rather than playing
+    pub is_spectator: bool,
+    /// When searching for a move, the turns the searched user used it on
+    #[serde(default)]
+    pub move_turns: Vec<u32>,
+    /// The format id, like "gen8ou", from the log or else the room name
+    #[serde(default)]
+    pub format: Option<Arc<str>>,
+    /// Where the log was found
+    #[serde(default)]
+    pub path: PathBuf,
+    /// When the battle ended, in seconds since 1970, if the log's `timestamp` could be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ended_at: Option<i64>,
+    /// When running several queries at once, the indices of those this matches
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queries: Vec<usize>,
+    /// The species on each player's team, kept only when `BattleSearcher::keep_teams` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub p1_team: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub p2_team: Vec<String>,
+    /// When each player's account was registered (`YYYY-MM-DD`), from `--accounts-db`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p1_registered: Option<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p2_registered: Option<Arc<str>>,
+    /// The ids players had at the time, if they've since been renamed; see `Renames::apply`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p1_previous_id: Option<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p2_previous_id: Option<Arc<str>>,
+    /// Where each player stood on the format's ladder, from `--ladder-snapshot`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p1_ladder: Option<LadderStanding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p2_ladder: Option<LadderStanding>,
+    /// Ways the log disagrees with its metadata, when `BattleSearcher::verify` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inconsistencies: Vec<String>,
+    /// The note on the battle from `--notes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<Arc<str>>,
+    /// Whether the battle has a public replay, from `--replays`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_replay: Option<PublicReplay>,
+    /// How many Pokémon p1 and p2 had left at the end, from the log's `score` or else counted
+    /// from its faints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<[u32; 2]>,
+    /// The battle's PRNG seed, as raw JSON, for replaying it in the simulator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<String>,
+    /// The ids and names of the third and fourth players, in multi battles; p3 partners p1,
+    /// and p4 partners p2
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p3: Option<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p4: Option<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p3_name: Option<Arc<str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p4_name: Option<Arc<str>>,
+    /// The slot the searched user played in, from "p1" to "p4"; `None` if they spectated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slot: Option<String>,
+    /// The label of the archive the log was found in, from its directory's `:source-label=`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_label: Option<Arc<str>>,
+}
+
+impl Match {
+    /// Details about how the battle ended, for verbose output
+    pub fn verbose_details(&self) -> String {
+        let mut details = format!(
+            "endType: {}, ladderError: {}, inputLog: {}",
+            self.end_type.as_deref().unwrap_or("unknown"),
+            if self.has_ladder_error { "yes" } else { "no" },
+            if self.has_input_log {
+                "present"
+            } else {
+                "missing"
+            },
+        );
+        if !self.move_turns.is_empty() {
+            let turns: Vec<String> = self.move_turns.iter().map(u32::to_string).collect();
+            details.push_str(&format!(", move used on turns: {}", turns.join(", ")));
+        }
+        if let Some(ref seed) = self.seed {
+            details.push_str(&format!(", seed: {}", seed));
+        }
+        details
+    }
+
+    /// When the battle ended, like `2021-05-01T10:27:03Z`, if its log's timestamp could be
+    /// read
+    pub fn timestamp(&self) -> Option<String> {
+        self.ended_at.map(date::format_timestamp)
+    }
+
+    /// The players on each side, with both names on each side of a multi battle, like
+    /// "Annika & Mia"
+    pub fn side_names(&self) -> [String; 2] {
+        let side = |name: &str, partner: &Option<Arc<str>>| match partner {
+            Some(partner) => format!("{} & {}", name, partner),
+            None => String::from(name),
+        };
+        [
+            side(&self.p1_name, &self.p3_name),
+            side(&self.p2_name, &self.p4_name),
+        ]
+    }
+
+    /// Which side the searched user, `user_id`, played on: 0 for p1's, with p3 in multi
+    /// battles, or 1 for p2's, with p4. Goes by the slot they were found in, if it's known.
+    pub fn user_side(&self, user_id: &str) -> usize {
+        match self.slot.as_deref() {
+            Some("p1") | Some("p3") => 0,
+            Some("p2") | Some("p4") => 1,
+            _ if *self.p2 == *user_id || self.p4.as_deref() == Some(user_id) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Whether `id` played on `side` (see `user_side`)
+    pub fn is_on_side(&self, side: usize, id: &str) -> bool {
+        let (player, partner) = if side == 0 {
+            (&self.p1, &self.p3)
+        } else {
+            (&self.p2, &self.p4)
+        };
+        **player == *id || partner.as_deref() == Some(id)
+    }
+
+    /// Which side won (see `user_side`), if a player did and was in the battle. Multi battles
+    /// can name both winners, like "Annika & Mia".
+    pub fn winning_side(&self) -> Option<usize> {
+        let winner = match self.winner {
+            Winner::Player(ref winner) => winner,
+            _ => return None,
+        };
+        (0..2).find(|&side| {
+            let (player, partner) = if side == 0 {
+                (&self.p1, &self.p3)
+            } else {
+                (&self.p2, &self.p4)
+            };
+            self.is_on_side(side, winner)
+                || partner
+                    .as_ref()
+                    .is_some_and(|partner| **winner == *format!("{}{}", player, partner))
+        })
+    }
+
+    /// Who won and how, like "Annika won by forfeit", noting if the user only spectated or
+    /// played as p3 or p4
+    pub fn outcome(&self) -> String {
+        let win_type_str = if self.is_forfeit {
+            " by forfeit"
+        } else if self.end_type.is_some() {
+            " normally"
+        } else {
+            ""
+        };
+        let win_str = match (&self.winner, &self.winner_name) {
+            (Winner::Player(_), Some(winner)) => format!("{} won{}", winner, win_type_str),
+            (Winner::Player(winner), None) => format!("{} won{}", winner, win_type_str),
+            (Winner::Nobody, _) => String::from("there was no winner"),
+            (Winner::Unknown, _) => String::from("the winner is unknown"),
+        };
+        let outcome = match self.score {
+            Some([p1, p2]) => format!("({}, {}-{})", win_str, p1, p2),
+            None => format!("({})", win_str),
+        };
+        if self.is_spectator {
+            format!("{} [spectator]", outcome)
+        } else {
+            match self.slot.as_deref() {
+                Some(slot @ "p3") | Some(slot @ "p4") => format!("{} [as {}]", outcome, slot),
+                _ => outcome,
+            }
+        }
+    }
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [p1_side, p2_side] = self.side_names();
+        write!(
+            f,
+            "({}) <<{}>> {} vs. {} {}",
+            self.date,
+            self.room,
+            p1_side,
+            p2_side,
+            self.outcome()
         )
-        .unwrap();
+    }
+}
+
+/// What checking logs changes: the parser, which pikkr trains on the first logs it parses,
+/// and what's recorded along the way. Each thread checking logs has its own, so that one
+/// `BattleSearcher` can be shared by all of them.
+pub struct SearchState {
+    json_parser: pikkr_annika::Pikkr<'static>,
+    pikkr_training_rounds: usize,
+    /// Where time is spent, when profiling
+    pub profile: Option<ThreadProfile>,
+    /// Logs that didn't conform to the schema, with how, when `BattleSearcher::validate` is
+    /// set
+    pub invalid_logs: Vec<(PathBuf, Vec<String>)>,
+    /// Where what's allocated only while checking a log goes; reset before each
+    scratch: Scratch,
+}
 
+impl SearchState {
+    pub fn new(pikkr_training_rounds: usize, profile: Option<ThreadProfile>) -> Self {
         Self {
-            user_id: str_to_id(username),
-            json_parser,
-            wins_only,
-            forfeits_only,
+            json_parser: Self::new_parser(pikkr_training_rounds),
+            pikkr_training_rounds,
+            profile,
+            invalid_logs: vec![],
+            scratch: Scratch::default(),
         }
     }
 
-    /// json is in the form [p1name, p2name, winner, endType]
-    pub fn check_log(&mut self, path: &PathBuf, date: &str) -> Result<(), BattleSearchError> {
-        let data = fs::read(path)?;
-        let json = self.json_parser.parse(&data).unwrap();
+    fn new_parser(pikkr_training_rounds: usize) -> pikkr_annika::Pikkr<'static> {
+        let queries: Vec<&[u8]> = LOG_FIELDS.iter().map(|field| field.as_bytes()).collect();
+        pikkr_annika::Pikkr::new(&queries, pikkr_training_rounds).unwrap()
+    }
 
-        if json.len() != 4 {
+    /// Replaces the parser with an untrained one, since a panic while parsing may leave it in
+    /// a bad state
+    pub fn reset_parser(&mut self) {
+        self.json_parser = Self::new_parser(self.pikkr_training_rounds);
+    }
+}
+
+/// What to search for. Checking a log doesn't change the searcher, so it can be shared between
+/// threads, each with a `SearchState` of its own.
+pub struct BattleSearcher {
+    /// The ids of the searched users; usually just one. If there are none, every battle is
+    /// checked as if its players were searched for.
+    user_ids: Vec<String>,
+    /// Patterns for the ids of more searched users
+    user_patterns: Vec<IdPattern>,
+    matcher: Arc<dyn Matcher>,
+    /// Also report battles the user joined or chatted in without playing
+    include_spectators: bool,
+    /// Only report battles where the searched user used this move (as an id)
+    move_id: Option<String>,
+    /// How players' names are converted to ids
+    id_scheme: IdScheme,
+    /// When running several queries at once, battles must match at least one of these,
+    /// and matches note which
+    pub queries: Arc<Vec<Box<dyn Matcher>>>,
+    /// Whether matches should include the species on each team, for usage stats
+    pub keep_teams: bool,
+    /// Whether to check matches' logs against their metadata, noting any inconsistencies
+    pub verify: bool,
+    /// Whether to check every log against the schema, noting those that don't conform in
+    /// `SearchState::invalid_logs`
+    pub validate: bool,
+    /// Only report battles where one player had at least this many more Pokémon left than
+    /// the other; battles whose score can't be told are left out
+    pub min_score_diff: Option<u32>,
+    /// The searched users' display names, lowercased, if players should be matched by their
+    /// exact names (apart from case) instead of by id
+    pub display_names: Option<Vec<String>>,
+}
+
+impl BattleSearcher {
+    pub fn new(
+        usernames: &[String],
+        matcher: Arc<dyn Matcher>,
+        include_spectators: bool,
+        move_name: Option<&str>,
+        id_scheme: IdScheme,
+    ) -> Self {
+        Self {
+            user_ids: usernames
+                .iter()
+                .filter(|name| IdPattern::parse(name).is_none())
+                .map(|name| id_scheme.to_id(name))
+                .collect(),
+            user_patterns: usernames
+                .iter()
+                .filter_map(|name| IdPattern::parse(name))
+                .collect(),
+            matcher,
+            include_spectators,
+            move_id: move_name.map(str_to_id),
+            id_scheme,
+            queries: Arc::new(vec![]),
+            keep_teams: false,
+            verify: false,
+            validate: false,
+            min_score_diff: None,
+            display_names: None,
+        }
+    }
+
+    /// Whether a searched user may have played in any battle among `players`, a player
+    /// index's names, one per line. Indexes don't list spectators, and every battle is checked
+    /// when no users are searched for, so then they always may have.
+    pub fn may_have_played(&self, players: &str) -> bool {
+        if self.include_spectators || (self.user_ids.is_empty() && self.user_patterns.is_empty()) {
+            return true;
+        }
+        players
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .any(|name| {
+                let id = self.id_scheme.to_id(name);
+                self.user_ids.contains(&id)
+                    || self
+                        .user_patterns
+                        .iter()
+                        .any(|pattern| pattern.matches(&id))
+            })
+    }
+
+    /// Whether `name`, which may be a raw JSON string, is a searched user's, by id or pattern,
+    /// or by display name when `display_names` is set. With no searched users, everyone is.
+    fn is_searched_user(&self, scratch: &Scratch, name: &[u8]) -> bool {
+        match self.display_names {
+            Some(ref names) => bytes_to_string(&Some(name))
+                .is_some_and(|name| names.contains(&name.to_lowercase())),
+            None => {
+                (self.user_ids.is_empty() && self.user_patterns.is_empty())
+                    || self
+                        .user_ids
+                        .iter()
+                        .any(|id| self.id_scheme.has_id(name, id))
+                    || (!self.user_patterns.is_empty() && {
+                        let id = scratch.id(self.id_scheme, name);
+                        self.user_patterns
+                            .iter()
+                            .any(|pattern| pattern.matches(&id))
+                    })
+            }
+        }
+    }
+
+    /// Checks the contents of the log at `path`.
+    ///
+    /// Returns `None` if the battle doesn't involve the searched user or is filtered out.
+    pub fn check_log(
+        &self,
+        state: &mut SearchState,
+        data: &[u8],
+        path: &Path,
+        date: &str,
+    ) -> Result<Option<Match>, BattleSearchError> {
+        let mut fallback = vec![];
+        if state.profile.is_none() {
+            let json = Self::parse_fields(state, data, &mut fallback)?;
+            return self.check_parsed_log(state, &json, path, date);
+        }
+
+        let start = Instant::now();
+        let json = Self::parse_fields(state, data, &mut fallback)?;
+        let profile = state.profile.as_mut().unwrap();
+        let start = ThreadProfile::record(&mut profile.parsing, start);
+        let result = self.check_parsed_log(state, &json, path, date);
+        let profile = state.profile.as_mut().unwrap();
+        ThreadProfile::record(&mut profile.matching, start);
+        profile.files += 1;
+        result
+    }
+
+    /// Parses a log's fields for checking with `check_fields`, possibly more than once
+    pub fn parse_log(
+        &self,
+        state: &mut SearchState,
+        data: &[u8],
+    ) -> Result<ParsedFields, BattleSearchError> {
+        let start = Instant::now();
+        let mut fallback = vec![];
+        let fields = Self::parse_fields(state, data, &mut fallback)?
+            .into_iter()
+            .map(|field| field.map(<[u8]>::to_vec))
+            .collect();
+        if let Some(ref mut profile) = state.profile {
+            ThreadProfile::record(&mut profile.parsing, start);
+        }
+        Ok(fields)
+    }
+
+    /// Checks a log parsed by `parse_log`, as `check_log` checks its contents
+    pub fn check_fields(
+        &self,
+        state: &mut SearchState,
+        fields: &ParsedFields,
+        path: &Path,
+        date: &str,
+    ) -> Result<Option<Match>, BattleSearchError> {
+        let start = Instant::now();
+        let json: Vec<Option<&[u8]>> = fields.iter().map(Option::as_deref).collect();
+        let result = self.check_parsed_log(state, &json, path, date);
+        if let Some(ref mut profile) = state.profile {
+            ThreadProfile::record(&mut profile.matching, start);
+            profile.files += 1;
+        }
+        result
+    }
+
+    /// Parses a log's fields with pikkr, falling back to the slower but more forgiving
+    /// serde_json for the rare logs pikkr can't handle; `fallback` holds the fields then.
+    fn parse_fields<'b>(
+        state: &mut SearchState,
+        data: &'b [u8],
+        fallback: &'b mut ParsedFields,
+    ) -> Result<Vec<Option<&'b [u8]>>, BattleSearchError> {
+        match state.json_parser.parse(data) {
+            Ok(json) => Ok(json),
+            Err(_) => {
+                *fallback = parse_with_serde(data)?;
+                Ok(fallback.iter().map(Option::as_deref).collect())
+            }
+        }
+    }
+
+    /// Checks a log's fields, in the form [p1name, p2name, winner, endType, ladderError,
+    /// inputLog, log, format, rated, turns, timestamp, score, seed, p1team, p2team, p3name,
+    /// p4name]
+    fn check_parsed_log(
+        &self,
+        state: &mut SearchState,
+        json: &[Option<&[u8]>],
+        path: &Path,
+        date: &str,
+    ) -> Result<Option<Match>, BattleSearchError> {
+        if json.len() != LOG_FIELDS.len() {
             // should never happen
             return Err(BattleSearchError::FaultyJSON(format!(
-                "BattleSearcher::check_log(): found {} elements in parsed JSON (expected 4)",
-                json.len()
+                "BattleSearcher::check_log(): found {} elements in parsed JSON (expected {})",
+                json.len(),
+                LOG_FIELDS.len()
             )));
         }
+        state.scratch.reset();
+        // before anything else, since logs are validated whether they match or not
+        if self.validate {
+            let problems = validate::schema_problems(json);
+            if !problems.is_empty() {
+                state.invalid_logs.push((path.to_path_buf(), problems));
+            }
+        }
 
-        // parse players
-        let p1id = match bytes_to_id(json.get(0).unwrap()) {
-            Some(a) => a,
-            None => return Err(BattleSearchError::FaultyJSON(format!("No p1 value"))),
+        // parse players; most logs aren't the searched user's, so ids are compared in place
+        let p1_raw = match json.first().unwrap() {
+            Some(raw) => raw,
+            None => return Err(BattleSearchError::FaultyJSON(String::from("No p1 value"))),
+        };
+        let p2_raw = match json.get(1).unwrap() {
+            Some(raw) => raw,
+            None => return Err(BattleSearchError::FaultyJSON(String::from("No p2 value"))),
         };
-        let p2id = match bytes_to_id(json.get(1).unwrap()) {
-            Some(a) => a,
-            None => return Err(BattleSearchError::FaultyJSON(format!("No p2 value"))),
+        // only multi battles have a p3 and p4
+        let (p3_raw, p4_raw) = match (json.get(15).unwrap(), json.get(16).unwrap()) {
+            (Some(p3), Some(p4)) if *p3 != b"null" && *p4 != b"null" => (Some(*p3), Some(*p4)),
+            _ => (None, None),
         };
-        let p1_is_searched_user = p1id == self.user_id;
-        let p2_is_searched_user = p2id == self.user_id;
-        if !p1_is_searched_user && !p2_is_searched_user {
+        let scratch = &state.scratch;
+        let is_searched_user = |raw: &[u8]| self.is_searched_user(scratch, raw);
+        let p1_is_searched_user = is_searched_user(p1_raw);
+        let p2_is_searched_user = is_searched_user(p2_raw);
+        let p3_is_searched_user = p3_raw.is_some_and(is_searched_user);
+        let p4_is_searched_user = p4_raw.is_some_and(is_searched_user);
+        let is_spectator = !p1_is_searched_user
+            && !p2_is_searched_user
+            && !p3_is_searched_user
+            && !p4_is_searched_user;
+        if is_spectator {
             // Searched user is not a player in the battle.
-            return Ok(());
+            let spectated = match json.get(6).unwrap() {
+                Some(log) if self.include_spectators => {
+                    user_spectated(log, scratch, is_searched_user)
+                }
+                _ => false,
+            };
+            if !spectated {
+                return Ok(None);
+            }
         }
 
-        // parse winner
-        let winner_id = bytes_to_id(json.get(2).unwrap());
-        let searched_user_won = match winner_id {
-            Some(ref winner) => winner == &self.user_id,
-            None => false,
+        let p1id = self.id_scheme.to_id(&String::from_utf8_lossy(p1_raw));
+        let p2id = self.id_scheme.to_id(&String::from_utf8_lossy(p2_raw));
+        let p1_name = bytes_to_string(json.first().unwrap()).unwrap_or_default();
+        let p2_name = bytes_to_string(json.get(1).unwrap()).unwrap_or_default();
+        let extra_player = |raw: Option<&[u8]>| {
+            raw.map(|raw| {
+                let id = self.id_scheme.to_id(&String::from_utf8_lossy(raw));
+                let name = bytes_to_string(&Some(raw)).unwrap_or_default();
+                (Arc::from(id), Arc::from(name))
+            })
         };
-        if !searched_user_won && self.wins_only {
-            return Ok(());
-        }
+        let (p3, p3_name) = extra_player(p3_raw).unzip();
+        let (p4, p4_name) = extra_player(p4_raw).unzip();
+        // which slot the searched user had, since with patterns their id isn't known
+        let slot = if p1_is_searched_user {
+            Some(String::from("p1"))
+        } else if p2_is_searched_user {
+            Some(String::from("p2"))
+        } else if p3_is_searched_user {
+            Some(String::from("p3"))
+        } else if p4_is_searched_user {
+            Some(String::from("p4"))
+        } else {
+            None
+        };
+        let winner = match json.get(2).unwrap() {
+            // `null` isn't written by the server, but would say as little as a missing winner
+            None | Some(b"null") => Winner::Unknown,
+            raw => match bytes_to_id(raw, self.id_scheme) {
+                Some(id) if !id.is_empty() => Winner::Player(id),
+                _ => Winner::Nobody,
+            },
+        };
+        let winner_name = match winner {
+            Winner::Player(_) => bytes_to_string(json.get(2).unwrap()),
+            _ => None,
+        };
+        let room = String::from(formats::room_from_filename(
+            path.file_name()
+                .and_then(|os_str| os_str.to_str())
+                .unwrap_or("unknown file"),
+        ));
 
-        // parse endType
-        let is_forfeit = match json.get(3).unwrap() {
-            Some(bytes) => String::from_utf8_lossy(bytes) == "\"forfeit\"",
-            None => false,
+        let meta = BattleMeta {
+            date: String::from(date),
+            p1: p1id,
+            p2: p2id,
+            winner,
+            end_type: bytes_to_string(json.get(3).unwrap()),
+            has_ladder_error: is_truthy(json.get(4).unwrap()),
+            has_input_log: is_truthy(json.get(5).unwrap()),
+            // old logs have no format, but it's also in the room name
+            format: match bytes_to_string(json.get(7).unwrap()) {
+                Some(format) => formats::normalize_format(&format),
+                None => RoomId::parse(&room).map(|room| room.format.id()),
+            },
+            rated: is_truthy(json.get(8).unwrap()),
+            turns: bytes_to_value(json.get(9).unwrap()),
+            timestamp: bytes_to_string(json.get(10).unwrap()),
+            score: bytes_to_value(json.get(11).unwrap()),
+            seed: json
+                .get(12)
+                .unwrap()
+                .filter(|raw| *raw != b"null")
+                .map(|raw| String::from_utf8_lossy(raw).into_owned()),
+            p1_team: bytes_to_value(json.get(13).unwrap()),
+            p2_team: bytes_to_value(json.get(14).unwrap()),
         };
-        if !is_forfeit && self.forfeits_only {
-            return Ok(());
+        if !self.matcher.matches(&meta) {
+            return Ok(None);
+        }
+        let queries: Vec<usize> = (0..self.queries.len())
+            .filter(|&idx| self.queries[idx].matches(&meta))
+            .collect();
+        if !self.queries.is_empty() && queries.is_empty() {
+            return Ok(None);
         }
 
-        // formatting
-        let win_type_str = if is_forfeit { "by forfeit" } else { "normally" };
-        let win_str = match winner_id {
-            Some(ref winner) => format!("{} won {}", winner, win_type_str),
-            None => String::from("there was no winner"),
+        let move_turns = match self.move_id {
+            Some(ref move_id) => {
+                let mut sides = vec![];
+                if p1_is_searched_user {
+                    sides.push("p1");
+                }
+                if p2_is_searched_user {
+                    sides.push("p2");
+                }
+                if p3_is_searched_user {
+                    sides.push("p3");
+                }
+                if p4_is_searched_user {
+                    sides.push("p4");
+                }
+                let turns = match json.get(6).unwrap() {
+                    Some(log) => move_turns(log, scratch, &sides, move_id),
+                    None => vec![],
+                };
+                if turns.is_empty() {
+                    return Ok(None);
+                }
+                turns
+            }
+            None => vec![],
         };
 
-        let room = match path.file_name() {
-            Some(os_str) => String::from(os_str.to_str().unwrap_or("unknown file")),
-            None => String::from("unknown file"),
+        let score = match meta.score {
+            Some(ref score) if score.len() == 2 => Some([score[0], score[1]]),
+            _ => json
+                .get(6)
+                .unwrap()
+                .and_then(|log| score_from_log(log, scratch)),
+        };
+        if let Some(min_diff) = self.min_score_diff {
+            if score.is_none_or(|[p1, p2]| p1.abs_diff(p2) < min_diff) {
+                return Ok(None);
+            }
         }
-        .replace(".log.json", "");
 
-        println!(
-            "({}) <<{}>> {} vs. {} ({})",
-            date, room, p1id, p2id, win_str
-        );
+        let inconsistencies = if self.verify {
+            log_inconsistencies(
+                *json.get(6).unwrap(),
+                scratch,
+                &meta.p1,
+                &meta.p2,
+                &meta.winner,
+                self.id_scheme,
+            )
+        } else {
+            vec![]
+        };
+        let species = |team: Option<Vec<PokemonSet>>| -> Vec<String> {
+            match team {
+                Some(team) if self.keep_teams => team.into_iter().map(|set| set.species).collect(),
+                _ => vec![],
+            }
+        };
 
-        Ok(())
+        Ok(Some(Match {
+            date: Arc::from(date),
+            room,
+            p1: Arc::from(meta.p1),
+            p2: Arc::from(meta.p2),
+            winner: meta.winner.map(Arc::from),
+            p1_name: Arc::from(p1_name),
+            p2_name: Arc::from(p2_name),
+            winner_name: winner_name.map(Arc::from),
+            is_forfeit: meta.end_type.as_deref() == Some("forfeit"),
+            end_type: meta.end_type,
+            has_ladder_error: meta.has_ladder_error,
+            has_input_log: meta.has_input_log,
+            is_spectator,
+            move_turns,
+            format: meta.format.map(Arc::from),
+            path: path.to_path_buf(),
+            ended_at: meta.timestamp.as_deref().and_then(date::parse_timestamp),
+            queries,
+            p1_team: species(meta.p1_team),
+            p2_team: species(meta.p2_team),
+            inconsistencies,
+            score,
+            seed: meta.seed,
+            p3,
+            p4,
+            p3_name,
+            p4_name,
+            slot,
+            // filled in later from what's outside the log
+            ..Match::default()
+        }))
     }
 }
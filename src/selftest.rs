@@ -0,0 +1,429 @@
+use crate::runner::{SearchJob, SearchSummary};
+use crate::search::{str_to_id, BattleSearchError, Match, WithPath};
+/// A self-test that searches a generated corpus of logs whose properties are known, to check
+/// that a build (or the filesystem it runs on) finds exactly the battles it should
+use battle_tools::layout::{ArchiveRoot, Layout};
+use flate2::{write::GzEncoder, Compression};
+use serde_json::json;
+use std::{
+    collections::{BTreeSet, HashMap},
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+};
+
+const PLAYERS: [&str; 4] = ["Alice", "Bob", "Carol", "Dave Jr."];
+const BATTLES: u64 = 120;
+const FIRST_ROOM: u64 = 1000;
+/// The `--max-file-size` for checking that logs in archives are held to it, which the corpus
+/// has one log over
+const MAX_FILE_SIZE: u64 = 64 * 1024;
+
+/// A generated battle
+struct Battle {
+    room: String,
+    date: String,
+    p1: &'static str,
+    p2: &'static str,
+    /// `None` for a tie
+    winner: Option<&'static str>,
+    forfeit: bool,
+    turns: u32,
+    format: &'static str,
+    gzipped: bool,
+}
+
+impl Battle {
+    fn generate(idx: u64) -> Self {
+        let number = FIRST_ROOM + idx;
+        let random = idx % 4 == 3;
+        let format = if random { "gen8randombattle" } else { "gen8ou" };
+        let p1 = PLAYERS[(idx % 4) as usize];
+        // never the same as p1
+        let p2 = PLAYERS[((idx + 1 + idx / 4 % 3) % 4) as usize];
+        let forfeit = idx.is_multiple_of(5);
+        Self {
+            room: format!("{}-{}", format, number),
+            date: format!("2021-05-{:02}", 1 + idx % 10),
+            p1,
+            p2,
+            winner: if idx.is_multiple_of(7) {
+                None
+            } else if idx.is_multiple_of(3) {
+                Some(p2)
+            } else {
+                Some(p1)
+            },
+            forfeit,
+            turns: if forfeit && idx.is_multiple_of(2) {
+                1
+            } else {
+                5 + (idx % 30) as u32
+            },
+            format,
+            gzipped: idx.is_multiple_of(6),
+        }
+    }
+
+    fn involves(&self, user: &str) -> bool {
+        self.p1 == user || self.p2 == user
+    }
+
+    fn to_json(&self) -> Vec<u8> {
+        let format_name = match self.format {
+            "gen8randombattle" => "[Gen 8] Random Battle",
+            _ => "[Gen 8] OU",
+        };
+        let day: u32 = self.date[8..].parse().unwrap();
+        let mut log = vec![
+            format!("|j|{}", self.p1),
+            format!("|j|{}", self.p2),
+            format!("|player|p1|{}|1", self.p1),
+            format!("|player|p2|{}|2", self.p2),
+        ];
+        log.extend((1..=self.turns).map(|turn| format!("|turn|{}", turn)));
+        match self.winner {
+            Some(winner) => log.push(format!("|win|{}", winner)),
+            None => log.push(String::from("|tie")),
+        }
+        let log = json!({
+            "winner": self.winner.unwrap_or(""),
+            "seed": [1, 2, 3, 4],
+            "turns": self.turns,
+            "p1": self.p1,
+            "p2": self.p2,
+            "p1team": [{"species": "Pikachu", "moves": ["Thunderbolt"]}],
+            "p2team": [{"species": "Eevee", "moves": ["Tackle"]}],
+            "score": [0, 1],
+            "inputLog": [">start"],
+            "log": log,
+            "endType": if self.forfeit { "forfeit" } else { "normal" },
+            "ladderError": false,
+            "timestamp": format!(
+                "Sat May {:02} 2021 12:00:00 GMT-0400 (Eastern Daylight Time)",
+                day
+            ),
+            "roomid": format!("battle-{}", self.room),
+            "format": format_name,
+        });
+        serde_json::to_vec(&log).unwrap()
+    }
+}
+
+/// The directory a battle's log goes in, like `2021-05/gen8ou/2021-05-01/` in a daily
+/// archive or `2021-05/gen8ou/` in a monthly one
+fn log_directory(root: &Path, layout: Layout, date: &str, format: &str) -> PathBuf {
+    let directory = root.join(&date[..7]).join(format);
+    match layout {
+        Layout::Monthly => directory,
+        _ => directory.join(date),
+    }
+}
+
+/// A tar archive holding `data` as `name`
+fn tar_archive(name: &str, data: &[u8]) -> Result<Vec<u8>, BattleSearchError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    let mut builder = tar::Builder::new(vec![]);
+    builder.append_data(&mut header, name, data)?;
+    Ok(builder.into_inner()?)
+}
+
+/// Writes the battles into `root` as an archive with the given layout, followed by a log that
+/// was cut off while being written and a tar archive with a log over `MAX_FILE_SIZE`
+fn write_corpus(root: &Path, layout: Layout, battles: &[Battle]) -> Result<(), BattleSearchError> {
+    for battle in battles {
+        let directory = log_directory(root, layout, &battle.date, battle.format);
+        fs::create_dir_all(&directory).with_path(&directory)?;
+        let data = battle.to_json();
+        if battle.gzipped {
+            let path = directory.join(format!("{}.log.json.gz", battle.room));
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(&data)?;
+            fs::write(&path, encoder.finish()?).with_path(&path)?;
+        } else {
+            let path = directory.join(format!("{}.log.json", battle.room));
+            fs::write(&path, data).with_path(&path)?;
+        }
+    }
+
+    let last = &battles[battles.len() - 1];
+    let directory = log_directory(root, layout, &last.date, "gen8ou");
+    fs::create_dir_all(&directory).with_path(&directory)?;
+    let data = last.to_json();
+    let path = directory.join(format!("gen8ou-{}.log.json", FIRST_ROOM + BATTLES));
+    fs::write(&path, &data[..data.len() / 2]).with_path(&path)?;
+
+    let oversized = Battle {
+        room: format!("gen8ou-{}", FIRST_ROOM + BATTLES + 1),
+        p1: "Eve",
+        p2: "Trent",
+        ..Battle::generate(0)
+    };
+    let mut data = oversized.to_json();
+    data.resize(MAX_FILE_SIZE as usize + 1, b' ');
+    let directory = log_directory(root, layout, &oversized.date, oversized.format);
+    let path = directory.join("oversized.tar");
+    let archive = tar_archive(&format!("{}.log.json", oversized.room), &data)?;
+    fs::write(&path, archive).with_path(&path)?;
+    Ok(())
+}
+
+/// A search to run against the corpus, and which battles it should find
+struct Check {
+    name: &'static str,
+    username: &'static str,
+    layout: Layout,
+    configure: fn(&mut SearchJob),
+    expected: fn(&Battle) -> bool,
+}
+
+const CHECKS: &[Check] = &[
+    Check {
+        name: "finds every game a user played",
+        username: "Alice",
+        layout: Layout::Daily,
+        configure: |_| (),
+        expected: |battle| battle.involves("Alice"),
+    },
+    Check {
+        name: "matches usernames by id",
+        username: "DAVE JR",
+        layout: Layout::Daily,
+        configure: |_| (),
+        expected: |battle| battle.involves("Dave Jr."),
+    },
+    Check {
+        name: "--wins-only",
+        username: "Bob",
+        layout: Layout::Daily,
+        configure: |job| job.wins_only = true,
+        expected: |battle| battle.winner == Some("Bob"),
+    },
+    Check {
+        name: "--forfeits-only",
+        username: "Carol",
+        layout: Layout::Daily,
+        configure: |job| job.forfeits_only = true,
+        expected: |battle| battle.involves("Carol") && battle.forfeit,
+    },
+    Check {
+        name: "--insta-forfeits",
+        username: "Alice",
+        layout: Layout::Daily,
+        configure: |job| job.insta_forfeits = true,
+        expected: |battle| battle.involves("Alice") && battle.forfeit && battle.turns <= 1,
+    },
+    Check {
+        name: "--no-randoms",
+        username: "Bob",
+        layout: Layout::Daily,
+        configure: |job| job.no_randoms = true,
+        expected: |battle| battle.involves("Bob") && battle.format != "gen8randombattle",
+    },
+    Check {
+        name: "--room-after and --room-before",
+        username: "Carol",
+        layout: Layout::Daily,
+        // the range still includes the partly-written log, which is checked for separately
+        configure: |job| {
+            job.room_after = Some(FIRST_ROOM + 30);
+            job.room_before = Some(FIRST_ROOM + BATTLES + 1);
+        },
+        expected: |battle| {
+            let number: u64 = battle.room.rsplit('-').next().unwrap().parse().unwrap();
+            battle.involves("Carol") && number > FIRST_ROOM + 30
+        },
+    },
+    Check {
+        name: "--filter",
+        username: "Dave Jr.",
+        layout: Layout::Daily,
+        configure: |job| {
+            job.filter =
+                Some(crate::matcher::parse_filter("losses or tie", &job.usernames).unwrap())
+        },
+        expected: |battle| battle.involves("Dave Jr.") && battle.winner != Some("Dave Jr."),
+    },
+    Check {
+        name: "--decompress-threads",
+        username: "Alice",
+        layout: Layout::Daily,
+        configure: |job| job.decompress_threads = 2,
+        expected: |battle| battle.involves("Alice"),
+    },
+    Check {
+        name: "many threads",
+        username: "Bob",
+        layout: Layout::Daily,
+        configure: |job| job.threads = 8,
+        expected: |battle| battle.involves("Bob"),
+    },
+    Check {
+        name: "--from",
+        username: "Bob",
+        layout: Layout::Daily,
+        configure: |job| job.dates.from = Some(String::from("2021-05-06")),
+        expected: |battle| battle.involves("Bob") && *battle.date >= *"2021-05-06",
+    },
+    Check {
+        name: "--from and --until over a monthly archive",
+        username: "Alice",
+        layout: Layout::Monthly,
+        // battles are only dated by month, so any could be from the first few days
+        configure: |job| {
+            job.dates.from = Some(String::from("2021-05"));
+            job.dates.until = Some(String::from("2021-05-03"));
+        },
+        expected: |battle| battle.involves("Alice"),
+    },
+    Check {
+        name: "--max-file-size over logs in archives",
+        username: "Eve",
+        layout: Layout::Daily,
+        configure: |job| job.file_limits.max_size = Some(MAX_FILE_SIZE),
+        expected: |_| false,
+    },
+    Check {
+        name: "finds nothing for a user who never played",
+        username: "Mallory",
+        layout: Layout::Daily,
+        configure: |_| (),
+        expected: |_| false,
+    },
+];
+
+/// Why a search's results aren't what they should be, if they aren't
+fn problems(
+    layout: Layout,
+    battles: &HashMap<&str, &Battle>,
+    expected: &BTreeSet<&str>,
+    matches: &[Match],
+    summary: &SearchSummary,
+) -> Vec<String> {
+    let mut problems = vec![];
+    let found: BTreeSet<&str> = matches.iter().map(|m| m.room.as_str()).collect();
+    if found != *expected {
+        let missing = expected.difference(&found).count();
+        let extra = found.difference(expected).count();
+        problems.push(format!(
+            "expected {} games, found {} ({} missing, {} unexpected)",
+            expected.len(),
+            found.len(),
+            missing,
+            extra
+        ));
+    }
+    for m in matches {
+        let battle = match battles.get(m.room.as_str()) {
+            Some(battle) => battle,
+            None => continue,
+        };
+        let date = match layout {
+            Layout::Monthly => &battle.date[..7],
+            _ => &battle.date,
+        };
+        if *m.date != *date {
+            problems.push(format!(
+                "{} is dated {} instead of {}",
+                m.room, m.date, date
+            ));
+        }
+        if m.format.as_deref() != Some(battle.format) {
+            problems.push(format!("{} has the wrong format", m.room));
+        }
+        if m.winner.player().map(|winner| &**winner) != battle.winner.map(str_to_id).as_deref() {
+            problems.push(format!("{} has the wrong winner", m.room));
+        }
+        if m.is_forfeit != battle.forfeit {
+            problems.push(format!("{} has the wrong end type", m.room));
+        }
+    }
+    if !summary.skipped_directories.is_empty() || summary.failed_files > 0 {
+        problems.push(String::from("some files or directories couldn't be read"));
+    }
+    if summary.incomplete_files != 1 {
+        problems.push(format!(
+            "expected 1 partly-written log to be skipped, but {} were",
+            summary.incomplete_files
+        ));
+    }
+    problems
+}
+
+/// Generates a corpus in a new directory inside `parent` (or the temporary directory), runs
+/// every check against it, and reports which pass. The corpus is deleted afterwards unless
+/// `keep` is set.
+pub fn run_selftest(parent: Option<&Path>, keep: bool) -> Result<(), BattleSearchError> {
+    let root: PathBuf = parent
+        .map_or_else(env::temp_dir, Path::to_path_buf)
+        .join(format!("battlesearch-selftest-{}", process::id()));
+    let battles: Vec<Battle> = (0..BATTLES).map(Battle::generate).collect();
+    // the same battles, in an archive of each layout checked
+    let corpus = |layout| match layout {
+        Layout::Monthly => root.join("monthly"),
+        _ => root.join("daily"),
+    };
+    for layout in [Layout::Daily, Layout::Monthly] {
+        write_corpus(&corpus(layout), layout, &battles)?;
+    }
+    eprintln!(
+        "Generated {} logs in daily and monthly archives in {}",
+        BATTLES + 1,
+        root.display()
+    );
+
+    let by_room: HashMap<&str, &Battle> = battles
+        .iter()
+        .map(|battle| (battle.room.as_str(), battle))
+        .collect();
+    let mut failed = 0;
+    for check in CHECKS {
+        let mut job = SearchJob::new(
+            check.username,
+            vec![ArchiveRoot {
+                path: corpus(check.layout),
+                layout: check.layout,
+                label: None,
+            }],
+        );
+        (check.configure)(&mut job);
+        let expected: BTreeSet<&str> = battles
+            .iter()
+            .filter(|battle| (check.expected)(battle))
+            .map(|battle| battle.room.as_str())
+            .collect();
+
+        let mut matches = vec![];
+        let problems = match job.run(|m| matches.push(m)) {
+            Ok(summary) => problems(check.layout, &by_room, &expected, &matches, &summary),
+            Err(e) => vec![e.to_string()],
+        };
+        if problems.is_empty() {
+            println!("ok      {}", check.name);
+        } else {
+            failed += 1;
+            println!("FAILED  {}", check.name);
+            for problem in problems {
+                println!("            {}", problem);
+            }
+        }
+    }
+
+    if keep {
+        eprintln!("Kept the corpus in {}", root.display());
+    } else if let Err(e) = fs::remove_dir_all(&root) {
+        eprintln!("Couldn't delete {}: {}", root.display(), e);
+    }
+    if failed > 0 {
+        return Err(BattleSearchError::Path(format!(
+            "{} of {} self-test checks failed",
+            failed,
+            CHECKS.len()
+        )));
+    }
+    println!("All {} checks passed.", CHECKS.len());
+    Ok(())
+}
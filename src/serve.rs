@@ -0,0 +1,380 @@
+use crate::logcache::LogCache;
+use crate::matcher;
+use crate::runner::SearchJob;
+use crate::search::{BattleSearchError, WithPath};
+/// A long-running JSON-RPC service for searching battle logs over HTTP
+///
+/// Requests are JSON-RPC 2.0 calls POSTed to any path:
+///     {"jsonrpc": "2.0", "id": 1, "method": "search",
+///      "params": {"username": "Annika", "directories": ["logs/2021-05"], "wins_only": true}}
+///
+/// The response body is newline-delimited JSON, sent with chunked encoding so matches stream
+/// as they are found: one `match` notification per match, then the JSON-RPC response for the call.
+/// Only directories within the served roots can be searched.
+use battle_tools::date;
+use battle_tools::formats;
+use battle_tools::layout::ArchiveRoot;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    slice,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// The largest request body accepted; search requests are far smaller
+const MAX_BODY: usize = 1 << 20;
+/// The most a request line and its headers may take up together
+const MAX_HEADER: u64 = 16 << 10;
+/// How many connections are handled at once; more are turned away until one finishes
+const MAX_CONNECTIONS: usize = 16;
+/// How long a client may go without sending anything while its request is read
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    username: String,
+    /// Directory arguments as on the command line, optionally with layout hints
+    directories: Vec<String>,
+    #[serde(default)]
+    wins_only: bool,
+    #[serde(default)]
+    forfeits_only: bool,
+    #[serde(default)]
+    insta_forfeits: bool,
+    #[serde(default)]
+    no_randoms: bool,
+    filter: Option<String>,
+    #[serde(default)]
+    include_spectators: bool,
+    #[serde(rename = "move")]
+    move_name: Option<String>,
+    #[serde(default)]
+    fail_fast: bool,
+    room_after: Option<u64>,
+    room_before: Option<u64>,
+    /// As with `--modified-since`, a date or an age like `48h`
+    modified_since: Option<String>,
+    /// As with `--rooms-file`, the only rooms to check
+    rooms: Option<Vec<String>>,
+    max_bytes: Option<u64>,
+}
+
+/// Writes a response body with chunked transfer encoding
+struct ChunkedWriter<'a> {
+    stream: &'a mut TcpStream,
+}
+
+impl<'a> ChunkedWriter<'a> {
+    fn start(stream: &'a mut TcpStream) -> std::io::Result<Self> {
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: application/x-ndjson\r\n\
+              Transfer-Encoding: chunked\r\n\
+              Connection: close\r\n\r\n",
+        )?;
+        Ok(Self { stream })
+    }
+
+    fn send_line(&mut self, value: &Value) -> std::io::Result<()> {
+        let line = format!("{}\n", value);
+        write!(self.stream, "{:x}\r\n{}\r\n", line.len(), line)?;
+        self.stream.flush()
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        self.stream.write_all(b"0\r\n\r\n")?;
+        self.stream.flush()
+    }
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+/// What a client sent
+enum Body {
+    Post(Vec<u8>),
+    /// Anything but a POST with a body
+    NotPost,
+    /// A body longer than `MAX_BODY`, which isn't read
+    TooLarge,
+    /// A request line and headers longer than `MAX_HEADER`, which aren't read past it
+    HeaderTooLarge,
+}
+
+fn read_body(stream: &TcpStream) -> std::io::Result<Body> {
+    let mut reader = BufReader::new(stream);
+    let mut head = reader.by_ref().take(MAX_HEADER);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    let is_post = request_line.starts_with("POST ");
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if head.read_line(&mut header)? == 0 {
+            if head.limit() == 0 {
+                return Ok(Body::HeaderTooLarge);
+            }
+            break;
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    if !is_post || content_length == 0 {
+        return Ok(Body::NotPost);
+    }
+    if content_length > MAX_BODY {
+        return Ok(Body::TooLarge);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Body::Post(body))
+}
+
+/// Parses a requested directory, which must be within one of the served `roots`
+fn parse_directory(arg: &str, roots: &[PathBuf]) -> Result<ArchiveRoot, String> {
+    let root = ArchiveRoot::parse(arg)?;
+    match fs::canonicalize(&root.path) {
+        Ok(path) if roots.iter().any(|served| path.starts_with(served)) => Ok(root),
+        _ => Err(format!(
+            "{} isn't within the served directories",
+            root.path.display()
+        )),
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    threads: u32,
+    log_cache: Option<Arc<LogCache>>,
+    roots: &[PathBuf],
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let body =
+        match read_body(&stream)? {
+            Body::Post(body) => body,
+            Body::NotPost => {
+                return stream.write_all(
+                    b"HTTP/1.1 405 Method Not Allowed\r\nAllow: POST\r\nContent-Length: 0\r\n\r\n",
+                )
+            }
+            Body::TooLarge => return stream.write_all(
+                b"HTTP/1.1 413 Payload Too Large\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            ),
+            Body::HeaderTooLarge => {
+                return stream.write_all(
+                    b"HTTP/1.1 431 Request Header Fields Too Large\r\nConnection: close\r\n\
+                  Content-Length: 0\r\n\r\n",
+                )
+            }
+        };
+
+    let mut writer = ChunkedWriter::start(&mut stream)?;
+    let request: RpcRequest = match serde_json::from_slice::<Value>(&body) {
+        Ok(value) => match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                writer.send_line(&error_response(
+                    &Value::Null,
+                    INVALID_REQUEST,
+                    &e.to_string(),
+                ))?;
+                return writer.finish();
+            }
+        },
+        Err(e) => {
+            writer.send_line(&error_response(&Value::Null, PARSE_ERROR, &e.to_string()))?;
+            return writer.finish();
+        }
+    };
+
+    if request.method != "search" {
+        writer.send_line(&error_response(
+            &request.id,
+            METHOD_NOT_FOUND,
+            &format!("Unknown method '{}'", request.method),
+        ))?;
+        return writer.finish();
+    }
+    let params: SearchParams = match serde_json::from_value(request.params) {
+        Ok(params) => params,
+        Err(e) => {
+            writer.send_line(&error_response(&request.id, INVALID_PARAMS, &e.to_string()))?;
+            return writer.finish();
+        }
+    };
+
+    let directories: Result<Vec<ArchiveRoot>, String> = params
+        .directories
+        .iter()
+        .map(|arg| parse_directory(arg, roots))
+        .collect();
+    let directories = match directories {
+        Ok(directories) => directories,
+        Err(e) => {
+            writer.send_line(&error_response(&request.id, INVALID_PARAMS, &e))?;
+            return writer.finish();
+        }
+    };
+
+    let mut job = SearchJob::new(&params.username, directories);
+    job.wins_only = params.wins_only;
+    job.forfeits_only = params.forfeits_only;
+    job.insta_forfeits = params.insta_forfeits;
+    job.no_randoms = params.no_randoms;
+    if let Some(ref filter) = params.filter {
+        match matcher::parse_filter(filter, slice::from_ref(&params.username)) {
+            Ok(filter) => job.filter = Some(filter),
+            Err(e) => {
+                writer.send_line(&error_response(&request.id, INVALID_PARAMS, &e.to_string()))?;
+                return writer.finish();
+            }
+        }
+    }
+    job.room_after = params.room_after;
+    job.room_before = params.room_before;
+    if let Some(ref since) = params.modified_since {
+        match date::parse_since(since) {
+            Ok(since) => job.modified_since = Some(since),
+            Err(e) => {
+                writer.send_line(&error_response(&request.id, INVALID_PARAMS, &e))?;
+                return writer.finish();
+            }
+        }
+    }
+    job.rooms = params.rooms.map(|rooms| {
+        rooms
+            .iter()
+            .map(|room| String::from(formats::strip_room(room)))
+            .collect()
+    });
+    job.threads = threads;
+    job.log_cache = log_cache;
+    job.include_spectators = params.include_spectators;
+    job.move_name = params.move_name;
+    job.fail_fast = params.fail_fast;
+    job.max_bytes = params.max_bytes;
+    // nor can symlinks lead out of them
+    job.restrict_to_root = true;
+
+    // stops the search if the client goes away
+    let cancel = Arc::new(AtomicBool::new(false));
+    job.cancel = Some(cancel.clone());
+
+    let mut match_count = 0;
+    let mut write_error = None;
+    let result = job.run(|m| {
+        match_count += 1;
+        if write_error.is_none() {
+            let notification = json!({"jsonrpc": "2.0", "method": "match", "params": m});
+            if let Err(e) = writer.send_line(&notification) {
+                write_error = Some(e);
+                cancel.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    if let Some(e) = write_error {
+        return Err(e);
+    }
+
+    let response = match result {
+        Ok(summary) => {
+            let skipped: Vec<Value> = summary
+                .skipped_directories
+                .iter()
+                .map(|(path, error)| json!({"path": path, "error": error}))
+                .collect();
+            json!({
+                "jsonrpc": "2.0",
+                "id": request.id,
+                "result": {
+                    "matches": match_count,
+                    "skipped_directories": skipped,
+                    "bytes_read": summary.bytes_read,
+                    "hit_byte_limit": summary.hit_byte_limit,
+                    "missing_rooms": summary.missing_rooms,
+                },
+            })
+        }
+        Err(e) => error_response(&request.id, INTERNAL_ERROR, &e.to_string()),
+    };
+    writer.send_line(&response)?;
+    writer.finish()
+}
+
+/// Serves search requests for directories within `roots` until the process is killed. With
+/// `cache_mb`, parsed logs are kept in memory between requests.
+pub fn serve(
+    listen: &str,
+    threads: u32,
+    cache_mb: Option<usize>,
+    roots: &[PathBuf],
+) -> Result<(), BattleSearchError> {
+    let roots: Vec<PathBuf> = roots
+        .iter()
+        .map(|root| fs::canonicalize(root).with_path(root))
+        .collect::<Result<_, _>>()?;
+    let roots: Arc<[PathBuf]> = Arc::from(roots);
+    let listener = TcpListener::bind(listen)?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+    let log_cache = cache_mb.map(|cache_mb| Arc::new(LogCache::new(cache_mb)));
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    let busy = b"HTTP/1.1 503 Service Unavailable\r\nRetry-After: 5\r\n\
+                                 Connection: close\r\nContent-Length: 0\r\n\r\n";
+                    if let Err(e) = stream.write_all(busy) {
+                        eprintln!("Error turning away a connection: {}", e);
+                    }
+                    continue;
+                }
+                let log_cache = log_cache.clone();
+                let roots = roots.clone();
+                let connections = connections.clone();
+                thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, threads, log_cache, &roots) {
+                        eprintln!("Error handling request: {}", e);
+                    }
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(e) => eprintln!("Error accepting connection: {}", e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use crate::search::{Match, Winner};
+/// Linking the games of best-of-three sets, as played in VGC, so each set is reported as one
+/// match with its result
+use battle_tools::formats::room_number;
+use std::{collections::HashMap, sync::Arc};
+
+/// The longest time between one game of a set ending and the next ending; a game takes
+/// well under this, so a rematch after a break starts a new set
+const MAX_GAME_GAP: i64 = 30 * 60;
+const GAMES_PER_SET: usize = 3;
+const WINS_PER_SET: usize = 2;
+
+/// Sets are only linked within a format, a pair of players, and an archive
+type SetKey<'a> = (Option<&'a str>, [&'a str; 2], Option<&'a str>);
+
+/// The games of a set, in order, with how many each player won
+struct Set<'a> {
+    games: Vec<&'a Match>,
+    wins: HashMap<Arc<str>, usize>,
+}
+
+impl<'a> Set<'a> {
+    fn new(m: &'a Match) -> Self {
+        let mut set = Self {
+            games: vec![],
+            wins: HashMap::new(),
+        };
+        set.add(m);
+        set
+    }
+
+    fn add(&mut self, m: &'a Match) {
+        if let Winner::Player(ref winner) = m.winner {
+            *self.wins.entry(winner.clone()).or_default() += 1;
+        }
+        self.games.push(m);
+    }
+
+    fn is_decided(&self) -> bool {
+        self.wins.values().any(|&wins| wins >= WINS_PER_SET)
+    }
+
+    /// Whether `next` is the following game of this set: the same players in the same format,
+    /// in a later room, ending soon after the last game (or on the same day, if either log has
+    /// no timestamp)
+    fn continues_with(&self, next: &Match) -> bool {
+        let last = self.games[self.games.len() - 1];
+        let soon_after = match (last.ended_at, next.ended_at) {
+            (Some(last), Some(next)) => next >= last && next - last <= MAX_GAME_GAP,
+            _ => last.date == next.date,
+        };
+        !self.is_decided()
+            && self.games.len() < GAMES_PER_SET
+            && room_number(&next.room) > room_number(&last.room)
+            && soon_after
+    }
+
+    fn wins(&self, player: &str) -> usize {
+        self.wins.get(player).copied().unwrap_or(0)
+    }
+
+    /// The set's result, like "Annika won the set 2-1", from the first game's p1's side
+    fn result(&self) -> String {
+        let first = self.games[0];
+        let (p1_wins, p2_wins) = (self.wins(&first.p1), self.wins(&first.p2));
+        let winner = if p1_wins >= WINS_PER_SET {
+            Some(&first.p1_name)
+        } else if p2_wins >= WINS_PER_SET {
+            Some(&first.p2_name)
+        } else {
+            None
+        };
+        match winner {
+            Some(winner) => format!(
+                "{} won the set {}-{}",
+                winner,
+                p1_wins.max(p2_wins),
+                p1_wins.min(p2_wins)
+            ),
+            None => format!("the set is unfinished, {}-{}", p1_wins, p2_wins),
+        }
+    }
+}
+
+/// Groups `sorted`, which should be in chronological order, into sets and prints each as one
+/// match. Games are linked when they're between the same players in the same format with
+/// sequential rooms and little time between them, until a player has won two.
+pub fn print_sets(sorted: &[Match]) {
+    // the sets still being played, by format and players
+    let mut open: HashMap<SetKey, usize> = HashMap::new();
+    let mut sets: Vec<Set> = vec![];
+    for m in sorted {
+        let mut players = [&*m.p1, &*m.p2];
+        players.sort_unstable();
+        let key = (m.format.as_deref(), players, m.source_label.as_deref());
+        match open.get(&key) {
+            Some(&idx) if sets[idx].continues_with(m) => sets[idx].add(m),
+            _ => {
+                open.insert(key, sets.len());
+                sets.push(Set::new(m));
+            }
+        }
+    }
+
+    let linked = sets.iter().filter(|set| set.games.len() > 1).count();
+    for set in &sets {
+        let [p1_side, p2_side] = set.games[0].side_names();
+        let rooms: Vec<&str> = set.games.iter().map(|m| m.room.as_str()).collect();
+        println!(
+            "({}) <<{}>> {} vs. {} ({})",
+            set.games[0].date,
+            rooms.join(", "),
+            p1_side,
+            p2_side,
+            set.result()
+        );
+    }
+    println!();
+    println!(
+        "{} game{} in {} set{}, {} of them with more than one game.",
+        sorted.len(),
+        if sorted.len() == 1 { "" } else { "s" },
+        sets.len(),
+        if sets.len() == 1 { "" } else { "s" },
+        linked
+    );
+}
@@ -0,0 +1,609 @@
+use crate::runner::{self, FileLimits};
+use crate::search::BattleSearchError;
+/// Where logs come from. Directories and archives of logs are all walked as a `LogSource`,
+/// so traversal, the worker threads, and the searcher don't depend on where a log was found.
+use battle_tools::layout::{self, ArchiveRoot, Layout};
+use flate2::read::GzDecoder;
+#[cfg(feature = "sevenz")]
+use sevenz_rust::{Password, SevenZReader};
+use std::{
+    collections::hash_map::RandomState,
+    fs::{self, DirEntry},
+    hash::BuildHasher,
+    io::{self, BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tar::{Archive, EntryType};
+use zip::ZipArchive;
+
+/// The date shown for logs whose date can't be determined from their archive's layout
+const UNKNOWN_DATE: &str = "unknown date";
+/// The index some archives keep in each day's directory of who played that day, one name per
+/// line
+const PLAYER_INDEX: &str = "players.txt";
+
+fn get_filename(file: &Path) -> Result<String, BattleSearchError> {
+    match file.file_name().and_then(|os_str| os_str.to_str()) {
+        Some(s) => Ok(String::from(s)),
+        None => Err(BattleSearchError::Path(format!(
+            "couldn't get the filename of {}",
+            file.display()
+        ))),
+    }
+}
+
+/// Whether an error was from a path being too long for the OS to open, as deeply nested
+/// archives can have
+fn is_too_long(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    return error.raw_os_error() == Some(libc::ENAMETOOLONG);
+    // ERROR_FILENAME_EXCED_RANGE
+    #[cfg(windows)]
+    return error.raw_os_error() == Some(206);
+    #[cfg(not(any(unix, windows)))]
+    return false;
+}
+
+/// `path` in a form the OS can open, or `None` if it's too long to be opened at all. On
+/// Windows, paths past the 260-character limit are given the `\\?\` prefix that lifts it.
+#[cfg(windows)]
+fn openable(path: PathBuf) -> Option<PathBuf> {
+    const MAX_PATH: usize = 260;
+    if path.as_os_str().len() < MAX_PATH || path.starts_with(r"\\?\") {
+        return Some(path);
+    }
+    let absolute = std::path::absolute(&path).ok()?;
+    let absolute = absolute.to_str()?;
+    Some(PathBuf::from(match absolute.strip_prefix(r"\\") {
+        // network shares, like \\server\share
+        Some(share) => format!(r"\\?\UNC\{}", share),
+        None => format!(r"\\?\{}", absolute),
+    }))
+}
+
+/// `path` in a form the OS can open, or `None` if it's too long to be opened at all
+#[cfg(not(windows))]
+fn openable(path: PathBuf) -> Option<PathBuf> {
+    #[cfg(unix)]
+    if path.as_os_str().len() >= libc::PATH_MAX as usize {
+        return None;
+    }
+    Some(path)
+}
+
+/// Whether a file is named like a log, gzipped or not
+fn is_log_name(filename: &str) -> bool {
+    filename.ends_with(".log.json") || filename.ends_with(".log.json.gz")
+}
+
+/// How to get a log's contents
+pub enum LogData {
+    /// The log is a file of its own, which worker threads read (and decompress) themselves
+    File,
+    /// The source already read the log, since it's inside something else, and decompressed it.
+    /// It's empty if it was cut off, which the worker counts as incomplete.
+    Read(Vec<u8>),
+}
+
+/// A log found by a source
+pub struct SourceLog {
+    /// The log's path, which for logs inside archives continues from the archive's path
+    pub path: PathBuf,
+    pub date: String,
+    pub data: LogData,
+}
+
+/// What sources hand their logs to, deciding which are searched along the way
+pub trait LogVisitor {
+    /// Whether a log or archive last modified at `modified` should be searched. It's only
+    /// called if it matters, since reading when a file was modified takes a system call.
+    fn is_fresh(&self, modified: &dyn Fn() -> Option<SystemTime>) -> bool;
+    /// Whether a log found at `path` should be searched, before it's read. Call only once the
+    /// log is known to be fresh, since logs that are wanted count as found.
+    fn wants(&mut self, path: &Path) -> bool;
+    /// Whether only a random sample of each directory's logs is to be searched, in which case
+    /// logs are only visited once the whole directory has been listed
+    fn is_sampling(&self) -> bool;
+    /// How many of the `logs` wanted in `directory` (apart from those in archives) should be
+    /// visited when sampling
+    fn sample_size(&mut self, directory: &Path, logs: usize) -> usize;
+    /// Whether a directory whose player index lists `players` may have logs worth visiting;
+    /// if not, it's skipped without being listed
+    fn wants_players(&mut self, players: &str) -> bool;
+    /// Whether the symlink at `path` may be followed; it's left alone if not
+    fn allows_symlink(&mut self, path: &Path) -> bool;
+    fn visit(&mut self, log: SourceLog);
+    /// Counts the bytes a source read itself
+    fn count_read(&mut self, bytes: usize);
+    /// The limits on logs a source reads itself, as from archives
+    fn file_limits(&self) -> FileLimits;
+    /// Handles a log a source read itself having been abandoned for going past its limits
+    fn abandon(&mut self, path: &Path, reason: &str);
+    /// Whether the rest of the logs can be left unread, because the search is stopping early
+    fn should_stop(&self) -> bool;
+    /// Handles an error reading `path`, skipping it unless the search should stop
+    fn skip(&mut self, path: &Path, error: BattleSearchError) -> Result<(), BattleSearchError>;
+    /// Skips `path`, which is too long for the OS to open; this never stops the search, since
+    /// the path can't be read however many times it's tried
+    fn skip_too_long(&mut self, path: &Path);
+}
+
+/// A provider of logs, such as a directory or an archive
+pub trait LogSource {
+    /// Hands every log in the source to `visitor`, stopping early if it says to
+    fn walk(&mut self, visitor: &mut dyn LogVisitor) -> Result<(), BattleSearchError>;
+}
+
+/// The source for a root that's being searched: an archive, if it's a file named like one,
+/// or else a directory
+pub fn open(root: &ArchiveRoot) -> Box<dyn LogSource> {
+    match archive_kind(&root.path) {
+        Some(kind) if root.path.is_file() => {
+            let metadata = fs::metadata(&root.path).ok();
+            let archive = ArchiveFile {
+                path: root.path.clone(),
+                layout: root.layout,
+                directory_date: None,
+                modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+                modification_date: metadata.as_ref().and_then(layout::modification_date),
+            };
+            return archive.into_source(kind);
+        }
+        _ => (),
+    }
+    Box::new(DirectorySource {
+        root: root.path.clone(),
+        layout: root.layout,
+    })
+}
+
+/// A directory of logs and archives, and all those in its subdirectories
+pub struct DirectorySource {
+    root: PathBuf,
+    layout: Layout,
+}
+
+impl DirectorySource {
+    fn walk_dir(
+        &self,
+        directory: &Path,
+        parent_date: Option<&str>,
+        visitor: &mut dyn LogVisitor,
+    ) -> Result<(), BattleSearchError> {
+        // listing a day's logs takes much longer than reading its index; without one (or if
+        // it can't be read), every log is looked at
+        if let Ok(players) = fs::read_to_string(directory.join(PLAYER_INDEX)) {
+            if !visitor.wants_players(&players) {
+                return Ok(());
+            }
+        }
+        let contents = match directory.read_dir() {
+            Ok(contents) => contents,
+            Err(e) if is_too_long(&e) => {
+                visitor.skip_too_long(directory);
+                return Ok(());
+            }
+            Err(e) => return visitor.skip(directory, e.into()),
+        };
+        let date = match self.layout {
+            Layout::Daily => match get_filename(directory) {
+                Ok(name) => Some(name),
+                Err(e) => return visitor.skip(directory, e),
+            },
+            _ => self.layout.directory_date(directory, parent_date),
+        };
+        // when sampling, logs are visited once the whole directory has been read; otherwise
+        // they're visited as they're found, so huge directories aren't held in memory
+        let sampling = visitor.is_sampling();
+        let mut logs = vec![];
+        for file in contents.flatten() {
+            let file_type = match file.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    visitor.skip(&file.path(), e.into())?;
+                    continue;
+                }
+            };
+            if visitor.should_stop() {
+                // no point walking further when workers won't read what's found
+                break;
+            }
+            let path = match openable(file.path()) {
+                Some(path) => path,
+                None => {
+                    visitor.skip_too_long(&file.path());
+                    continue;
+                }
+            };
+            // the walk doesn't otherwise leave the root, since symlinked directories aren't
+            // walked into
+            if file_type.is_symlink() && !visitor.allows_symlink(&path) {
+                continue;
+            }
+            if file_type.is_dir() {
+                self.walk_dir(&path, date.as_deref(), visitor)?;
+                continue;
+            }
+
+            if let Some(kind) = archive_kind(&path) {
+                let metadata = file.metadata().ok();
+                let archive = ArchiveFile {
+                    path,
+                    layout: self.layout,
+                    directory_date: date.clone(),
+                    modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+                    modification_date: metadata.as_ref().and_then(layout::modification_date),
+                };
+                archive.into_source(kind).walk(visitor)?;
+                continue;
+            }
+
+            if file.file_name() == PLAYER_INDEX {
+                continue;
+            }
+            if !visitor.is_fresh(&|| file.metadata().ok()?.modified().ok()) || !visitor.wants(&path)
+            {
+                continue;
+            }
+            if sampling {
+                logs.push((file, path));
+            } else {
+                visit_file(visitor, &file, path, date.as_deref());
+            }
+        }
+        if !sampling {
+            return Ok(());
+        }
+
+        let sample_size = visitor.sample_size(directory, logs.len());
+        if sample_size < logs.len() {
+            // keys that differ between searches, so each samples different logs
+            let keys = RandomState::new();
+            logs.sort_by_cached_key(|(_, path)| keys.hash_one(path));
+            logs.truncate(sample_size);
+        }
+        for (file, path) in logs {
+            if visitor.should_stop() {
+                break;
+            }
+            visit_file(visitor, &file, path, date.as_deref());
+        }
+        Ok(())
+    }
+}
+
+/// Visits the log `file`, found at `path`, dated `date` or else by when it was last modified
+fn visit_file(visitor: &mut dyn LogVisitor, file: &DirEntry, path: PathBuf, date: Option<&str>) {
+    let date = match date {
+        Some(date) => String::from(date),
+        None => file
+            .metadata()
+            .ok()
+            .and_then(|metadata| layout::modification_date(&metadata))
+            .unwrap_or_else(|| String::from(UNKNOWN_DATE)),
+    };
+    visitor.visit(SourceLog {
+        path,
+        date,
+        data: LogData::File,
+    });
+}
+
+impl LogSource for DirectorySource {
+    fn walk(&mut self, visitor: &mut dyn LogVisitor) -> Result<(), BattleSearchError> {
+        self.walk_dir(&self.root, None, visitor)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+    GzippedTar,
+    #[cfg(feature = "sevenz")]
+    SevenZip,
+}
+
+/// What kind of archive a file is, going by its name, if it's one logs can be read from
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_str()?;
+    #[cfg(feature = "sevenz")]
+    if name.ends_with(".7z") {
+        return Some(ArchiveKind::SevenZip);
+    }
+    if name.ends_with(".zip") {
+        Some(ArchiveKind::Zip)
+    } else if name.ends_with(".tar") {
+        Some(ArchiveKind::Tar)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(ArchiveKind::GzippedTar)
+    } else {
+        None
+    }
+}
+
+/// Whether logs can be read from a file as an archive, going by its name
+pub fn is_archive(path: &Path) -> bool {
+    archive_kind(path).is_some()
+}
+
+/// An archive file, and what's known about it from where it was found
+struct ArchiveFile {
+    path: PathBuf,
+    layout: Layout,
+    /// The date of the directory the archive is in, if the layout gives one
+    directory_date: Option<String>,
+    modified: Option<SystemTime>,
+    modification_date: Option<String>,
+}
+
+impl ArchiveFile {
+    fn into_source(self, kind: ArchiveKind) -> Box<dyn LogSource> {
+        match kind {
+            ArchiveKind::Zip => Box::new(ZipSource { archive: self }),
+            ArchiveKind::Tar => Box::new(TarSource {
+                archive: self,
+                gzipped: false,
+            }),
+            ArchiveKind::GzippedTar => Box::new(TarSource {
+                archive: self,
+                gzipped: true,
+            }),
+            #[cfg(feature = "sevenz")]
+            ArchiveKind::SevenZip => Box::new(SevenZipSource { archive: self }),
+        }
+    }
+
+    /// The date of an entry, from the directories it's in within the archive, continuing from
+    /// the date of the directory the archive is in
+    fn entry_date(&self, name: &str) -> String {
+        let mut date = self.directory_date.clone();
+        if let Some((directories, _)) = name.rsplit_once('/') {
+            for directory in directories.split('/') {
+                date = self
+                    .layout
+                    .directory_date(Path::new(directory), date.as_deref());
+            }
+        }
+        date.or_else(|| self.modification_date.clone())
+            .unwrap_or_else(|| String::from(UNKNOWN_DATE))
+    }
+
+    /// Where an entry is, if it's a log that's wanted
+    fn wanted_entry(&self, name: &str, visitor: &mut dyn LogVisitor) -> Option<PathBuf> {
+        let filename = name.rsplit('/').next().unwrap_or(name);
+        let entry_path = self.path.join(name);
+        if is_log_name(filename) && visitor.wants(&entry_path) {
+            Some(entry_path)
+        } else {
+            None
+        }
+    }
+
+    /// Reads a wanted entry from `entry` and hands it to the visitor, decompressing it if
+    /// it's gzipped, within the visitor's `FileLimits`. `archived` is how many bytes of the
+    /// archive the entry takes up. Returns whether it could be read, or was abandoned.
+    fn visit_entry(
+        &self,
+        name: &str,
+        entry_path: PathBuf,
+        entry: io::Result<impl Read>,
+        archived: u64,
+        visitor: &mut dyn LogVisitor,
+    ) -> bool {
+        let limits = visitor.file_limits();
+        let deadline = limits.deadline();
+        let mut data = vec![];
+        let read = entry.and_then(|entry| {
+            limits.read(entry, &mut data, deadline)?;
+            if name.ends_with(".gz") {
+                let mut decompressed = vec![];
+                limits.read(GzDecoder::new(data.as_slice()), &mut decompressed, deadline)?;
+                data = decompressed;
+            }
+            Ok(())
+        });
+        let readable = match read {
+            Ok(()) => {
+                visitor.count_read(archived as usize);
+                true
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                data.clear();
+                true
+            }
+            Err(e) if runner::is_abandoned(&e) => {
+                visitor.abandon(&entry_path, &e.to_string());
+                return true;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Error reading {}",
+                    BattleSearchError::from(e).at(&entry_path)
+                );
+                false
+            }
+        };
+        if readable {
+            visitor.visit(SourceLog {
+                date: self.entry_date(name),
+                path: entry_path,
+                data: LogData::Read(data),
+            });
+        }
+        readable
+    }
+}
+
+/// The logs in a zip archive, as some mirrors distribute them
+struct ZipSource {
+    archive: ArchiveFile,
+}
+
+impl LogSource for ZipSource {
+    fn walk(&mut self, visitor: &mut dyn LogVisitor) -> Result<(), BattleSearchError> {
+        let archive = &self.archive;
+        if !visitor.is_fresh(&|| archive.modified) {
+            return Ok(());
+        }
+        let zip = fs::File::open(&archive.path)
+            .and_then(|file| Ok(ZipArchive::new(BufReader::new(file))?));
+        let mut zip = match zip {
+            Ok(zip) => zip,
+            Err(e) => return visitor.skip(&archive.path, e.into()),
+        };
+        for idx in 0..zip.len() {
+            if visitor.should_stop() {
+                break;
+            }
+            let name = match zip.name_for_index(idx) {
+                Some(name) => String::from(name),
+                None => continue,
+            };
+            if let Some(entry_path) = archive.wanted_entry(&name, visitor) {
+                let entry = zip.by_index(idx).map_err(io::Error::from);
+                let archived = entry.as_ref().map_or(0, |entry| entry.compressed_size());
+                archive.visit_entry(&name, entry_path, entry, archived, visitor);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The logs in a 7z archive, which is read through from start to end, since entries in a
+/// solid block are compressed together
+#[cfg(feature = "sevenz")]
+struct SevenZipSource {
+    archive: ArchiveFile,
+}
+
+#[cfg(feature = "sevenz")]
+impl LogSource for SevenZipSource {
+    fn walk(&mut self, visitor: &mut dyn LogVisitor) -> Result<(), BattleSearchError> {
+        let archive = &self.archive;
+        if !visitor.is_fresh(&|| archive.modified) {
+            return Ok(());
+        }
+        let mut reader = match SevenZReader::open(&archive.path, Password::empty()) {
+            Ok(reader) => reader,
+            Err(e) => return visitor.skip(&archive.path, sevenz_error(e).into()),
+        };
+        let result = reader.for_each_entries(|entry, data| {
+            if visitor.should_stop() {
+                return Ok(false);
+            }
+            if !entry.is_directory() {
+                if let Some(entry_path) = archive.wanted_entry(entry.name(), visitor) {
+                    // entries in a solid block are compressed together, so there's no
+                    // compressed size to count for one
+                    let archived = entry.size();
+                    archive.visit_entry(
+                        entry.name(),
+                        entry_path,
+                        Ok(&mut *data),
+                        archived,
+                        visitor,
+                    );
+                }
+            }
+            // the next entry in the block starts where this one ends
+            io::copy(data, &mut io::sink())?;
+            Ok(true)
+        });
+        match result {
+            Err(e) => visitor.skip(&archive.path, sevenz_error(e).into()),
+            Ok(()) => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "sevenz")]
+fn sevenz_error(error: sevenz_rust::Error) -> io::Error {
+    match error {
+        sevenz_rust::Error::Io(e, _) => e,
+        e => io::Error::new(ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+/// The logs in a tar archive, which is read through from start to end
+struct TarSource {
+    archive: ArchiveFile,
+    gzipped: bool,
+}
+
+impl TarSource {
+    fn walk_entries<R: Read>(&self, reader: R, visitor: &mut dyn LogVisitor) -> io::Result<()> {
+        let mut tar = Archive::new(reader);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if visitor.should_stop() {
+                break;
+            }
+            // directories, links, and the like
+            if !matches!(
+                entry.header().entry_type(),
+                EntryType::Regular | EntryType::Continuous
+            ) {
+                continue;
+            }
+            // with the long names of GNU and pax headers
+            let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            if let Some(entry_path) = self.archive.wanted_entry(&name, visitor) {
+                let size = entry.size();
+                // the rest of the archive can't be found without reading this entry
+                if !self
+                    .archive
+                    .visit_entry(&name, entry_path, Ok(&mut entry), size, visitor)
+                {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A reader that notes when it reaches the end of what it reads
+struct EndTracker<R> {
+    reader: R,
+    ended: bool,
+}
+
+impl<R: Read> Read for EndTracker<R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buffer)?;
+        if read == 0 && !buffer.is_empty() {
+            self.ended = true;
+        }
+        Ok(read)
+    }
+}
+
+impl LogSource for TarSource {
+    fn walk(&mut self, visitor: &mut dyn LogVisitor) -> Result<(), BattleSearchError> {
+        let path = &self.archive.path;
+        if !visitor.is_fresh(&|| self.archive.modified) {
+            return Ok(());
+        }
+        let file = match fs::File::open(path) {
+            Ok(file) => BufReader::new(file),
+            Err(e) => return visitor.skip(path, e.into()),
+        };
+        let mut reader = EndTracker {
+            reader: if self.gzipped {
+                Box::new(GzDecoder::new(file)) as Box<dyn Read>
+            } else {
+                Box::new(file)
+            },
+            ended: false,
+        };
+        let result = self.walk_entries(&mut reader, visitor);
+        match result {
+            // a tar archive that's still being written, which ends partway through an entry
+            Err(e) if reader.ended || e.kind() == ErrorKind::UnexpectedEof => Ok(()),
+            Err(e) => visitor.skip(path, e.into()),
+            Ok(()) => Ok(()),
+        }
+    }
+}
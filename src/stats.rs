@@ -0,0 +1,190 @@
+use crate::search::{str_to_id, Match, Winner};
+/// Summaries of a user's games, grouped by opponent and by format
+use battle_tools::formats::room_number;
+use std::{collections::HashMap, sync::Arc};
+
+/// The label for groups collapsed by `--min-games`
+const OTHER: &str = "(other)";
+
+#[derive(Default, Clone)]
+struct Record {
+    games: usize,
+    wins: usize,
+    losses: usize,
+    ties: usize,
+}
+
+impl Record {
+    /// Counts `m`, which the user played on `side` of
+    fn add(&mut self, m: &Match, side: usize) {
+        self.games += 1;
+        match m.winner {
+            Winner::Player(_) if m.winning_side() == Some(side) => self.wins += 1,
+            Winner::Player(_) => self.losses += 1,
+            Winner::Nobody => self.ties += 1,
+            Winner::Unknown => (),
+        }
+    }
+
+    fn merge(&mut self, other: &Record) {
+        self.games += other.games;
+        self.wins += other.wins;
+        self.losses += other.losses;
+        self.ties += other.ties;
+    }
+}
+
+/// Games grouped by some key, remembering the first name seen for display
+#[derive(Default)]
+struct Groups {
+    records: HashMap<String, (String, Record)>,
+}
+
+impl Groups {
+    fn add(&mut self, key: &str, name: &str, m: &Match, side: usize) {
+        self.records
+            .entry(String::from(key))
+            .or_insert_with(|| (String::from(name), Record::default()))
+            .1
+            .add(m, side);
+    }
+
+    /// Groups sorted by most games, with those under `min_games` collapsed into one at the end
+    fn into_rows(self, min_games: usize) -> Vec<(String, Record)> {
+        let mut rows = vec![];
+        let mut other = Record::default();
+        for (_, (name, record)) in self.records {
+            if record.games < min_games {
+                other.merge(&record);
+            } else {
+                rows.push((name, record));
+            }
+        }
+        rows.sort_by(|(a_name, a), (b_name, b)| b.games.cmp(&a.games).then(a_name.cmp(b_name)));
+        if other.games > 0 {
+            rows.push((String::from(OTHER), other));
+        }
+        rows
+    }
+
+    /// How many groups there are, and the share of games in the largest one and largest
+    /// three, as percentages; a user who mostly plays one opponent may be win trading.
+    fn concentration(&self) -> (usize, f64, f64) {
+        let mut games: Vec<usize> = self
+            .records
+            .values()
+            .map(|(_, record)| record.games)
+            .collect();
+        games.sort_unstable_by(|a, b| b.cmp(a));
+        let total: usize = games.iter().sum();
+        let share = |top: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                games.iter().take(top).sum::<usize>() as f64 / total as f64 * 100.0
+            }
+        };
+        (games.len(), share(1), share(3))
+    }
+}
+
+fn print_table(title: &str, rows: &[(String, Record)]) {
+    println!("{}:", title);
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, record) in rows {
+        println!(
+            "    {:<width$}  {:>5} game{:<1}  {}-{}-{} (W-L-T)",
+            name,
+            record.games,
+            if record.games == 1 { "" } else { "s" },
+            record.wins,
+            record.losses,
+            record.ties,
+            width = width
+        );
+    }
+}
+
+/// The opponent's id and name in a game the user played on `side` of; in multi battles, both
+/// opponents together, like "Annika & Mia"
+pub fn opponent(m: &Match, side: usize) -> (String, String) {
+    let [p1_side, p2_side] = m.side_names();
+    let ids = |player: &str, partner: &Option<Arc<str>>| match partner {
+        Some(partner) => format!("{} & {}", player, partner),
+        None => String::from(player),
+    };
+    if side == 0 {
+        (ids(&m.p2, &m.p4), p2_side)
+    } else {
+        (ids(&m.p1, &m.p3), p1_side)
+    }
+}
+
+/// Where a match falls chronologically: by date, then by room number within a date
+fn chronological_key(m: &Match) -> (&str, Option<u64>) {
+    (&m.date, room_number(&m.room))
+}
+
+/// Prints the searched user's first and last games and how varied their opponents were, then
+/// their records against each opponent and in each format.
+/// Opponents and formats with fewer than `min_games` games are grouped together as "(other)".
+pub fn print_stats<'a>(username: &str, matches: impl Iterator<Item = &'a Match>, min_games: usize) {
+    let user_id = str_to_id(username);
+    let mut opponents = Groups::default();
+    let mut formats = Groups::default();
+    let mut first: Option<&Match> = None;
+    let mut last: Option<&Match> = None;
+    for m in matches.filter(|m| !m.is_spectator) {
+        if first.is_none_or(|first| chronological_key(m) < chronological_key(first)) {
+            first = Some(m);
+        }
+        if last.is_none_or(|last| chronological_key(m) > chronological_key(last)) {
+            last = Some(m);
+        }
+        let side = m.user_side(&user_id);
+        let (opponent_id, opponent_name) = opponent(m, side);
+        opponents.add(&opponent_id, &opponent_name, m, side);
+        let format = m.format.as_deref().unwrap_or("unknown format");
+        formats.add(format, format, m, side);
+    }
+
+    if let (Some(first), Some(last)) = (first, last) {
+        println!("First game: {} ({})", first.date, first.room);
+        println!("Last game:  {} ({})", last.date, last.room);
+        println!();
+    }
+    let (distinct, top_share, top_three_share) = opponents.concentration();
+    if distinct > 0 {
+        println!("Distinct opponents: {}", distinct);
+        println!(
+            "Share of games against the top opponent: {:.0}%; against the top 3: {:.0}%",
+            top_share, top_three_share
+        );
+        println!();
+    }
+    print_table("Games by opponent", &opponents.into_rows(min_games));
+    println!();
+    print_table("Games by format", &formats.into_rows(min_games));
+}
+
+/// Prints the searched user's `top` most played opponents and their records against them
+pub fn print_top_opponents<'a>(
+    username: &str,
+    matches: impl Iterator<Item = &'a Match>,
+    top: usize,
+) {
+    let user_id = str_to_id(username);
+    let mut opponents = Groups::default();
+    for m in matches.filter(|m| !m.is_spectator) {
+        let side = m.user_side(&user_id);
+        let (opponent_id, opponent_name) = opponent(m, side);
+        opponents.add(&opponent_id, &opponent_name, m, side);
+    }
+    let distinct = opponents.records.len();
+    let mut rows = opponents.into_rows(1);
+    rows.truncate(top);
+    print_table(
+        &format!("Top {} of {} opponents", rows.len(), distinct),
+        &rows,
+    );
+}
@@ -0,0 +1,122 @@
+/// The searched user's longest runs of consecutive wins and losses, as claimed for ladder
+/// achievements
+use crate::search::{str_to_id, Match, Winner};
+
+/// A run of consecutive games with the same result, by their indices in the sorted matches
+#[derive(Clone, Copy)]
+struct Streak {
+    first: usize,
+    last: usize,
+}
+
+impl Streak {
+    fn len(&self) -> usize {
+        self.last - self.first + 1
+    }
+}
+
+/// Keeps the longest streak of a result, preferring the earliest of equally long ones
+#[derive(Default)]
+struct Longest {
+    current: Option<Streak>,
+    longest: Option<Streak>,
+}
+
+impl Longest {
+    fn extend(&mut self, idx: usize) {
+        let streak = match self.current {
+            Some(streak) => Streak {
+                last: idx,
+                ..streak
+            },
+            None => Streak {
+                first: idx,
+                last: idx,
+            },
+        };
+        self.current = Some(streak);
+        if self
+            .longest
+            .is_none_or(|longest| streak.len() > longest.len())
+        {
+            self.longest = Some(streak);
+        }
+    }
+
+    fn end(&mut self) {
+        self.current = None;
+    }
+}
+
+fn print_streak(kind: &str, plural: &str, streak: Option<Streak>, matches: &[&Match]) {
+    let streak = match streak {
+        Some(streak) => streak,
+        None => return println!("No {}.", plural),
+    };
+    let (first, last) = (matches[streak.first], matches[streak.last]);
+    if streak.len() == 1 {
+        println!(
+            "Longest {} streak: 1 game, {} ({})",
+            kind, first.room, first.date
+        );
+    } else {
+        println!(
+            "Longest {} streak: {} games, from {} ({}) to {} ({})",
+            kind,
+            streak.len(),
+            first.room,
+            first.date,
+            last.room,
+            last.date
+        );
+    }
+}
+
+/// Prints the searched user's longest win and loss streaks among `sorted`, which should be in
+/// chronological order. Ties end both kinds of streak; games whose result is unknown are left
+/// out without ending either.
+pub fn print_streaks(username: &str, sorted: &[Match]) {
+    let user_id = str_to_id(username);
+    let mut unknown = 0;
+    let played: Vec<&Match> = sorted
+        .iter()
+        .filter(|m| !m.is_spectator)
+        .filter(|m| {
+            let known = !matches!(m.winner, Winner::Unknown);
+            if !known {
+                unknown += 1;
+            }
+            known
+        })
+        .collect();
+
+    let mut wins = Longest::default();
+    let mut losses = Longest::default();
+    for (idx, m) in played.iter().enumerate() {
+        match m.winner {
+            Winner::Player(ref winner) if **winner == *user_id => {
+                wins.extend(idx);
+                losses.end();
+            }
+            Winner::Player(_) => {
+                losses.extend(idx);
+                wins.end();
+            }
+            _ => {
+                wins.end();
+                losses.end();
+            }
+        }
+    }
+
+    print_streak("win", "wins", wins.longest, &played);
+    print_streak("loss", "losses", losses.longest, &played);
+    if unknown > 0 {
+        println!(
+            "{} game{} had no recorded winner and {} left out.",
+            unknown,
+            if unknown == 1 { "" } else { "s" },
+            if unknown == 1 { "was" } else { "were" }
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::runner::{SearchJob, SearchSummary};
+use crate::search::{BattleSearchError, BattleSearcher, Match};
+/// Searching as a `Stream` of matches, for async programs like web services: the search runs
+/// on its own threads, which wait while matches are waiting to be taken, so a slow consumer
+/// holds up the search instead of the matches piling up in memory
+use battle_tools::layout::ArchiveRoot;
+use futures_core::Stream;
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+/// How many matches can wait to be taken from a stream before the search waits
+const STREAM_CAPACITY: usize = 64;
+
+/// What a search stream yields: each match as it's found, then what happened during the
+/// search, unless an error ended it first
+#[derive(Debug)]
+pub enum SearchEvent {
+    Match(Box<Match>),
+    /// The last item, once the search has finished
+    Finished(Box<SearchSummary>),
+}
+
+/// What the searching thread and the stream share
+#[derive(Default)]
+struct Channel {
+    state: Mutex<ChannelState>,
+    /// Signalled when a match is taken, or the stream is dropped
+    taken: Condvar,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    waiting: VecDeque<Result<SearchEvent, BattleSearchError>>,
+    /// Whether the search has finished, so nothing more will be sent
+    finished: bool,
+    /// Whether the stream has been dropped, so nothing more will be taken
+    dropped: bool,
+    /// The task to wake when something is sent
+    waker: Option<Waker>,
+}
+
+impl Channel {
+    /// Sends an item to the stream, first waiting for there to be room if `wait`
+    fn send(&self, item: Result<SearchEvent, BattleSearchError>, wait: bool) {
+        let mut state = self.state.lock().unwrap();
+        while wait && state.waiting.len() >= STREAM_CAPACITY && !state.dropped {
+            state = self.taken.wait(state).unwrap();
+        }
+        if state.dropped {
+            return;
+        }
+        state.waiting.push_back(item);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.finished = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The matches of a search, as they are found, and its summary. Dropping it stops the search.
+struct MatchStream {
+    channel: Arc<Channel>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl MatchStream {
+    /// Runs `job` on another thread, checking logs with `searcher`
+    fn start(mut job: SearchJob, searcher: Arc<BattleSearcher>) -> Self {
+        let channel = Arc::new(Channel::default());
+        let cancel = Arc::new(AtomicBool::new(false));
+        job.cancel = Some(cancel.clone());
+        let sender = channel.clone();
+        thread::spawn(move || {
+            let result = job.run_with(searcher, |m| {
+                sender.send(Ok(SearchEvent::Match(Box::new(m))), true)
+            });
+            // the last item, so it doesn't need to wait its turn
+            sender.send(
+                result.map(|summary| SearchEvent::Finished(Box::new(summary))),
+                false,
+            );
+            sender.finish();
+        });
+        Self { channel, cancel }
+    }
+}
+
+impl Stream for MatchStream {
+    type Item = Result<SearchEvent, BattleSearchError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.channel.state.lock().unwrap();
+        match state.waiting.pop_front() {
+            Some(item) => {
+                self.channel.taken.notify_one();
+                Poll::Ready(Some(item))
+            }
+            None if state.finished => Poll::Ready(None),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for MatchStream {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.channel.state.lock().unwrap().dropped = true;
+        self.channel.taken.notify_all();
+    }
+}
+
+impl BattleSearcher {
+    /// Searches `directories` for logs this searcher matches, yielding each match as it's
+    /// found and then the search's summary, or the error that ended the search. Logs are read
+    /// as a `SearchJob` with its default options reads them.
+    pub fn search_stream(
+        self,
+        directories: Vec<ArchiveRoot>,
+    ) -> impl Stream<Item = Result<SearchEvent, BattleSearchError>> + Unpin {
+        MatchStream::start(SearchJob::new_multi(vec![], directories), Arc::new(self))
+    }
+}
+
+impl SearchJob {
+    /// Runs the search as `run` does, yielding each match as it's found instead and then the
+    /// summary `run` returns, or the error that ended the search
+    pub fn search_stream(
+        mut self,
+    ) -> impl Stream<Item = Result<SearchEvent, BattleSearchError>> + Unpin {
+        let searcher = Arc::new(self.build_searcher());
+        MatchStream::start(self, searcher)
+    }
+}
@@ -0,0 +1,99 @@
+use crate::search::{str_to_id, Match};
+/// Grouping a user's teams by similarity, to see which distinct teams they used and when
+use battle_tools::formats::room_number;
+use std::collections::BTreeSet;
+
+/// How similar two teams' sets of species must be (shared species over all species) to count
+/// as the same team, so that swapping out one Pokémon of six doesn't make a new team
+const SIMILARITY_THRESHOLD: f64 = 0.7;
+
+/// Games with teams similar enough to be one team
+struct TeamCluster<'a> {
+    format: &'a str,
+    /// The first team in the cluster, which later teams are compared to
+    species: BTreeSet<&'a str>,
+    /// In chronological order
+    games: Vec<&'a Match>,
+}
+
+fn similarity(a: &BTreeSet<&str>, b: &BTreeSet<&str>) -> f64 {
+    let shared = a.intersection(b).count();
+    let total = a.union(b).count();
+    if total == 0 {
+        return 0.0;
+    }
+    shared as f64 / total as f64
+}
+
+/// Prints the distinct teams the searched user used, by format, with how many games each was
+/// used in and the first and last of those games. Games without a recorded team are left out.
+pub fn print_team_clusters<'a>(username: &str, matches: impl Iterator<Item = &'a Match>) {
+    let user_id = str_to_id(username);
+    let mut games: Vec<(&Match, &Vec<String>)> = matches
+        .filter_map(|m| {
+            let team = if *m.p1 == *user_id {
+                &m.p1_team
+            } else if *m.p2 == *user_id {
+                &m.p2_team
+            } else {
+                return None;
+            };
+            if team.is_empty() {
+                None
+            } else {
+                Some((m, team))
+            }
+        })
+        .collect();
+    games.sort_by(|(a, _), (b, _)| {
+        a.date
+            .cmp(&b.date)
+            .then_with(|| room_number(&a.room).cmp(&room_number(&b.room)))
+    });
+
+    let mut clusters: Vec<TeamCluster> = vec![];
+    for (m, team) in games {
+        let format = m.format.as_deref().unwrap_or("unknown format");
+        let species: BTreeSet<&str> = team.iter().map(String::as_str).collect();
+        let existing = clusters.iter_mut().find(|cluster| {
+            cluster.format == format
+                && similarity(&cluster.species, &species) >= SIMILARITY_THRESHOLD
+        });
+        match existing {
+            Some(cluster) => cluster.games.push(m),
+            None => clusters.push(TeamCluster {
+                format,
+                species,
+                games: vec![m],
+            }),
+        }
+    }
+
+    if clusters.is_empty() {
+        println!("No teams were recorded in the matching games.");
+        return;
+    }
+    clusters.sort_by(|a, b| a.format.cmp(b.format));
+    let mut previous_format = None;
+    for cluster in &clusters {
+        if previous_format != Some(cluster.format) {
+            if previous_format.is_some() {
+                println!();
+            }
+            println!("{}:", cluster.format);
+            previous_format = Some(cluster.format);
+        }
+        let species: Vec<&str> = cluster.species.iter().copied().collect();
+        let (first, last) = (cluster.games[0], cluster.games[cluster.games.len() - 1]);
+        println!(
+            "    {}: {} game{}, first {} ({}), last {} ({})",
+            species.join(" / "),
+            cluster.games.len(),
+            if cluster.games.len() == 1 { "" } else { "s" },
+            first.date,
+            first.room,
+            last.date,
+            last.room
+        );
+    }
+}
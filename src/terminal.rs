@@ -0,0 +1,40 @@
+/// Finding out about the terminal output is written to, so lines can be laid out to fit it
+use std::env;
+
+/// The width of the terminal standard output goes to, in columns, or `None` if it isn't a
+/// terminal. `COLUMNS` is used if the terminal doesn't say.
+pub fn stdout_width() -> Option<usize> {
+    if !is_terminal() {
+        return None;
+    }
+    window_width().or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+}
+
+#[cfg(unix)]
+fn is_terminal() -> bool {
+    // SAFETY: isatty only inspects the file descriptor
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+#[cfg(unix)]
+fn window_width() -> Option<usize> {
+    // SAFETY: TIOCGWINSZ only writes a winsize to the pointer it's given
+    let size = unsafe {
+        let mut size: libc::winsize = std::mem::zeroed();
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 {
+            return None;
+        }
+        size
+    };
+    Some(size.ws_col as usize).filter(|&width| width > 0)
+}
+
+#[cfg(not(unix))]
+fn is_terminal() -> bool {
+    false
+}
+
+#[cfg(not(unix))]
+fn window_width() -> Option<usize> {
+    None
+}
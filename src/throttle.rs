@@ -0,0 +1,52 @@
+/// Token-bucket throttling of disk reads, shared across worker threads
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+pub struct Throttle {
+    bytes_per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Bytes that may be read right now; negative if the bucket is in debt
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Throttle {
+    pub fn new(megabytes_per_second: f64) -> Self {
+        let bytes_per_second = megabytes_per_second * 1_000_000.0;
+        Self {
+            bytes_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_second,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Accounts for `bytes` having been read, sleeping until the bucket is out of debt.
+    ///
+    /// At most one second's worth of bandwidth can be saved up, so bursts stay short.
+    pub fn consume(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill =
+                now.duration_since(bucket.last_refill).as_secs_f64() * self.bytes_per_second;
+            bucket.tokens = (bucket.tokens + refill).min(self.bytes_per_second) - bytes as f64;
+            bucket.last_refill = now;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / self.bytes_per_second)
+            } else {
+                Duration::from_secs(0)
+            }
+        };
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::output;
+use crate::search::{Match, Winner};
+/// The history of the searched user's games against one opponent, laid out for pasting into a
+/// staff report
+use std::sync::Arc;
+
+/// Whether `m` has `a` and `b` on opposite sides, counting partners in multi battles
+fn is_between(m: &Match, a: &str, b: &str) -> bool {
+    let on_side = |player: &Arc<str>, partner: &Option<Arc<str>>, id: &str| {
+        **player == *id || partner.as_deref() == Some(id)
+    };
+    let (p1_side, p2_side) = (
+        |id: &str| on_side(&m.p1, &m.p3, id),
+        |id: &str| on_side(&m.p2, &m.p4, id),
+    );
+    (p1_side(a) && p2_side(b)) || (p2_side(a) && p1_side(b))
+}
+
+fn plural(count: usize, word: &str) -> String {
+    format!("{} {}{}", count, word, if count == 1 { "" } else { "s" })
+}
+
+/// Prints each game between the searched user and `opponent_id` in `sorted`, which should be
+/// in chronological order, with how long it came after the last, then a tally of the results
+pub fn print_timeline(user_id: &str, opponent_id: &str, sorted: &[Match]) {
+    let games: Vec<&Match> = sorted
+        .iter()
+        .filter(|m| !m.is_spectator && is_between(m, user_id, opponent_id))
+        .collect();
+    let (first, last) = match (games.first(), games.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return println!("No games between {} and {}.", user_id, opponent_id),
+    };
+    // names as the players last wrote them
+    let name = |id: &str| {
+        let players = [
+            (Some(&last.p1), Some(&last.p1_name)),
+            (Some(&last.p2), Some(&last.p2_name)),
+            (last.p3.as_ref(), last.p3_name.as_ref()),
+            (last.p4.as_ref(), last.p4_name.as_ref()),
+        ];
+        players
+            .iter()
+            .find(|(player, _)| player.is_some_and(|player| **player == *id))
+            .and_then(|(_, name)| *name)
+            .map_or_else(|| String::from(id), |name| String::from(&**name))
+    };
+    let (user_name, opponent_name) = (name(user_id), name(opponent_id));
+
+    println!(
+        "{} between {} and {}, from {} to {}:",
+        plural(games.len(), "game"),
+        user_name,
+        opponent_name,
+        first.date,
+        last.date
+    );
+    let (mut wins, mut losses, mut ties, mut unknown) = (0, 0, 0, 0);
+    for (idx, m) in games.iter().enumerate() {
+        let gap = match idx
+            .checked_sub(1)
+            .map(|prev| output::gap_days(games[prev], m))
+        {
+            None => String::new(),
+            Some(Some(0)) => String::from("same day"),
+            Some(Some(1)) => String::from("1 day later"),
+            Some(Some(days)) => format!("{} days later", days),
+            Some(None) => String::from("?"),
+        };
+        match m.winner {
+            Winner::Player(_) if m.winning_side() == Some(m.user_side(user_id)) => wins += 1,
+            Winner::Player(_) => losses += 1,
+            Winner::Nobody => ties += 1,
+            Winner::Unknown => unknown += 1,
+        }
+        println!(
+            "{:>4}. {:<10} {:<12} <<{}>> {}",
+            idx + 1,
+            m.date,
+            gap,
+            m.room,
+            m.outcome()
+        );
+    }
+
+    println!();
+    let mut tally = vec![
+        format!("{} won {}", user_name, wins),
+        format!("{} won {}", opponent_name, losses),
+    ];
+    if ties > 0 {
+        tally.push(format!("{} with no winner", ties));
+    }
+    if unknown > 0 {
+        tally.push(format!("{} with an unknown result", unknown));
+    }
+    println!("{}.", tally.join(", "));
+}
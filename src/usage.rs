@@ -0,0 +1,109 @@
+/// How often each Pokémon appeared on a user's teams, per format
+use crate::search::{str_to_id, BattleSearchError, Match, WithPath};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+const UNKNOWN_FORMAT: &str = "unknown format";
+
+#[derive(Default, Serialize)]
+pub struct FormatUsage {
+    /// How many of the user's teams in this format were recorded
+    pub teams: usize,
+    /// Pokémon by how many of those teams they were on, most used first
+    pub pokemon: Vec<PokemonUsage>,
+}
+
+#[derive(Serialize)]
+pub struct PokemonUsage {
+    pub species: String,
+    pub teams: usize,
+}
+
+/// Tallies the species on the searched user's teams, by format. Games without a recorded team
+/// for the user (including those they only spectated) are left out.
+pub fn count_usage<'a>(
+    username: &str,
+    matches: impl Iterator<Item = &'a Match>,
+) -> BTreeMap<String, FormatUsage> {
+    let user_id = str_to_id(username);
+    let mut counts: BTreeMap<String, (usize, HashMap<&str, usize>)> = BTreeMap::new();
+    for m in matches {
+        let team = if *m.p1 == *user_id {
+            &m.p1_team
+        } else if *m.p2 == *user_id {
+            &m.p2_team
+        } else {
+            continue;
+        };
+        if team.is_empty() {
+            continue;
+        }
+        let format = m.format.as_deref().unwrap_or(UNKNOWN_FORMAT);
+        let (teams, pokemon) = counts.entry(String::from(format)).or_default();
+        *teams += 1;
+        for species in team {
+            *pokemon.entry(species).or_insert(0) += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .map(|(format, (teams, pokemon))| {
+            let mut pokemon: Vec<PokemonUsage> = pokemon
+                .into_iter()
+                .map(|(species, teams)| PokemonUsage {
+                    species: String::from(species),
+                    teams,
+                })
+                .collect();
+            pokemon.sort_by(|a, b| b.teams.cmp(&a.teams).then(a.species.cmp(&b.species)));
+            (format, FormatUsage { teams, pokemon })
+        })
+        .collect()
+}
+
+/// Prints a table of each format's usage, with the share of teams each Pokémon was on
+pub fn print_usage(usage: &BTreeMap<String, FormatUsage>) {
+    if usage.is_empty() {
+        println!("No teams were recorded in the matching games.");
+        return;
+    }
+    for (idx, (format, format_usage)) in usage.iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        println!(
+            "{} ({} team{}):",
+            format,
+            format_usage.teams,
+            if format_usage.teams == 1 { "" } else { "s" }
+        );
+        let width = format_usage
+            .pokemon
+            .iter()
+            .map(|usage| usage.species.chars().count())
+            .max()
+            .unwrap_or(0);
+        for usage in &format_usage.pokemon {
+            println!(
+                "    {:<width$}  {:>5}  {:>5.1}%",
+                usage.species,
+                usage.teams,
+                100.0 * usage.teams as f64 / format_usage.teams as f64,
+                width = width
+            );
+        }
+    }
+}
+
+pub fn write_usage_json(
+    usage: &BTreeMap<String, FormatUsage>,
+    path: &Path,
+) -> Result<(), BattleSearchError> {
+    fs::write(path, serde_json::to_string_pretty(usage).unwrap()).with_path(path)?;
+    Ok(())
+}
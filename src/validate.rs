@@ -0,0 +1,120 @@
+/// Checking logs against the layout Showdown writes them in, so damaged or hand-edited logs
+/// can be found across an archive whether or not they match a search
+use serde_json::from_slice;
+
+/// The types a JSON value can have, apart from null
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    String,
+    Bool,
+    Number,
+    Array,
+    Object,
+}
+
+impl Kind {
+    /// The type of a raw JSON value, or `None` for null or something that isn't JSON
+    fn of(raw: &[u8]) -> Option<Self> {
+        match raw.iter().find(|byte| !byte.is_ascii_whitespace())? {
+            b'"' => Some(Kind::String),
+            b't' | b'f' => Some(Kind::Bool),
+            b'-' | b'0'..=b'9' => Some(Kind::Number),
+            b'[' => Some(Kind::Array),
+            b'{' => Some(Kind::Object),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::String => "a string",
+            Kind::Bool => "a boolean",
+            Kind::Number => "a number",
+            Kind::Array => "an array",
+            Kind::Object => "an object",
+        }
+    }
+}
+
+struct Field {
+    name: &'static str,
+    /// Whether every log has the field; many others are missing from older logs
+    required: bool,
+    kinds: &'static [Kind],
+}
+
+const fn field(name: &'static str, required: bool, kinds: &'static [Kind]) -> Field {
+    Field {
+        name,
+        required,
+        kinds,
+    }
+}
+
+/// What's expected of each field, in the order of `search::LOG_FIELDS`
+const SCHEMA: [Field; 17] = [
+    field("p1", true, &[Kind::String]),
+    field("p2", true, &[Kind::String]),
+    field("winner", false, &[Kind::String]),
+    field("endType", false, &[Kind::String]),
+    field("ladderError", false, &[Kind::Bool]),
+    field("inputLog", false, &[Kind::Array]),
+    field("log", true, &[Kind::Array]),
+    field("format", false, &[Kind::String]),
+    // a message like "Rated battle" on some servers
+    field("rated", false, &[Kind::Bool, Kind::Number, Kind::String]),
+    field("turns", false, &[Kind::Number]),
+    field("timestamp", false, &[Kind::String]),
+    field("score", false, &[Kind::Array]),
+    field("seed", false, &[Kind::Array]),
+    field("p1team", false, &[Kind::Array]),
+    field("p2team", false, &[Kind::Array]),
+    field("p3", false, &[Kind::String]),
+    field("p4", false, &[Kind::String]),
+];
+
+/// The `endType`s the server writes
+const END_TYPES: [&str; 3] = ["normal", "forfeit", "forced"];
+
+/// Ways a log's fields (as found for `search::LOG_FIELDS`) differ from what Showdown writes:
+/// missing fields, fields of the wrong type, and unknown end types
+pub fn schema_problems(json: &[Option<&[u8]>]) -> Vec<String> {
+    let mut problems = vec![];
+    for (field, raw) in SCHEMA.iter().zip(json) {
+        let kind = match raw.map(Kind::of) {
+            Some(Some(kind)) => kind,
+            // null
+            Some(None) | None => {
+                if field.required {
+                    problems.push(format!("no {}", field.name));
+                }
+                continue;
+            }
+        };
+        if !field.kinds.contains(&kind) {
+            let expected: Vec<&str> = field.kinds.iter().map(|kind| kind.name()).collect();
+            problems.push(format!(
+                "{} is {} instead of {}",
+                field.name,
+                kind.name(),
+                expected.join(" or ")
+            ));
+        }
+    }
+
+    for (idx, name) in [(0, "p1"), (1, "p2")] {
+        if let Some(Some(raw)) = json.get(idx) {
+            if from_slice::<String>(raw).is_ok_and(|player| player.trim().is_empty()) {
+                problems.push(format!("{} is empty", name));
+            }
+        }
+    }
+    if let Some(Some(raw)) = json.get(3) {
+        if let Ok(end_type) = from_slice::<String>(raw) {
+            if !END_TYPES.contains(&end_type.as_str()) {
+                problems.push(format!("unknown endType \"{}\"", end_type));
+            }
+        }
+    }
+    problems
+}